@@ -20,11 +20,17 @@ cargo test \
 cargo check \
     --manifest-path source/postcard-rpc/Cargo.toml \
     --no-default-features \
-    --features=use-std,cobs-serial,raw-nusb
+    --features=use-std,cobs-serial,raw-nusb,tcp,udp
 cargo test \
     --manifest-path source/postcard-rpc/Cargo.toml \
     --no-default-features \
-    --features=use-std,cobs-serial,raw-nusb
+    --features=use-std,cobs-serial,raw-nusb,tcp,udp
+
+# Host + device explorer binary
+cargo check \
+    --manifest-path source/postcard-rpc/Cargo.toml \
+    --no-default-features \
+    --features=cli
 
 # Host + wasm host-client impls
 RUSTFLAGS="--cfg=web_sys_unstable_apis" \
@@ -51,6 +57,25 @@ cargo check \
     --no-default-features \
     --features=embassy-usb-0_4-server \
     --target thumbv7em-none-eabihf
+cargo check \
+    --manifest-path source/postcard-rpc/Cargo.toml \
+    --no-default-features \
+    --features=embassy-usb-0_4-cdc-acm-server \
+    --target thumbv7em-none-eabihf
+cargo check \
+    --manifest-path source/postcard-rpc/Cargo.toml \
+    --no-default-features \
+    --features=embassy-net-0_6-udp-server \
+    --target thumbv7em-none-eabihf
+cargo check \
+    --manifest-path source/postcard-rpc/Cargo.toml \
+    --no-default-features \
+    --features=embedded-io-cobs-server \
+    --target thumbv7em-none-eabihf
+cargo test \
+    --manifest-path source/postcard-rpc/Cargo.toml \
+    --no-default-features \
+    --features=use-std,embedded-io-cobs-server
 
 # Example projects
 cargo build \
@@ -1 +1,100 @@
-// I'm just here so we can write integration tests
+//! Helpers shared by the integration tests
+
+use std::future::pending;
+
+use postcard_rpc::{
+    header::VarSeqKind,
+    host_client::{test_channels as client, HostClient},
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{new_server, Settings, WireRxBuf, WireRxImpl, WireTxImpl},
+            ChannelWireRx, ChannelWireTx,
+        },
+        Dispatch, Sender, Server,
+    },
+    standard_icd::WireError,
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// The server type run by [`spawn_server_on_channels`]
+pub type ChannelServer<D> = Server<WireTxImpl, WireRxImpl, WireRxBuf, D>;
+
+/// A server started by [`spawn_server_on_channels`]
+///
+/// Dropping the handle leaves the server running.
+pub struct ServerHandle {
+    stop: oneshot::Sender<()>,
+}
+
+impl ServerHandle {
+    /// Stop the server, closing its channels
+    pub fn abort(self) {
+        let _ = self.stop.send(());
+    }
+}
+
+/// Run a server for `app` with a receive buffer of `buf` bytes, talking over
+/// the given channels
+///
+/// `configure` is called before the server runs, e.g. to set a topic filter.
+/// The server runs on its own thread, as the future of a generic server can't
+/// be spawned on the runtime of the test.
+pub fn spawn_server_on_channels<D>(
+    app: D,
+    buf: usize,
+    server_tx: mpsc::Sender<Vec<u8>>,
+    server_rx: mpsc::Receiver<Vec<u8>>,
+    configure: impl FnOnce(&mut ChannelServer<D>),
+) -> (Sender<WireTxImpl>, ServerHandle)
+where
+    D: Dispatch<Tx = WireTxImpl> + Send + 'static,
+{
+    let kkind = app.min_key_len();
+    let mut server = new_server(
+        app,
+        Settings {
+            tx: ChannelWireTx::new(server_tx),
+            rx: ChannelWireRx::new(server_rx),
+            buf,
+            kkind,
+        },
+    );
+    configure(&mut server);
+    let sender = server.sender();
+
+    let (stop, stopped) = oneshot::channel();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let stopped = async {
+                // Only stop when asked to, not when the handle is dropped
+                if stopped.await.is_err() {
+                    pending::<()>().await;
+                }
+            };
+            tokio::select! {
+                _ = server.run() => {}
+                _ = stopped => {}
+            }
+        });
+    });
+    (sender, ServerHandle { stop })
+}
+
+/// Run a server for `app` with a receive buffer of `buf` bytes, and connect a
+/// new client to it
+pub fn spawn_channel_server<D>(app: D, buf: usize) -> (Sender<WireTxImpl>, HostClient<WireError>)
+where
+    D: Dispatch<Tx = WireTxImpl> + Send + 'static,
+{
+    let (client_tx, server_rx) = mpsc::channel(16);
+    let (server_tx, client_rx) = mpsc::channel(16);
+    let (sender, _handle) = spawn_server_on_channels(app, buf, server_tx, server_rx, |_| {});
+    (
+        sender,
+        client::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1),
+    )
+}
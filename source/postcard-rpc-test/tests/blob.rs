@@ -0,0 +1,277 @@
+use core::num::NonZeroUsize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    host_client::{HostClient, HostErr},
+    server::{
+        blob::{blob_close, blob_open, blob_read, blob_write, BlobContext, BlobStorage},
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        SpawnContext,
+    },
+    standard_icd::{
+        BlobCloseEndpoint, BlobCrc, BlobError, BlobInfo, BlobOpenEndpoint, BlobReadEndpoint,
+        BlobSummary, BlobWriteEndpoint, WireError, STANDARD_ICD_BLOB_ENDPOINTS,
+    },
+    topics,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    include = [STANDARD_ICD_BLOB_ENDPOINTS];
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+/// A blob that is stored (or being stored) in RAM
+#[derive(Default)]
+pub struct StoredBlob {
+    data: Vec<u8>,
+    expected: Option<(u32, u32)>,
+}
+
+#[derive(Default)]
+pub struct Flash {
+    blobs: HashMap<String, StoredBlob>,
+    // handle -> name
+    open: HashMap<u32, String>,
+    next_handle: u32,
+    bytes_written: usize,
+    fail_after: Option<usize>,
+    corrupt: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct RamStorage {
+    flash: Arc<Mutex<Flash>>,
+    read_buf: Vec<u8>,
+}
+
+impl BlobStorage for RamStorage {
+    async fn open_read(&mut self, name: &str) -> Result<BlobInfo, BlobError> {
+        let mut flash = self.flash.lock().unwrap();
+        let len = flash.blobs.get(name).ok_or(BlobError::NotFound)?.data.len() as u32;
+        let handle = flash.next_handle;
+        flash.next_handle += 1;
+        flash.open.insert(handle, name.to_string());
+        Ok(BlobInfo {
+            handle,
+            len,
+            offset: 0,
+        })
+    }
+
+    async fn open_write(&mut self, name: &str, len: u32, crc: u32) -> Result<BlobInfo, BlobError> {
+        let mut flash = self.flash.lock().unwrap();
+        let blob = flash.blobs.entry(name.to_string()).or_default();
+        // Resume if this is the same blob as before
+        if blob.expected != Some((len, crc)) {
+            blob.data.clear();
+            blob.expected = Some((len, crc));
+        }
+        let offset = blob.data.len() as u32;
+        let handle = flash.next_handle;
+        flash.next_handle += 1;
+        flash.open.insert(handle, name.to_string());
+        Ok(BlobInfo {
+            handle,
+            len,
+            offset,
+        })
+    }
+
+    async fn write(&mut self, handle: u32, offset: u32, data: &[u8]) -> Result<(), BlobError> {
+        let mut flash = self.flash.lock().unwrap();
+        if flash.fail_after.is_some_and(|n| flash.bytes_written >= n) {
+            return Err(BlobError::Storage);
+        }
+        flash.bytes_written += data.len();
+        let corrupt = flash.corrupt;
+        let name = flash
+            .open
+            .get(&handle)
+            .ok_or(BlobError::InvalidHandle)?
+            .clone();
+        let blob = flash.blobs.get_mut(&name).unwrap();
+        if offset as usize != blob.data.len() {
+            return Err(BlobError::BadOffset);
+        }
+        blob.data.extend_from_slice(data);
+        if corrupt {
+            blob.data[0] ^= 0xFF;
+        }
+        Ok(())
+    }
+
+    async fn read(&mut self, handle: u32, offset: u32, len: u32) -> Result<&[u8], BlobError> {
+        let flash = self.flash.lock().unwrap();
+        let name = flash.open.get(&handle).ok_or(BlobError::InvalidHandle)?;
+        let data = &flash.blobs[name].data;
+        let start = (offset as usize).min(data.len());
+        let end = (start + len as usize).min(data.len());
+        self.read_buf.clear();
+        self.read_buf.extend_from_slice(&data[start..end]);
+        Ok(&self.read_buf)
+    }
+
+    async fn close(&mut self, handle: u32) -> Result<BlobSummary, BlobError> {
+        let mut flash = self.flash.lock().unwrap();
+        let name = flash.open.remove(&handle).ok_or(BlobError::InvalidHandle)?;
+        let blob = flash.blobs.get_mut(&name).unwrap();
+        let summary = BlobSummary {
+            len: blob.data.len() as u32,
+            crc: BlobCrc::checksum(&blob.data),
+        };
+        if blob.expected == Some((summary.len, summary.crc)) {
+            // Complete, nothing to resume anymore
+            blob.expected = None;
+        }
+        Ok(summary)
+    }
+}
+
+pub struct TestContext {
+    storage: RamStorage,
+}
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+impl BlobContext for TestContext {
+    type Storage = RamStorage;
+
+    fn blob_storage(&mut self) -> &mut Self::Storage {
+        &mut self.storage
+    }
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler           |
+        | ----------        | ----      | -------           |
+        | BlobOpenEndpoint  | async     | blob_open         |
+        | BlobWriteEndpoint | async     | blob_write        |
+        | BlobReadEndpoint  | async     | blob_read         |
+        | BlobCloseEndpoint | async     | blob_close        |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn setup() -> (Arc<Mutex<Flash>>, HostClient<WireError>) {
+    let storage = RamStorage::default();
+    let flash = storage.flash.clone();
+    let app = SingleDispatcher::new(TestContext { storage }, ChannelWireSpawn {});
+    let (_sender, cli) = spawn_channel_server(app, 128);
+    (flash, cli)
+}
+
+#[test]
+fn crc() {
+    // The standard check value of CRC-32/ISO-HDLC
+    assert_eq!(BlobCrc::checksum(b"123456789"), 0xCBF4_3926);
+    let split = BlobCrc::new().update(b"1234").update(b"56789").finish();
+    assert_eq!(split, 0xCBF4_3926);
+}
+
+#[tokio::test]
+async fn blob_roundtrip() {
+    let (flash, cli) = setup();
+    let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+    let chunk_size = NonZeroUsize::new(64).unwrap();
+
+    let summary = cli.upload_blob("fw.bin", &data, chunk_size).await.unwrap();
+    assert_eq!(summary.len, 1000);
+    assert_eq!(summary.crc, BlobCrc::checksum(&data));
+    assert_eq!(flash.lock().unwrap().blobs["fw.bin"].data, data);
+
+    let read = cli.download_blob("fw.bin", chunk_size).await.unwrap();
+    assert_eq!(read, data);
+
+    let res = cli.download_blob("missing.bin", chunk_size).await;
+    assert_eq!(res, Err(HostErr::Endpoint(BlobError::NotFound)));
+}
+
+#[tokio::test]
+async fn blob_resume() {
+    let (flash, cli) = setup();
+    let data = vec![0xA5; 500];
+    let chunk_size = NonZeroUsize::new(50).unwrap();
+
+    flash.lock().unwrap().fail_after = Some(200);
+    let res = cli.upload_blob("anim.bin", &data, chunk_size).await;
+    assert_eq!(res, Err(HostErr::Endpoint(BlobError::Storage)));
+    assert_eq!(flash.lock().unwrap().blobs["anim.bin"].data.len(), 200);
+
+    // The second attempt only sends the rest
+    {
+        let mut flash = flash.lock().unwrap();
+        flash.fail_after = None;
+        flash.bytes_written = 0;
+    }
+    cli.upload_blob("anim.bin", &data, chunk_size)
+        .await
+        .unwrap();
+    assert_eq!(flash.lock().unwrap().bytes_written, 300);
+    assert_eq!(flash.lock().unwrap().blobs["anim.bin"].data, data);
+
+    // Different data starts over
+    flash.lock().unwrap().bytes_written = 0;
+    cli.upload_blob("anim.bin", &data[..100], chunk_size)
+        .await
+        .unwrap();
+    assert_eq!(flash.lock().unwrap().bytes_written, 100);
+}
+
+#[tokio::test]
+async fn blob_crc_mismatch() {
+    let (flash, cli) = setup();
+    let data = vec![1; 100];
+    let chunk_size = NonZeroUsize::new(100).unwrap();
+
+    flash.lock().unwrap().corrupt = true;
+    let res = cli.upload_blob("bad.bin", &data, chunk_size).await;
+    assert!(matches!(
+        res,
+        Err(HostErr::Endpoint(BlobError::CrcMismatch { expected, .. })) if expected == BlobCrc::checksum(&data)
+    ));
+}
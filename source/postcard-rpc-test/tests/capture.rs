@@ -0,0 +1,133 @@
+use std::{fs::File, io, path::PathBuf};
+
+use postcard_rpc::{
+    header::{VarKey, VarSeqKind},
+    host_client::{
+        capture::{read_capture, Capture, Direction, Replay},
+        HostClient, HostErr,
+    },
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    test_utils::local_setup_with_capture,
+    Endpoint,
+};
+
+fn capture_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("postcard-rpc-{name}-{}.prpc", std::process::id()))
+}
+
+/// Record a session of three pings, with the server replying ten times the request
+async fn record(name: &str) -> PathBuf {
+    let path = capture_path(name);
+    let capture = Capture::create(&path).unwrap();
+    let (mut srv, cli) = local_setup_with_capture::<WireError>(8, ERROR_PATH, &capture);
+
+    for i in 0..3u32 {
+        let fut = cli.send_resp::<PingEndpoint>(&i);
+        let srv_fut = async {
+            let req = srv.recv_from_client().await.unwrap();
+            let seq_no: u32 = req.header.seq_no.into();
+            let body: u32 = postcard::from_bytes(&req.body).unwrap();
+            srv.reply::<PingEndpoint>(seq_no, &(body * 10))
+                .await
+                .unwrap();
+        };
+        let (resp, ()) = tokio::join!(fut, srv_fut);
+        assert_eq!(resp, Ok(i * 10));
+    }
+    cli.close();
+    capture.flush().await.unwrap();
+
+    path
+}
+
+#[tokio::test]
+async fn capture_and_replay() {
+    let path = record("replay").await;
+
+    let frames = read_capture(File::open(&path).unwrap()).unwrap();
+    assert_eq!(frames.len(), 6);
+    for (i, pair) in frames.chunks(2).enumerate() {
+        assert_eq!(pair[0].direction, Direction::ToDevice);
+        assert_eq!(pair[1].direction, Direction::ToHost);
+        let (req_hdr, req_body) = pair[0].header().unwrap();
+        let (resp_hdr, resp_body) = pair[1].header().unwrap();
+        assert_eq!(req_hdr.key, VarKey::Key8(PingEndpoint::REQ_KEY));
+        assert_eq!(resp_hdr.key, VarKey::Key8(PingEndpoint::RESP_KEY));
+        assert_eq!(req_hdr.seq_no, resp_hdr.seq_no);
+        assert_eq!(postcard::from_bytes::<u32>(req_body).unwrap(), i as u32);
+        assert_eq!(
+            postcard::from_bytes::<u32>(resp_body).unwrap(),
+            i as u32 * 10
+        );
+        assert!(pair[0].timestamp <= pair[1].timestamp);
+    }
+
+    // Now without a server
+    let replay = Replay::open(&path).unwrap();
+    let cli = HostClient::<WireError>::new_replay(replay, ERROR_PATH, 8, VarSeqKind::Seq2);
+    for i in 0..3u32 {
+        assert_eq!(cli.send_resp::<PingEndpoint>(&i).await, Ok(i * 10));
+    }
+
+    // The connection closes once the host goes beyond the recording
+    assert_eq!(
+        cli.send_resp::<PingEndpoint>(&3).await,
+        Err(HostErr::Closed)
+    );
+    cli.wait_closed().await;
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn truncated_capture() {
+    let path = record("truncated").await;
+
+    // Cut the last record short, like a crash while writing would
+    let mut data = std::fs::read(&path).unwrap();
+    data.truncate(data.len() - 2);
+    let frames = read_capture(data.as_slice()).unwrap();
+    assert_eq!(frames.len(), 5);
+
+    data[0] = b'X';
+    let err = read_capture(data.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Accepts `limit` bytes, then fails every write
+struct LimitedWriter {
+    limit: usize,
+}
+
+impl io::Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.limit < buf.len() {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        self.limit -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn failed_capture() {
+    // Room for the magic only, the first record fails
+    let capture = Capture::new(LimitedWriter { limit: 8 }).unwrap();
+    capture.flush().await.unwrap();
+    let (mut srv, cli) = local_setup_with_capture::<WireError>(8, ERROR_PATH, &capture);
+
+    // The connection keeps working
+    let fut = cli.send_resp::<PingEndpoint>(&1);
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        let seq_no: u32 = req.header.seq_no.into();
+        srv.reply::<PingEndpoint>(seq_no, &10).await.unwrap();
+    };
+    let (resp, ()) = tokio::join!(fut, srv_fut);
+    assert_eq!(resp, Ok(10));
+    assert!(capture.flush().await.is_err());
+}
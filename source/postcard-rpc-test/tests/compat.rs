@@ -0,0 +1,258 @@
+use postcard_schema::{schema::owned::OwnedNamedType, Schema};
+use tokio::sync::mpsc;
+
+use postcard_rpc::{
+    header::{VarKey, VarKeyKind, VarSeqKind},
+    host_client::{test_channels as client, HostClient, MessageKind, SchemaError, SchemaReport},
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{new_server, Settings},
+            ChannelWireRx, ChannelWireSpawn, ChannelWireTx,
+        },
+        Dispatch,
+    },
+    standard_icd::WireError,
+    DeviceMap, Key,
+};
+
+/// The ICD the host was built with
+mod host_icd {
+    use postcard_rpc::{endpoints, topics, TopicDirection};
+
+    endpoints! {
+        list = ENDPOINT_LIST;
+        | EndpointTy        | RequestTy     | ResponseTy    | Path          |
+        | ----------        | ---------     | ----------    | ----          |
+        | AlphaEndpoint     | u32           | u32           | "alpha"       |
+        | BetaEndpoint      | u32           | u8            | "beta"        |
+        | GammaEndpoint     | ()            | ()            | "gamma"       |
+    }
+
+    topics! {
+        list = TOPICS_IN_LIST;
+        direction = TopicDirection::ToServer;
+        | TopicTy           | MessageTy     | Path          |
+        | -------           | ---------     | ----          |
+        | DeltaTopic        | u16           | "delta"       |
+    }
+
+    topics! {
+        list = TOPICS_OUT_LIST;
+        direction = TopicDirection::ToClient;
+        | TopicTy           | MessageTy     | Path          |
+        | -------           | ---------     | ----          |
+    }
+}
+
+/// The ICD of the (newer) firmware
+mod device_icd {
+    use postcard_rpc::{
+        define_dispatch, endpoints,
+        header::VarHeader,
+        server::impls::test_channels::dispatch_impl::{WireSpawnImpl, WireTxImpl},
+        server::SpawnContext,
+        topics, TopicDirection,
+    };
+
+    endpoints! {
+        list = ENDPOINT_LIST;
+        | EndpointTy        | RequestTy     | ResponseTy    | Path          |
+        | ----------        | ---------     | ----------    | ----          |
+        | AlphaEndpoint     | u32           | u32           | "alpha"       |
+        | BetaEndpoint      | u32           | u16           | "beta"        |
+        | EpsilonEndpoint   | ()            | ()            | "epsilon"     |
+    }
+
+    topics! {
+        list = TOPICS_IN_LIST;
+        direction = TopicDirection::ToServer;
+        | TopicTy           | MessageTy     | Path          |
+        | -------           | ---------     | ----          |
+        | DeltaTopic        | u16           | "delta"       |
+    }
+
+    topics! {
+        list = TOPICS_OUT_LIST;
+        direction = TopicDirection::ToClient;
+        | TopicTy           | MessageTy     | Path          |
+        | -------           | ---------     | ----          |
+        | ZetaTopic         | u8            | "zeta"        |
+    }
+
+    pub struct TestContext;
+
+    impl SpawnContext for TestContext {
+        type SpawnCtxt = ();
+
+        fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+    }
+
+    define_dispatch! {
+        app: DeviceDispatcher;
+        spawn_fn: spawn_fn;
+        tx_impl: WireTxImpl;
+        spawn_impl: WireSpawnImpl;
+        context: TestContext;
+
+        endpoints: {
+            list: ENDPOINT_LIST;
+
+            | EndpointTy        | kind      | handler           |
+            | ----------        | ----      | -------           |
+            | AlphaEndpoint     | blocking  | alpha_handler     |
+        };
+        topics_in: {
+            list: TOPICS_IN_LIST;
+
+            | TopicTy           | kind      | handler           |
+            | ----------        | ----      | -------           |
+        };
+        topics_out: {
+            list: TOPICS_OUT_LIST;
+        };
+    }
+
+    fn alpha_handler(_context: &mut TestContext, _header: VarHeader, body: u32) -> u32 {
+        body
+    }
+}
+
+const HOST_MAP: DeviceMap = DeviceMap {
+    types: host_icd::ENDPOINT_LIST.types,
+    endpoints: host_icd::ENDPOINT_LIST.endpoints,
+    topics_in: host_icd::TOPICS_IN_LIST.topics,
+    topics_out: host_icd::TOPICS_OUT_LIST.topics,
+    min_key_len: VarKeyKind::Key8,
+};
+
+fn setup() -> HostClient<WireError> {
+    let (client_tx, server_rx) = mpsc::channel(16);
+    let (server_tx, client_rx) = mpsc::channel(16);
+
+    let app = device_icd::DeviceDispatcher::new(device_icd::TestContext, ChannelWireSpawn {});
+    let kkind = app.min_key_len();
+    let mut server = new_server(
+        app,
+        Settings {
+            tx: ChannelWireTx::new(server_tx),
+            rx: ChannelWireRx::new(server_rx),
+            buf: 1024,
+            kkind,
+        },
+    );
+    tokio::task::spawn(async move {
+        server.run().await;
+    });
+
+    client::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1)
+}
+
+#[tokio::test]
+async fn incompatible_device() {
+    let cli = setup();
+
+    let res = cli.check_compatibility(&HOST_MAP).await;
+    let Err(SchemaError::Incompatible(diff)) = res else {
+        panic!("Expected an incompatible schema");
+    };
+    assert!(!diff.is_compatible());
+    assert_eq!(diff.missing_endpoints, ["gamma"]);
+    assert_eq!(diff.extra_endpoints, ["epsilon"]);
+    assert!(diff.missing_topics_in.is_empty());
+    assert!(diff.extra_topics_in.is_empty());
+    assert!(diff.missing_topics_out.is_empty());
+    assert_eq!(diff.extra_topics_out, ["zeta"]);
+    assert!(diff.key_collisions.is_empty());
+
+    assert_eq!(diff.type_mismatches.len(), 1);
+    let mismatch = &diff.type_mismatches[0];
+    assert_eq!(mismatch.path, "beta");
+    assert_eq!(mismatch.kind, MessageKind::Response);
+    assert_eq!(mismatch.expected, Some(OwnedNamedType::from(u8::SCHEMA)));
+    assert_eq!(mismatch.actual, OwnedNamedType::from(u16::SCHEMA));
+}
+
+#[tokio::test]
+async fn compatible_device() {
+    let cli = setup();
+
+    // Only ask for what the device and host have in common
+    const COMMON_MAP: DeviceMap = DeviceMap {
+        types: host_icd::ENDPOINT_LIST.types,
+        endpoints: &[(
+            "alpha",
+            <host_icd::AlphaEndpoint as postcard_rpc::Endpoint>::REQ_KEY,
+            <host_icd::AlphaEndpoint as postcard_rpc::Endpoint>::RESP_KEY,
+        )],
+        topics_in: host_icd::TOPICS_IN_LIST.topics,
+        topics_out: &[],
+        min_key_len: VarKeyKind::Key8,
+    };
+    let diff = cli.check_compatibility(&COMMON_MAP).await.unwrap();
+    assert!(diff.is_compatible());
+    assert!(!diff.is_empty());
+    assert!(diff.extra_endpoints.contains(&"epsilon".to_string()));
+}
+
+#[test]
+fn key_collisions() {
+    // Find two paths that collide when using one byte keys
+    let paths: Vec<&'static str> = (0..1000).map(|i| &*format!("path/{i}").leak()).collect();
+    let mut pair = None;
+    'outer: for (i, a) in paths.iter().enumerate() {
+        for b in paths.iter().skip(i + 1) {
+            let mut ka = VarKey::Key8(Key::for_path::<u32>(a));
+            let mut kb = VarKey::Key8(Key::for_path::<u32>(b));
+            ka.shrink_to(VarKeyKind::Key1);
+            kb.shrink_to(VarKeyKind::Key1);
+            if ka == kb {
+                pair = Some((*a, *b));
+                break 'outer;
+            }
+        }
+    }
+    let (a, b) = pair.unwrap();
+
+    // The host only knows `a`, the device only knows `b`
+    let endpoints: &'static [(&'static str, Key, Key)] =
+        vec![(a, Key::for_path::<u32>(a), Key::for_path::<u32>(a))].leak();
+    let map = DeviceMap {
+        types: &[],
+        endpoints,
+        topics_in: &[],
+        topics_out: &[],
+        min_key_len: VarKeyKind::Key1,
+    };
+    let mut report = SchemaReport::default();
+    report
+        .add_endpoint(
+            b.to_string(),
+            Key::for_path::<u32>(b),
+            Key::for_path::<u32>(b),
+        )
+        .unwrap();
+
+    let diff = report.diff(&map);
+    assert_eq!(diff.missing_endpoints, [a]);
+    assert_eq!(diff.extra_endpoints, [b]);
+    assert_eq!(diff.key_collisions.len(), 2);
+    assert_eq!(
+        diff.key_collisions[0].first,
+        (a.to_string(), MessageKind::Request)
+    );
+    assert_eq!(
+        diff.key_collisions[0].second,
+        (b.to_string(), MessageKind::Request)
+    );
+    assert_eq!(
+        diff.key_collisions[1].first,
+        (a.to_string(), MessageKind::Response)
+    );
+
+    // With full size keys, there is no collision
+    let map = DeviceMap {
+        min_key_len: VarKeyKind::Key8,
+        ..map
+    };
+    assert!(report.diff(&map).key_collisions.is_empty());
+}
@@ -0,0 +1,150 @@
+use postcard_schema::{schema::owned::OwnedNamedType, Schema};
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    endpoints,
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq, VarSeqKind},
+    host_client::{
+        decode::{decode_frame, DecodeError},
+        dynamic::Value,
+        MessageKind, RpcFrame, SchemaReport,
+    },
+    topics, Endpoint, Key, Topic, TopicDirection,
+};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Reading {
+    pub channel: u8,
+    pub value: i32,
+}
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+    | ReadEndpoint      | u8            | Reading       | "reading/get"     |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | -------           | ---------     | ----              |
+    | ReadingTopic      | Reading       | "reading/stream"  |
+}
+
+fn report() -> SchemaReport {
+    let mut report = SchemaReport::default();
+    report.add_type(OwnedNamedType::from(Reading::SCHEMA));
+    report
+        .add_endpoint(
+            ReadEndpoint::PATH.to_string(),
+            ReadEndpoint::REQ_KEY,
+            ReadEndpoint::RESP_KEY,
+        )
+        .unwrap();
+    report
+        .add_topic_out(ReadingTopic::PATH.to_string(), ReadingTopic::TOPIC_KEY)
+        .unwrap();
+    report
+}
+
+fn frame(key: Key, kind: VarKeyKind, seq_no: VarSeq, body: &impl Serialize) -> Vec<u8> {
+    let mut key = VarKey::Key8(key);
+    key.shrink_to(kind);
+    RpcFrame {
+        header: VarHeader { key, seq_no },
+        body: postcard::to_stdvec(body).unwrap(),
+    }
+    .to_bytes()
+}
+
+#[test]
+fn decode_full_key() {
+    let report = report();
+    let reading = Reading {
+        channel: 3,
+        value: -1000,
+    };
+    let bytes = frame(
+        ReadEndpoint::RESP_KEY,
+        VarKeyKind::Key8,
+        VarSeq::Seq4(70000),
+        &reading,
+    );
+
+    let decoded = decode_frame(&bytes, &report).unwrap();
+    assert_eq!(decoded.key_kind(), VarKeyKind::Key8);
+    assert_eq!(decoded.seq_kind(), VarSeqKind::Seq4);
+    assert_eq!(decoded.version(), 0);
+    assert_eq!(decoded.body, postcard::to_stdvec(&reading).unwrap());
+    assert_eq!(decoded.matches.len(), 1);
+
+    let m = &decoded.matches[0];
+    assert_eq!(m.path, "reading/get");
+    assert_eq!(m.kind, MessageKind::Response);
+    assert_eq!(m.key, ReadEndpoint::RESP_KEY);
+    assert_eq!(m.ty.name, "Reading");
+    assert_eq!(
+        m.value,
+        Ok(r#"{"channel": 3, "value": -1000}"#.parse::<Value>().unwrap())
+    );
+
+    let text = decoded.to_string();
+    assert!(text.contains("key: 8 bytes, seq: 4 bytes, version: 0"));
+    assert!(text.contains("Seq:     70000"));
+    assert!(text.contains(r#"Response "reading/get""#));
+}
+
+#[test]
+fn decode_short_key() {
+    let report = report();
+    let bytes = frame(
+        ReadingTopic::TOPIC_KEY,
+        VarKeyKind::Key2,
+        VarSeq::Seq1(9),
+        &Reading {
+            channel: 1,
+            value: 2,
+        },
+    );
+
+    let decoded = decode_frame(&bytes, &report).unwrap();
+    assert_eq!(decoded.key_kind(), VarKeyKind::Key2);
+    assert_eq!(decoded.seq_kind(), VarSeqKind::Seq1);
+    let m = decoded
+        .matches
+        .iter()
+        .find(|m| m.path == "reading/stream")
+        .unwrap();
+    // The full key is resolved from the report
+    assert_eq!(m.key, ReadingTopic::TOPIC_KEY);
+    assert_eq!(m.kind, MessageKind::TopicOut);
+    assert!(m.value.is_ok());
+}
+
+#[test]
+fn decode_unknown_and_bad() {
+    let report = report();
+    let bytes = frame(
+        Key::for_path::<u32>("nobody/home"),
+        VarKeyKind::Key8,
+        VarSeq::Seq2(1),
+        &5u32,
+    );
+    let decoded = decode_frame(&bytes, &report).unwrap();
+    assert!(decoded.matches.is_empty());
+    assert!(decoded.to_string().contains("unknown key"));
+
+    assert_eq!(decode_frame(&[], &report), Err(DecodeError::Empty));
+    let mut bad_version = bytes.clone();
+    bad_version[0] |= 0x01;
+    assert_eq!(
+        decode_frame(&bad_version, &report),
+        Err(DecodeError::UnsupportedVersion(1))
+    );
+    assert_eq!(
+        decode_frame(&bytes[..3], &report),
+        Err(DecodeError::BadHeader)
+    );
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use postcard_schema::{schema::owned::OwnedNamedType, Schema};
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::VarHeader,
+    host_client::{
+        dynamic::{from_postcard, to_postcard, DynamicErr, Value, ValueError},
+        HostClient, HostErr,
+    },
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        SpawnContext,
+    },
+    standard_icd::WireError,
+    topics, Key, TopicDirection,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Config {
+    pub id: u16,
+    pub offset: i64,
+    pub ratio: f32,
+    pub name: String,
+    pub modes: Vec<Mode>,
+    pub pos: (u8, i8),
+    pub serial: Option<u128>,
+    pub limits: BTreeMap<String, u32>,
+    pub flags: BTreeMap<u8, bool>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum Mode {
+    Off,
+    Fixed(i32),
+    Range { lo: u8, hi: u8 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum ConfigResult {
+    Applied { changes: u32 },
+    Rejected(String),
+}
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy        | Path          |
+    | ----------        | ---------     | ----------        | ----          |
+    | ConfigEndpoint    | Config        | ConfigResult      | "config/set"  |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path          |
+    | -------           | ---------     | ----          |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path          |
+    | -------           | ---------     | ----          |
+}
+
+pub struct TestContext;
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+define_dispatch! {
+    app: TestApp;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler           |
+        | ----------        | ----      | -------           |
+        | ConfigEndpoint    | blocking  | config_handler    |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn config_handler(_context: &mut TestContext, _header: VarHeader, body: Config) -> ConfigResult {
+    if body.name.is_empty() {
+        ConfigResult::Rejected("no name".into())
+    } else {
+        ConfigResult::Applied {
+            changes: body.modes.len() as u32,
+        }
+    }
+}
+
+fn setup() -> HostClient<WireError> {
+    let app = TestApp::new(TestContext, ChannelWireSpawn {});
+    let (_sender, cli) = spawn_channel_server(app, 1024);
+    cli
+}
+
+fn sample() -> (Config, Value) {
+    let config = Config {
+        id: 513,
+        offset: -70_000,
+        ratio: 0.5,
+        name: "motor".into(),
+        modes: vec![Mode::Off, Mode::Fixed(-3), Mode::Range { lo: 1, hi: 9 }],
+        pos: (255, -128),
+        serial: Some(u128::MAX),
+        limits: [("max".to_string(), 100), ("min".to_string(), 4)].into(),
+        flags: [(7, true)].into(),
+    };
+    let value = r#"{
+        "id": 513,
+        "offset": -70000,
+        "ratio": 0.5,
+        "name": "motor",
+        "modes": ["Off", {"Fixed": -3}, {"Range": {"lo": 1, "hi": 9}}],
+        "pos": [255, -128],
+        "serial": "340282366920938463463374607431768211455",
+        "limits": {"max": 100, "min": 4},
+        "flags": [[7, true]]
+    }"#
+    .parse()
+    .unwrap();
+    (config, value)
+}
+
+#[test]
+fn round_trip() {
+    let ty = OwnedNamedType::from(Config::SCHEMA);
+    let (config, value) = sample();
+
+    let bytes = to_postcard(&ty, &value).unwrap();
+    assert_eq!(bytes, postcard::to_stdvec(&config).unwrap());
+    assert_eq!(from_postcard(&ty, &bytes).unwrap(), value);
+}
+
+#[test]
+fn bad_values() {
+    let ty = OwnedNamedType::from(Config::SCHEMA);
+    let (_, mut value) = sample();
+
+    value["modes"][2]["Range"]["hi"] = 256.into();
+    assert_eq!(
+        to_postcard(&ty, &value),
+        Err(ValueError::Mismatch {
+            path: ".modes[2].Range.hi".into(),
+            expected: "a u8".into(),
+        })
+    );
+
+    let (_, mut value) = sample();
+    value.as_object_mut().unwrap().remove("name");
+    assert_eq!(
+        to_postcard(&ty, &value),
+        Err(ValueError::Mismatch {
+            path: ".name".into(),
+            expected: "a value".into(),
+        })
+    );
+
+    let (config, _) = sample();
+    let bytes = postcard::to_stdvec(&config).unwrap();
+    assert_eq!(
+        from_postcard(&ty, &bytes[..bytes.len() - 1]),
+        Err(ValueError::UnexpectedEnd)
+    );
+    let mut bytes = bytes;
+    bytes.push(0);
+    assert_eq!(from_postcard(&ty, &bytes), Err(ValueError::TrailingBytes));
+}
+
+#[tokio::test]
+async fn send_dynamic() {
+    let cli = setup();
+
+    let report = cli.get_schema_report().await.unwrap();
+    let ep = report
+        .endpoints
+        .iter()
+        .find(|e| e.path == "config/set")
+        .unwrap();
+
+    let (_, value) = sample();
+    let resp = cli.send_resp_dynamic(ep, &value).await.unwrap();
+    assert_eq!(
+        resp,
+        r#"{"Applied": {"changes": 3}}"#.parse::<Value>().unwrap()
+    );
+
+    let mut value = value;
+    value["name"] = "".into();
+    let resp = cli.send_resp_dynamic(ep, &value).await.unwrap();
+    assert_eq!(resp, r#"{"Rejected": "no name"}"#.parse::<Value>().unwrap());
+
+    // Bad requests are never sent
+    value["pos"] = "nowhere".into();
+    let res = cli.send_resp_dynamic(ep, &value).await;
+    assert_eq!(
+        res,
+        Err(DynamicErr::Value(ValueError::Mismatch {
+            path: ".pos".into(),
+            expected: "an array of length 2".into(),
+        }))
+    );
+
+    // Errors from the server are still reported as wire errors
+    let mut bad_ep = ep.clone();
+    bad_ep.req_key = Key::for_path::<Config>("config/other");
+    let res = cli.send_resp_dynamic(&bad_ep, &sample().1).await;
+    assert!(matches!(
+        res,
+        Err(DynamicErr::Host(HostErr::Wire(WireError::UnknownKey)))
+    ));
+}
@@ -0,0 +1,148 @@
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::VarHeader,
+    host_client::HostErr,
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{spawn_fn, WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn, ChannelWireTx,
+        },
+        Sender, SpawnContext,
+    },
+    topics, Endpoint,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Reading(pub u32);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum SensorError {
+    NotReady,
+    Overrange(u32),
+}
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy    | ErrorTy       | Path              |
+    | ----------        | ---------     | ----------    | -------       | ----              |
+    | ReadEndpoint      | u32           | Reading       | SensorError   | "sensor/read"     |
+    | ReadSpawnEndpoint | u32           | Reading       | SensorError   | "sensor/read2"    |
+    | ResetEndpoint     | ()            | ()            | -             | "sensor/reset"    |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+pub struct TestContext;
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler           |
+        | ----------        | ----      | -------           |
+        | ReadEndpoint      | blocking  | read_handler      |
+        | ReadSpawnEndpoint | spawn     | read_spawn        |
+        | ResetEndpoint     | async     | reset_handler     |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn read(val: u32) -> Result<Reading, SensorError> {
+    match val {
+        0 => Err(SensorError::NotReady),
+        1..=100 => Ok(Reading(val)),
+        _ => Err(SensorError::Overrange(val)),
+    }
+}
+
+fn read_handler(
+    _context: &mut TestContext,
+    _header: VarHeader,
+    body: u32,
+) -> Result<Reading, SensorError> {
+    read(body)
+}
+
+async fn read_spawn(_context: (), header: VarHeader, body: u32, out: Sender<ChannelWireTx>) {
+    let _ = out
+        .reply::<ReadSpawnEndpoint>(header.seq_no, &read(body))
+        .await;
+}
+
+async fn reset_handler(_context: &mut TestContext, _header: VarHeader, _body: ()) {}
+
+fn setup() -> postcard_rpc::host_client::HostClient<postcard_rpc::standard_icd::WireError> {
+    let app = SingleDispatcher::new(TestContext, ChannelWireSpawn {});
+    let (_sender, cli) = spawn_channel_server(app, 1024);
+    cli
+}
+
+#[tokio::test]
+async fn typed_errors() {
+    let cli = setup();
+
+    let resp = cli.send_resp_fallible::<ReadEndpoint>(&42).await;
+    assert_eq!(resp, Ok(Reading(42)));
+    let resp = cli.send_resp_fallible::<ReadEndpoint>(&0).await;
+    assert_eq!(resp, Err(HostErr::Endpoint(SensorError::NotReady)));
+    let resp = cli.send_resp_fallible::<ReadSpawnEndpoint>(&1000).await;
+    assert_eq!(resp, Err(HostErr::Endpoint(SensorError::Overrange(1000))));
+
+    // The plain `send_resp` gets the whole result
+    let resp = cli.send_resp::<ReadEndpoint>(&0).await;
+    assert_eq!(resp, Ok(Err(SensorError::NotReady)));
+    let resp = cli.send_resp::<ResetEndpoint>(&()).await;
+    assert_eq!(resp, Ok(()));
+}
+
+#[tokio::test]
+async fn typed_errors_in_schema() {
+    let cli = setup();
+    let report = cli.get_schema_report().await.unwrap();
+
+    assert!(report.types.iter().any(|t| t.name == "Reading"));
+    assert!(report.types.iter().any(|t| t.name == "SensorError"));
+    let ep = report
+        .endpoints
+        .iter()
+        .find(|e| e.path == ReadEndpoint::PATH)
+        .unwrap();
+    assert_eq!(ep.resp_key, ReadEndpoint::RESP_KEY);
+    assert_eq!(ep.resp_ty.name, "Result<T, E>");
+}
@@ -0,0 +1,300 @@
+use core::time::Duration;
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::{VarKey, VarSeq},
+    host_client::{FlowSubscribeError, HostClient, HostErr, TopicStats},
+    server::{
+        flow::{topic_flow, FlowContext, TopicFlow},
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        Sender, SpawnContext,
+    },
+    standard_icd::{
+        TopicFlowEndpoint, TopicFlowError, TopicFlowRequest, TopicFlowStats, WireError,
+        STANDARD_ICD_FLOW_ENDPOINTS,
+    },
+    test_utils::local_setup,
+    topics, Endpoint, Key, Topic,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Accel(pub u32);
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    include = [STANDARD_ICD_FLOW_ENDPOINTS];
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+    | AccelTopic        | Accel         | "accel"           |
+}
+
+pub struct TestContext {
+    flows: [&'static TopicFlow; 1],
+}
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+impl FlowContext for TestContext {
+    fn topic_flows(&self) -> &[&TopicFlow] {
+        &self.flows
+    }
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler           |
+        | ----------        | ----      | -------           |
+        | TopicFlowEndpoint | blocking  | topic_flow        |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn setup() -> (
+    &'static TopicFlow,
+    Sender<WireTxImpl>,
+    HostClient<WireError>,
+) {
+    // Each test gets its own flow state
+    let flow: &'static TopicFlow = Box::leak(Box::new(TopicFlow::new(AccelTopic::TOPIC_KEY)));
+    let app = SingleDispatcher::new(TestContext { flows: [flow] }, ChannelWireSpawn {});
+    let (sender, cli) = spawn_channel_server(app, 128);
+    (flow, sender, cli)
+}
+
+async fn publish_n(sender: &Sender<WireTxImpl>, flow: &TopicFlow, range: core::ops::Range<u32>) {
+    for i in range {
+        sender
+            .publish_flow::<AccelTopic>(flow, VarSeq::Seq4(i), &Accel(i))
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn flow_window() {
+    let (flow, sender, cli) = setup();
+
+    assert!(!flow.stats().enabled);
+    let mut sub = cli.subscribe_flow::<AccelTopic>(4).await.unwrap();
+    assert!(flow.stats().enabled);
+
+    // Only the window is published, the rest is dropped on the server
+    publish_n(&sender, flow, 0..10).await;
+    assert_eq!(
+        sub.server_stats().await.unwrap(),
+        TopicFlowStats {
+            enabled: true,
+            credits: 0,
+            sent: 4,
+            dropped: 6,
+        }
+    );
+
+    // Receiving half of the window grants it again, in the background
+    assert_eq!(sub.recv().await, Some(Accel(0)));
+    assert_eq!(sub.recv().await, Some(Accel(1)));
+    while flow.stats().credits != 2 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    publish_n(&sender, flow, 10..13).await;
+    for i in [2, 3, 10, 11] {
+        assert_eq!(sub.recv().await, Some(Accel(i)));
+    }
+
+    // Nothing was dropped by the client
+    let stats = sub.stats().await;
+    assert_eq!(
+        stats,
+        TopicStats {
+            received: 6,
+            late: 0,
+            dropped: 0,
+        }
+    );
+    assert_eq!(cli.topic_stats(AccelTopic::TOPIC_KEY).await, stats);
+
+    let stats = sub.close().await.unwrap();
+    assert!(!stats.enabled);
+    assert_eq!(stats.dropped, 7);
+    // Without flow control, everything is published
+    assert!(sender
+        .publish_flow::<AccelTopic>(flow, VarSeq::Seq4(20), &Accel(20))
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn flow_zero_window() {
+    let (flow, _sender, cli) = setup();
+    let res = cli.subscribe_flow::<AccelTopic>(0).await;
+    assert!(matches!(res, Err(FlowSubscribeError::ZeroWindow)));
+    assert!(!flow.stats().enabled);
+}
+
+#[tokio::test]
+async fn flow_grant_does_not_block() {
+    let (mut srv, cli) = local_setup::<WireError>(8, "error");
+
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        assert_eq!(req.header.key, VarKey::Key8(TopicFlowEndpoint::REQ_KEY));
+        let resp: Result<TopicFlowStats, TopicFlowError> = Ok(TopicFlowStats {
+            enabled: true,
+            credits: 2,
+            sent: 0,
+            dropped: 0,
+        });
+        srv.reply::<TopicFlowEndpoint>(req.header.seq_no.into(), &resp)
+            .await
+            .unwrap();
+    };
+    let (sub, ()) = tokio::join!(cli.subscribe_flow::<AccelTopic>(2), srv_fut);
+    let mut sub = sub.unwrap();
+
+    // The server never answers the grant, but the messages are still received
+    for i in 0..2 {
+        srv.publish::<AccelTopic>(i, &Accel(i)).await.unwrap();
+    }
+    assert_eq!(sub.recv().await, Some(Accel(0)));
+    assert_eq!(sub.recv().await, Some(Accel(1)));
+    let grant = srv.recv_from_client().await.unwrap();
+    assert_eq!(grant.header.key, VarKey::Key8(TopicFlowEndpoint::REQ_KEY));
+}
+
+#[tokio::test]
+async fn failed_grants_are_retried() {
+    let (mut srv, cli) = local_setup::<WireError>(8, "error");
+
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        let resp: Result<TopicFlowStats, TopicFlowError> = Ok(TopicFlowStats {
+            enabled: true,
+            credits: 1,
+            sent: 0,
+            dropped: 0,
+        });
+        srv.reply::<TopicFlowEndpoint>(req.header.seq_no.into(), &resp)
+            .await
+            .unwrap();
+    };
+    let (sub, ()) = tokio::join!(cli.subscribe_flow::<AccelTopic>(1), srv_fut);
+    let mut sub = sub.unwrap();
+
+    // The server is out of credits, and never answers the first grant
+    srv.publish::<AccelTopic>(0, &Accel(0)).await.unwrap();
+    assert_eq!(sub.recv().await, Some(Accel(0)));
+    let expected = TopicFlowRequest::Grant {
+        key: AccelTopic::TOPIC_KEY,
+        limit: 2,
+    };
+    let grant = srv.recv_from_client().await.unwrap();
+    assert_eq!(
+        postcard::from_bytes::<TopicFlowRequest>(&grant.body),
+        Ok(expected)
+    );
+
+    // ...so the same grant is sent again, without another message
+    let retry = srv.recv_from_client().await.unwrap();
+    assert_eq!(
+        postcard::from_bytes::<TopicFlowRequest>(&retry.body),
+        Ok(expected)
+    );
+}
+
+#[tokio::test]
+async fn grants_are_idempotent() {
+    let (flow, _sender, cli) = setup();
+    for limit in [4, 4, 2] {
+        let stats = cli
+            .send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Grant {
+                key: AccelTopic::TOPIC_KEY,
+                limit,
+            })
+            .await
+            .unwrap();
+        assert_eq!(stats.credits, 4);
+    }
+    assert!(flow.try_take());
+    assert_eq!(flow.stats().credits, 3);
+}
+
+#[tokio::test]
+async fn flow_unknown_topic() {
+    let (_flow, _sender, cli) = setup();
+    let res = cli
+        .send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Stats {
+            key: Key::for_path::<Accel>("other"),
+        })
+        .await;
+    assert_eq!(res, Err(HostErr::Endpoint(TopicFlowError::UnknownTopic)));
+}
+
+#[tokio::test]
+async fn client_drop_counters() {
+    let (flow, sender, cli) = setup();
+    let mut sub = cli.subscribe_exclusive::<AccelTopic>(2).await.unwrap();
+
+    // Flow control is not enabled, so the client drops what does not fit
+    publish_n(&sender, flow, 0..5).await;
+    // The response is received after the published messages
+    let server = cli
+        .send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Stats {
+            key: AccelTopic::TOPIC_KEY,
+        })
+        .await
+        .unwrap();
+    assert_eq!(server.sent, 5);
+    assert_eq!(server.dropped, 0);
+
+    assert_eq!(
+        cli.topic_stats(AccelTopic::TOPIC_KEY).await,
+        TopicStats {
+            received: 2,
+            late: 0,
+            dropped: 3,
+        }
+    );
+    assert_eq!(sub.recv().await, Some(Accel(0)));
+    assert_eq!(sub.recv().await, Some(Accel(1)));
+}
@@ -0,0 +1,178 @@
+use core::{num::NonZeroUsize, time::Duration};
+
+use postcard_rpc::{
+    endpoints,
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq, VarSeqKind},
+    host_client::{HostClient, HostClientConfig, HostErr, Priority, QueueStats, RpcFrame},
+    standard_icd::{GetAllSchemasEndpoint, WireError, ERROR_PATH},
+    test_utils::{local_setup_with_config, LocalFakeServer},
+    Endpoint, Key,
+};
+use tokio::task::JoinHandle;
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy    | Path          |
+    | ----------        | ---------     | ----------    | ----          |
+    | ControlEndpoint   | u32           | u32           | "control"     |
+    | NormalEndpoint    | u32           | u32           | "normal"      |
+    | BulkEndpoint      | u32           | u32           | "bulk"        |
+    | StreamEndpoint    | u32           | u32 stream    | "stream"      |
+}
+
+fn setup(max_in_flight: Option<usize>) -> (LocalFakeServer, HostClient<WireError>) {
+    let mut config = HostClientConfig::new(VarSeqKind::Seq2, ERROR_PATH, 8);
+    config.max_in_flight = max_in_flight.map(|n| NonZeroUsize::new(n).unwrap());
+    let (srv, cli) = local_setup_with_config::<WireError>(&config);
+    cli.set_priority::<ControlEndpoint>(Priority::High);
+    cli.set_priority::<BulkEndpoint>(Priority::Bulk);
+    (srv, cli)
+}
+
+fn spawn_req<E>(cli: &HostClient<WireError>, val: u32) -> JoinHandle<Result<u32, HostErr<WireError>>>
+where
+    E: Endpoint<Request = u32, Response = u32>,
+{
+    let cli = cli.clone();
+    tokio::task::spawn(async move { cli.send_resp::<E>(&val).await })
+}
+
+/// Wait until the stats match, the requests are sent by other tasks
+async fn wait_stats(cli: &HostClient<WireError>, f: impl Fn(&QueueStats) -> bool) {
+    for _ in 0..1000 {
+        if f(&cli.queue_stats()) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    panic!("unexpected stats: {:?}", cli.queue_stats());
+}
+
+/// Receive the next request, and answer it with its value
+async fn answer(srv: &mut LocalFakeServer) -> (Key, u32) {
+    let req = srv.recv_from_client().await.unwrap();
+    let val: u32 = postcard::from_bytes(&req.body).unwrap();
+    let seq_no = req.header.seq_no.into();
+    let key = match req.header.key {
+        VarKey::Key8(key) => key,
+        other => panic!("unexpected key: {other:?}"),
+    };
+    if key == ControlEndpoint::REQ_KEY {
+        srv.reply::<ControlEndpoint>(seq_no, &val).await.unwrap();
+    } else if key == NormalEndpoint::REQ_KEY {
+        srv.reply::<NormalEndpoint>(seq_no, &val).await.unwrap();
+    } else {
+        srv.reply::<BulkEndpoint>(seq_no, &val).await.unwrap();
+    }
+    (key, val)
+}
+
+#[tokio::test]
+async fn priorities() {
+    let (mut srv, cli) = setup(Some(2));
+    assert_eq!(cli.priority::<NormalEndpoint>(), Priority::Normal);
+    assert_eq!(cli.priority::<GetAllSchemasEndpoint>(), Priority::Bulk);
+
+    // Bulk requests leave a slot to the others
+    let bulk: Vec<_> = (0..3).map(|i| spawn_req::<BulkEndpoint>(&cli, i)).collect();
+    wait_stats(&cli, |s| s.in_flight == 1 && s.waiting == [0, 0, 2]).await;
+    let first_bulk = srv.recv_from_client().await.unwrap();
+
+    let control = spawn_req::<ControlEndpoint>(&cli, 10);
+    assert_eq!(answer(&mut srv).await, (ControlEndpoint::REQ_KEY, 10));
+    assert_eq!(control.await.unwrap(), Ok(10));
+
+    // Once full, waiting requests are sent by priority
+    let normal = spawn_req::<NormalEndpoint>(&cli, 20);
+    wait_stats(&cli, |s| s.in_flight == 2 && s.waiting == [0, 0, 2]).await;
+    let control = spawn_req::<ControlEndpoint>(&cli, 11);
+    wait_stats(&cli, |s| s.waiting == [1, 0, 2]).await;
+
+    // Answering the normal request lets the control request through
+    assert_eq!(answer(&mut srv).await, (NormalEndpoint::REQ_KEY, 20));
+    assert_eq!(normal.await.unwrap(), Ok(20));
+    assert_eq!(answer(&mut srv).await, (ControlEndpoint::REQ_KEY, 11));
+    assert_eq!(control.await.unwrap(), Ok(11));
+
+    // The bulk requests continue, one at a time
+    let val: u32 = postcard::from_bytes(&first_bulk.body).unwrap();
+    srv.reply::<BulkEndpoint>(first_bulk.header.seq_no.into(), &val)
+        .await
+        .unwrap();
+    for _ in 1..3 {
+        let (key, _) = answer(&mut srv).await;
+        assert_eq!(key, BulkEndpoint::REQ_KEY);
+    }
+    for (i, req) in bulk.into_iter().enumerate() {
+        assert_eq!(req.await.unwrap(), Ok(i as u32));
+    }
+    wait_stats(&cli, |s| s.in_flight == 0 && s.waiting == [0, 0, 0]).await;
+}
+
+#[tokio::test]
+async fn unlimited_by_default() {
+    let (mut srv, cli) = setup(None);
+    let reqs: Vec<_> = (0..4).map(|i| spawn_req::<BulkEndpoint>(&cli, i)).collect();
+    wait_stats(&cli, |s| s.in_flight == 4).await;
+    let stats = cli.queue_stats();
+    assert_eq!(stats.max_in_flight, None);
+    assert_eq!(stats.outgoing_depth, 8);
+    for _ in 0..4 {
+        answer(&mut srv).await;
+    }
+    for (i, req) in reqs.into_iter().enumerate() {
+        assert_eq!(req.await.unwrap(), Ok(i as u32));
+    }
+}
+
+#[tokio::test]
+async fn raw_requests_with_shrunk_keys() {
+    let (mut srv, cli) = setup(Some(2));
+    let bulk = spawn_req::<BulkEndpoint>(&cli, 0);
+    wait_stats(&cli, |s| s.in_flight == 1).await;
+
+    // The priority is still known from the shrunk key
+    let mut key = VarKey::Key8(BulkEndpoint::REQ_KEY);
+    key.shrink_to(VarKeyKind::Key2);
+    let frame = RpcFrame {
+        header: VarHeader {
+            key,
+            seq_no: VarSeq::Seq2(100),
+        },
+        body: postcard::to_stdvec(&1u32).unwrap(),
+    };
+    let raw = {
+        let cli = cli.clone();
+        tokio::task::spawn(async move { cli.send_resp_raw(frame, BulkEndpoint::RESP_KEY).await })
+    };
+    wait_stats(&cli, |s| s.waiting == [0, 0, 1]).await;
+
+    assert_eq!(answer(&mut srv).await, (BulkEndpoint::REQ_KEY, 0));
+    assert_eq!(bulk.await.unwrap(), Ok(0));
+    let req = srv.recv_from_client().await.unwrap();
+    assert_eq!(req.header.key, key);
+    srv.reply::<BulkEndpoint>(100, &1).await.unwrap();
+    let resp = raw.await.unwrap().unwrap();
+    assert_eq!(postcard::from_bytes::<u32>(&resp.body), Ok(1));
+}
+
+#[tokio::test]
+async fn streams_wait_for_their_turn() {
+    let (mut srv, cli) = setup(Some(1));
+    let normal = spawn_req::<NormalEndpoint>(&cli, 0);
+    wait_stats(&cli, |s| s.in_flight == 1).await;
+
+    let stream = {
+        let cli = cli.clone();
+        tokio::task::spawn(async move { cli.send_stream::<StreamEndpoint>(&1).await.is_ok() })
+    };
+    wait_stats(&cli, |s| s.waiting == [0, 1, 0]).await;
+    assert_eq!(answer(&mut srv).await, (NormalEndpoint::REQ_KEY, 0));
+    assert_eq!(normal.await.unwrap(), Ok(0));
+
+    // The open stream does not count as in flight
+    assert!(stream.await.unwrap());
+    let req = srv.recv_from_client().await.unwrap();
+    assert_eq!(req.header.key, VarKey::Key8(StreamEndpoint::REQ_KEY));
+    wait_stats(&cli, |s| s.in_flight == 0 && s.waiting == [0, 0, 0]).await;
+}
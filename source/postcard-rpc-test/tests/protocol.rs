@@ -0,0 +1,251 @@
+use core::{num::NonZeroUsize, time::Duration};
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::{VarHeader, VarKey, VarSeqKind},
+    host_client::{
+        test_channels as client, FlowSubscribeError, HostClient, HostClientConfig, HostErr,
+    },
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        protocol::{protocol_negotiation, ProtocolContext, ProtocolState},
+        SpawnContext,
+    },
+    standard_icd::{
+        ProtocolAgreement, ProtocolEndpoint, ProtocolError, ProtocolInfo, WireError, ERROR_PATH,
+        STANDARD_ICD_PROTOCOL_ENDPOINTS,
+    },
+    test_utils::{local_setup, local_setup_with_config},
+    topics, Endpoint,
+};
+use postcard_rpc_test::{spawn_server_on_channels, ServerHandle};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Accel(pub u32);
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    include = [STANDARD_ICD_PROTOCOL_ENDPOINTS];
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+    | AccelTopic        | Accel         | "accel"           |
+}
+
+pub struct TestContext {
+    protocol: &'static ProtocolState,
+}
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+impl ProtocolContext for TestContext {
+    fn protocol(&self) -> &ProtocolState {
+        self.protocol
+    }
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler               |
+        | ----------        | ----      | -------               |
+        | ProtocolEndpoint  | blocking  | protocol_negotiation  |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn spawn_server(
+    capabilities: u32,
+    server_tx: mpsc::Sender<Vec<u8>>,
+    server_rx: mpsc::Receiver<Vec<u8>>,
+) -> (&'static ProtocolState, ServerHandle) {
+    // Each server gets its own state
+    let protocol: &'static ProtocolState = Box::leak(Box::new(ProtocolState::new(capabilities)));
+    let app = SingleDispatcher::new(TestContext { protocol }, ChannelWireSpawn {});
+    let (_sender, handle) = spawn_server_on_channels(app, 128, server_tx, server_rx, |server| {
+        server.set_protocol(protocol);
+    });
+    (protocol, handle)
+}
+
+fn setup(capabilities: u32) -> (&'static ProtocolState, HostClient<WireError>) {
+    let (client_tx, server_rx) = mpsc::channel(16);
+    let (server_tx, client_rx) = mpsc::channel(16);
+    let (protocol, _) = spawn_server(capabilities, server_tx, server_rx);
+
+    (
+        protocol,
+        client::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1),
+    )
+}
+
+fn config(negotiate_protocol: bool) -> HostClientConfig<'static> {
+    let mut config = HostClientConfig::new(VarSeqKind::Seq1, ERROR_PATH, 8);
+    config.negotiate_protocol = negotiate_protocol;
+    config
+}
+
+async fn wait_for_protocol(cli: &HostClient<WireError>, capabilities: u32) {
+    while cli.protocol().map(|p| p.capabilities) != Some(capabilities) {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn negotiate() {
+    let (protocol, cli) = setup(ProtocolInfo::CAP_BLOB | (1 << 31));
+    assert_eq!(cli.protocol(), None);
+    assert_eq!(cli.protocol_version(), 0);
+
+    // Only the capabilities known to both sides are agreed on
+    let agreement = cli.negotiate_protocol().await.unwrap();
+    let expected = ProtocolAgreement {
+        version: 0,
+        capabilities: ProtocolInfo::CAP_BLOB,
+    };
+    assert_eq!(agreement, expected);
+    assert_eq!(cli.protocol(), Some(expected));
+    assert_eq!(cli.protocol_version(), 0);
+    assert_eq!(protocol.version(), 0);
+}
+
+#[tokio::test]
+async fn no_common_version() {
+    let (_protocol, cli) = setup(0);
+
+    // A client that only speaks versions unknown to the server
+    let req = ProtocolInfo {
+        versions: 0b0110,
+        capabilities: 0,
+    };
+    let res = cli.send_resp_fallible::<ProtocolEndpoint>(&req).await;
+    assert_eq!(res, Err(HostErr::Endpoint(ProtocolError::NoCommonVersion)));
+}
+
+#[tokio::test]
+async fn unsupported_agreement() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+
+    // A server must not pick a version the client did not offer
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        let info: ProtocolInfo = postcard::from_bytes(&req.body).unwrap();
+        assert_eq!(info.versions, VarHeader::SUPPORTED_VERSIONS);
+        let resp: Result<ProtocolAgreement, ProtocolError> = Ok(ProtocolAgreement {
+            version: 3,
+            capabilities: 0,
+        });
+        srv.reply::<ProtocolEndpoint>(req.header.seq_no.into(), &resp)
+            .await
+            .unwrap();
+    };
+    let (res, ()) = tokio::join!(cli.negotiate_protocol(), srv_fut);
+    assert_eq!(res, Err(HostErr::BadResponse));
+    assert_eq!(cli.protocol(), None);
+}
+
+#[tokio::test]
+async fn capabilities_are_respected() {
+    let (mut srv, cli) = local_setup_with_config::<WireError>(&config(true));
+
+    // The client negotiates on its own, and the server supports nothing optional
+    let req = srv.recv_from_client().await.unwrap();
+    assert_eq!(req.header.key, VarKey::Key8(ProtocolEndpoint::REQ_KEY));
+    let resp: Result<ProtocolAgreement, ProtocolError> = Ok(ProtocolAgreement {
+        version: 0,
+        capabilities: 0,
+    });
+    srv.reply::<ProtocolEndpoint>(req.header.seq_no.into(), &resp)
+        .await
+        .unwrap();
+    wait_for_protocol(&cli, 0).await;
+    assert!(!cli.supports(ProtocolInfo::CAP_BLOB));
+
+    let chunk_size = NonZeroUsize::new(2).unwrap();
+    let res = cli.upload_blob("blob", &[1, 2, 3], chunk_size).await;
+    assert!(matches!(res, Err(HostErr::Unsupported)));
+    let res = cli.download_blob("blob", chunk_size).await;
+    assert!(matches!(res, Err(HostErr::Unsupported)));
+    let res = cli.subscribe_flow::<AccelTopic>(4).await;
+    assert!(matches!(
+        res,
+        Err(FlowSubscribeError::Grant(HostErr::Unsupported))
+    ));
+
+    // Subscribing works, but the server is not told about it
+    let sub = cli.subscribe_exclusive::<AccelTopic>(4).await.unwrap();
+    drop(sub);
+    let res = tokio::time::timeout(Duration::from_millis(50), srv.recv_from_client()).await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn negotiate_on_reconnect() {
+    let (servers_tx, mut servers) = mpsc::channel(4);
+    let capabilities = [ProtocolInfo::CAP_BLOB, ProtocolInfo::CAP_TOPIC_FLOW];
+    let mut next = capabilities.into_iter();
+    let cli = client::new_reconnecting_from_channels(
+        move || {
+            let servers_tx = servers_tx.clone();
+            let capabilities = next.next().unwrap();
+            async move {
+                let (client_tx, server_rx) = mpsc::channel(16);
+                let (server_tx, client_rx) = mpsc::channel(16);
+                servers_tx
+                    .send(spawn_server(capabilities, server_tx, server_rx))
+                    .await
+                    .unwrap();
+                (client_tx, client_rx)
+            }
+        },
+        &config(true),
+    );
+
+    let (_, handle) = servers.recv().await.unwrap();
+    wait_for_protocol(&cli, ProtocolInfo::CAP_BLOB).await;
+
+    // The new device has other capabilities
+    handle.abort();
+    let _server = servers.recv().await.unwrap();
+    wait_for_protocol(&cli, ProtocolInfo::CAP_TOPIC_FLOW).await;
+    cli.close();
+}
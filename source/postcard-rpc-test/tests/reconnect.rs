@@ -0,0 +1,71 @@
+use core::time::Duration;
+
+use postcard_rpc::{
+    host_client::ConnectionState,
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    test_utils::local_setup_reconnecting,
+    topics, TopicDirection,
+};
+
+topics! {
+    list = TOPICS_OUT;
+    direction = TopicDirection::ToClient;
+    | TopicTy   | MessageTy | Path    |
+    | -------   | --------- | ----    |
+    | TickTopic | u32       | "tick"  |
+}
+
+#[tokio::test]
+async fn reconnects_after_disconnect() {
+    let (mut servers, cli) = local_setup_reconnecting::<WireError>(8, ERROR_PATH);
+    let mut state = cli.connection_state();
+
+    let mut srv = servers.recv().await.unwrap();
+    state
+        .wait_for(|s| *s == ConnectionState::Connected)
+        .await
+        .unwrap();
+    let mut sub = cli.subscribe_multi::<TickTopic>(8).await.unwrap();
+
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        srv.reply::<PingEndpoint>(req.header.seq_no.into(), &1)
+            .await
+            .unwrap();
+    };
+    let (resp, ()) = tokio::join!(cli.send_resp::<PingEndpoint>(&1), srv_fut);
+    assert_eq!(resp, Ok(1));
+    srv.publish::<TickTopic>(0, &10).await.unwrap();
+    assert_eq!(sub.recv().await.unwrap(), 10);
+
+    // Unplug the device, the client notices and connects again
+    srv.cause_fatal_error();
+    let mut srv = servers.recv().await.unwrap();
+    state
+        .wait_for(|s| *s == ConnectionState::Connected)
+        .await
+        .unwrap();
+    assert!(!cli.is_closed());
+
+    // Requests and subscriptions keep working on the new connection
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        srv.reply::<PingEndpoint>(req.header.seq_no.into(), &2)
+            .await
+            .unwrap();
+    };
+    let (resp, ()) = tokio::join!(cli.send_resp::<PingEndpoint>(&2), srv_fut);
+    assert_eq!(resp, Ok(2));
+    srv.publish::<TickTopic>(1, &20).await.unwrap();
+    assert_eq!(sub.recv().await.unwrap(), 20);
+
+    // Only closing the client stops it for good
+    cli.close();
+    state
+        .wait_for(|s| *s == ConnectionState::Closed)
+        .await
+        .unwrap();
+    assert!(sub.recv().await.is_err());
+    let next = tokio::time::timeout(Duration::from_secs(1), servers.recv()).await;
+    assert!(matches!(next, Ok(None)));
+}
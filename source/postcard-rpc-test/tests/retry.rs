@@ -0,0 +1,87 @@
+use core::time::Duration;
+
+use postcard_rpc::{
+    header::{VarHeader, VarKey},
+    host_client::{HostErr, RetryPolicy, RpcFrame},
+    standard_icd::{PingEndpoint, WireError, ERROR_KEY, ERROR_PATH},
+    test_utils::{local_setup, LocalFakeServer},
+};
+
+async fn reply_err(srv: &mut LocalFakeServer, req: &RpcFrame, err: WireError) {
+    let frame = RpcFrame {
+        header: VarHeader {
+            key: VarKey::Key8(ERROR_KEY),
+            seq_no: req.header.seq_no,
+        },
+        body: postcard::to_stdvec(&err).unwrap(),
+    };
+    srv.to_client.send(frame.to_bytes()).await.unwrap();
+}
+
+#[tokio::test]
+async fn retry_on_timeout() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+    cli.set_retry_policy::<PingEndpoint>(
+        RetryPolicy::new(3, Duration::from_millis(50))
+            .with_backoff(Duration::from_millis(5), Duration::from_millis(10)),
+    );
+
+    let srv_fut = async {
+        // Ignore the first attempt, answer the second
+        let first = srv.recv_from_client().await.unwrap();
+        let second = srv.recv_from_client().await.unwrap();
+        assert_ne!(first.header.seq_no, second.header.seq_no);
+        assert_eq!(first.body, second.body);
+        srv.reply::<PingEndpoint>(second.header.seq_no.into(), &42)
+            .await
+            .unwrap();
+    };
+    let (resp, ()) = tokio::join!(cli.send_resp::<PingEndpoint>(&42), srv_fut);
+    assert_eq!(resp, Ok(42));
+
+    // Once the policy is removed, there is only one attempt, which never finishes
+    cli.clear_retry_policy::<PingEndpoint>();
+    let resp = tokio::time::timeout(
+        Duration::from_millis(100),
+        cli.send_resp::<PingEndpoint>(&43),
+    )
+    .await;
+    assert!(resp.is_err());
+    srv.recv_from_client().await.unwrap();
+    assert!(srv.from_client.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn retry_gives_up() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+    let policy = RetryPolicy::new(2, Duration::from_millis(20));
+
+    let resp = cli.send_resp_with_retry::<PingEndpoint>(&42, &policy).await;
+    assert_eq!(resp, Err(HostErr::Timeout));
+
+    srv.recv_from_client().await.unwrap();
+    srv.recv_from_client().await.unwrap();
+    assert!(srv.from_client.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn retry_on_wire_err() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+    let policy = RetryPolicy::new(3, Duration::from_secs(1))
+        .with_retry_on(|e| matches!(e, WireError::FailedToSpawn));
+
+    let srv_fut = async {
+        // Retried
+        let req = srv.recv_from_client().await.unwrap();
+        reply_err(&mut srv, &req, WireError::FailedToSpawn).await;
+        // Not retried
+        let req = srv.recv_from_client().await.unwrap();
+        reply_err(&mut srv, &req, WireError::DeserFailed).await;
+    };
+    let (resp, ()) = tokio::join!(
+        cli.send_resp_with_retry::<PingEndpoint>(&42, &policy),
+        srv_fut
+    );
+    assert_eq!(resp, Err(HostErr::Wire(WireError::DeserFailed)));
+    assert!(srv.from_client.try_recv().is_err());
+}
@@ -0,0 +1,158 @@
+use core::time::Duration;
+
+use tokio::sync::mpsc;
+
+use postcard_rpc::{
+    header::{VarHeader, VarKey, VarSeq, VarSeqKind},
+    host_client::{test_channels as client, HostClient, HostErr, RpcFrame},
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    test_utils::local_setup,
+    Endpoint,
+};
+
+/// A fake server speaking raw frames over channels
+struct RawServer {
+    rx: mpsc::Receiver<Vec<u8>>,
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl RawServer {
+    async fn recv(&mut self) -> VarHeader {
+        let frame = self.rx.recv().await.unwrap();
+        VarHeader::take_from_slice(&frame).unwrap().0
+    }
+
+    async fn reply(&mut self, seq_no: VarSeq, val: u32) {
+        let mut frame = VarHeader {
+            key: VarKey::Key8(PingEndpoint::RESP_KEY),
+            seq_no,
+        }
+        .write_to_vec();
+        frame.extend(postcard::to_stdvec(&val).unwrap());
+        self.tx.send(frame).await.unwrap();
+    }
+}
+
+fn setup_seq1() -> (RawServer, HostClient<WireError>) {
+    let (client_tx, server_rx) = mpsc::channel(512);
+    let (server_tx, client_rx) = mpsc::channel(512);
+    let cli = client::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1);
+    let srv = RawServer {
+        rx: server_rx,
+        tx: server_tx,
+    };
+    (srv, cli)
+}
+
+#[tokio::test]
+async fn seq1_wraparound_skips_pending() {
+    let (mut srv, cli) = setup_seq1();
+
+    // The first request stays in flight
+    let first = tokio::task::spawn({
+        let cli = cli.clone();
+        async move { cli.send_resp::<PingEndpoint>(&1000).await }
+    });
+    let hdr = srv.recv().await;
+    assert!(matches!(hdr.seq_no, VarSeq::Seq1(0)));
+
+    // After wrapping around, the number of the first request is skipped
+    let mut seqs = vec![];
+    for i in 1..=300 {
+        let srv_fut = async {
+            let hdr = srv.recv().await;
+            srv.reply(hdr.seq_no, i).await;
+            hdr.seq_no
+        };
+        let (resp, seq_no) = tokio::join!(cli.send_resp::<PingEndpoint>(&i), srv_fut);
+        assert_eq!(resp, Ok(i));
+        let VarSeq::Seq1(seq) = seq_no else {
+            panic!("expected a one byte sequence number, got {seq_no:?}");
+        };
+        seqs.push(seq);
+    }
+    assert!(!seqs.contains(&0));
+    assert_eq!(seqs[..3], [1, 2, 3]);
+    assert_eq!(seqs[254..257], [255, 1, 2]);
+
+    srv.reply(VarSeq::Seq1(0), 1000).await;
+    assert_eq!(first.await.unwrap(), Ok(1000));
+}
+
+#[tokio::test]
+async fn seq1_limits_requests_in_flight() {
+    let (mut srv, cli) = setup_seq1();
+
+    let mut pending = vec![];
+    for i in 0..257u32 {
+        let cli = cli.clone();
+        pending.push(tokio::task::spawn(async move {
+            cli.send_resp::<PingEndpoint>(&i).await
+        }));
+    }
+
+    // Only as many requests as there are sequence numbers are sent...
+    let mut seen = vec![];
+    for _ in 0..256 {
+        seen.push(srv.recv().await.seq_no);
+    }
+    let more = tokio::time::timeout(Duration::from_millis(50), srv.recv()).await;
+    assert!(more.is_err());
+
+    // A raw request with a number in use fails without waiting for a free one
+    let frame = RpcFrame {
+        header: VarHeader {
+            key: VarKey::Key8(PingEndpoint::REQ_KEY),
+            seq_no: seen[3],
+        },
+        body: postcard::to_stdvec(&5u32).unwrap(),
+    };
+    let res = tokio::time::timeout(
+        Duration::from_millis(50),
+        cli.send_resp_raw(frame, PingEndpoint::RESP_KEY),
+    )
+    .await;
+    assert!(matches!(res, Ok(Err(HostErr::DuplicateSeq))));
+
+    // ...until one of them is finished
+    srv.reply(seen[17], 17).await;
+    let hdr = srv.recv().await;
+    assert_eq!(hdr.seq_no, seen[17]);
+}
+
+#[tokio::test]
+async fn duplicate_raw_seq() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+
+    let frame = || RpcFrame {
+        header: VarHeader {
+            key: VarKey::Key8(PingEndpoint::REQ_KEY),
+            seq_no: VarSeq::Seq2(7),
+        },
+        body: postcard::to_stdvec(&5u32).unwrap(),
+    };
+
+    let first = tokio::task::spawn({
+        let cli = cli.clone();
+        async move { cli.send_resp_raw(frame(), PingEndpoint::RESP_KEY).await }
+    });
+    let req = srv.recv_from_client().await.unwrap();
+
+    // The same number can't be used while the first request is in flight
+    let res = cli.send_resp_raw(frame(), PingEndpoint::RESP_KEY).await;
+    assert!(matches!(res, Err(HostErr::DuplicateSeq)));
+
+    // But it can be once it is done
+    srv.reply::<PingEndpoint>(req.header.seq_no.into(), &5)
+        .await
+        .unwrap();
+    assert!(first.await.unwrap().is_ok());
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        srv.reply::<PingEndpoint>(req.header.seq_no.into(), &6)
+            .await
+            .unwrap();
+    };
+    let (res, ()) = tokio::join!(cli.send_resp_raw(frame(), PingEndpoint::RESP_KEY), srv_fut);
+    assert!(res.is_ok());
+}
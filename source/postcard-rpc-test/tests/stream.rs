@@ -0,0 +1,189 @@
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::VarHeader,
+    host_client::HostErr,
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{spawn_fn, WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn, ChannelWireTx,
+        },
+        SpawnContext, StreamSink,
+    },
+    standard_icd::WireError,
+    topics,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Sample {
+    pub idx: u32,
+    pub value: i16,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum SampleError {
+    TooMany,
+}
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy            | RequestTy | ResponseTy        | ErrorTy       | Path                  |
+    | ----------            | --------- | ----------        | -------       | ----                  |
+    | SampleEndpoint        | u32       | Sample stream     | -             | "sample/stream"       |
+    | CheckedSampleEndpoint | u32       | Sample stream     | SampleError   | "sample/checked"      |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+pub struct TestContext;
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy            | kind      | handler           |
+        | ----------            | ----      | -------           |
+        | SampleEndpoint        | stream    | sample_handler    |
+        | CheckedSampleEndpoint | stream    | checked_handler   |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+async fn sample_handler(
+    _context: (),
+    _header: VarHeader,
+    count: u32,
+    sink: StreamSink<SampleEndpoint, ChannelWireTx>,
+) {
+    for idx in 0..count {
+        let value = idx as i16 * 10;
+        sink.send(&Sample { idx, value }).await.unwrap();
+    }
+    sink.end().await.unwrap();
+}
+
+async fn checked_handler(
+    _context: (),
+    _header: VarHeader,
+    count: u32,
+    sink: StreamSink<CheckedSampleEndpoint, ChannelWireTx>,
+) {
+    for idx in 0..count.min(3) {
+        sink.send(&Ok(Sample { idx, value: 0 })).await.unwrap();
+    }
+    if count > 3 {
+        sink.send(&Err(SampleError::TooMany)).await.unwrap();
+    }
+    sink.end().await.unwrap();
+}
+
+fn setup() -> postcard_rpc::host_client::HostClient<WireError> {
+    let app = SingleDispatcher::new(TestContext, ChannelWireSpawn {});
+    let (_sender, cli) = spawn_channel_server(app, 1024);
+    cli
+}
+
+#[tokio::test]
+async fn stream_items_then_end() {
+    let cli = setup();
+
+    let mut stream = cli.send_stream::<SampleEndpoint>(&5).await.unwrap();
+    for idx in 0..5 {
+        let item = stream.recv().await.unwrap().unwrap();
+        assert_eq!(
+            item,
+            Sample {
+                idx,
+                value: idx as i16 * 10
+            }
+        );
+    }
+    assert!(stream.recv().await.is_none());
+    assert!(stream.is_done());
+    assert!(stream.recv().await.is_none());
+
+    // An empty stream ends right away
+    let mut stream = cli.send_stream::<SampleEndpoint>(&0).await.unwrap();
+    assert!(stream.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn concurrent_streams() {
+    let cli = setup();
+
+    let mut a = cli.send_stream::<SampleEndpoint>(&3).await.unwrap();
+    let mut b = cli.send_stream::<SampleEndpoint>(&4).await.unwrap();
+
+    let mut a_items = vec![];
+    while let Some(item) = a.recv().await {
+        a_items.push(item.unwrap().idx);
+    }
+    let mut b_items = vec![];
+    while let Some(item) = b.recv().await {
+        b_items.push(item.unwrap().idx);
+    }
+    assert_eq!(a_items, [0, 1, 2]);
+    assert_eq!(b_items, [0, 1, 2, 3]);
+}
+
+#[tokio::test]
+async fn stream_with_typed_errors() {
+    let cli = setup();
+
+    let mut stream = cli.send_stream::<CheckedSampleEndpoint>(&10).await.unwrap();
+    for idx in 0..3 {
+        let item = stream.recv().await.unwrap().unwrap();
+        assert_eq!(item, Ok(Sample { idx, value: 0 }));
+    }
+    let item = stream.recv().await.unwrap().unwrap();
+    assert_eq!(item, Err(SampleError::TooMany));
+    assert!(stream.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn stream_closed() {
+    let cli = setup();
+
+    let mut stream = cli.send_stream::<SampleEndpoint>(&1).await.unwrap();
+    assert!(stream.recv().await.unwrap().is_ok());
+    assert!(stream.recv().await.is_none());
+
+    cli.close();
+    let res = cli.send_stream::<SampleEndpoint>(&1).await;
+    assert!(matches!(res, Err(HostErr::Closed)));
+}
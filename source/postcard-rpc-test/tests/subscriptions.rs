@@ -0,0 +1,293 @@
+use core::time::Duration;
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::{VarSeq, VarSeqKind},
+    host_client::{
+        test_channels as client, ConnectionState, HostClient, HostClientConfig, HostErr,
+    },
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        protocol::{protocol_negotiation, ProtocolContext, ProtocolState},
+        subscriptions::{topic_subscription, SubscriptionContext, TopicFilter, TopicRegistry},
+        Sender, SpawnContext,
+    },
+    standard_icd::{
+        ProtocolEndpoint, ProtocolInfo, TopicSubscriptionEndpoint, TopicSubscriptionError,
+        TopicSubscriptionRequest, WireError, ERROR_PATH, STANDARD_ICD_PROTOCOL_ENDPOINTS,
+        STANDARD_ICD_SUBSCRIPTION_ENDPOINTS,
+    },
+    topics, Key, Topic,
+};
+use postcard_rpc_test::{spawn_server_on_channels, ServerHandle};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Accel(pub u32);
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    include = [STANDARD_ICD_PROTOCOL_ENDPOINTS, STANDARD_ICD_SUBSCRIPTION_ENDPOINTS];
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+    | AccelTopic        | Accel         | "accel"           |
+    | GyroTopic         | Accel         | "gyro"            |
+}
+
+pub struct TestContext {
+    protocol: &'static ProtocolState,
+    topics: &'static TopicRegistry<1>,
+}
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+impl ProtocolContext for TestContext {
+    fn protocol(&self) -> &ProtocolState {
+        self.protocol
+    }
+}
+
+impl SubscriptionContext for TestContext {
+    fn topic_filter(&self) -> &dyn TopicFilter {
+        self.topics
+    }
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy                | kind      | handler               |
+        | ----------                | ----      | -------               |
+        | ProtocolEndpoint          | blocking  | protocol_negotiation  |
+        | TopicSubscriptionEndpoint | blocking  | topic_subscription    |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+/// Run a server with a fresh registry, stopped by aborting it
+fn spawn_server(
+    server_tx: mpsc::Sender<Vec<u8>>,
+    server_rx: mpsc::Receiver<Vec<u8>>,
+) -> (&'static TopicRegistry<1>, Sender<WireTxImpl>, ServerHandle) {
+    // Each server gets its own state
+    let protocol: &'static ProtocolState = Box::leak(Box::new(ProtocolState::new(
+        ProtocolInfo::CAP_TOPIC_SUBSCRIPTION,
+    )));
+    let topics: &'static TopicRegistry<1> = Box::leak(Box::new(TopicRegistry::new()));
+    let app = SingleDispatcher::new(TestContext { protocol, topics }, ChannelWireSpawn {});
+    let (sender, handle) = spawn_server_on_channels(app, 128, server_tx, server_rx, |server| {
+        server.set_protocol(protocol);
+        server.set_topic_filter(topics);
+    });
+    (topics, sender, handle)
+}
+
+/// Connect a client, which has not negotiated the protocol yet
+fn setup_unnegotiated() -> (
+    &'static TopicRegistry<1>,
+    Sender<WireTxImpl>,
+    HostClient<WireError>,
+) {
+    let (client_tx, server_rx) = mpsc::channel(16);
+    let (server_tx, client_rx) = mpsc::channel(16);
+    let (topics, sender, _handle) = spawn_server(server_tx, server_rx);
+
+    (
+        topics,
+        sender,
+        client::new_from_channels(client_tx, client_rx, VarSeqKind::Seq1),
+    )
+}
+
+async fn setup() -> (
+    &'static TopicRegistry<1>,
+    Sender<WireTxImpl>,
+    HostClient<WireError>,
+) {
+    let (topics, sender, cli) = setup_unnegotiated();
+    cli.negotiate_protocol().await.unwrap();
+    (topics, sender, cli)
+}
+
+/// Wait until the server has (or has not) subscribed to `key`
+async fn wait_subscribed(topics: &TopicRegistry<1>, key: Key, subscribed: bool) {
+    while topics.is_subscribed(key) != subscribed {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn only_subscribed_topics_are_published() {
+    let (topics, sender, cli) = setup().await;
+    assert!(!sender.is_subscribed(AccelTopic::TOPIC_KEY));
+
+    // Subscribing tells the server
+    let mut sub = cli.subscribe_multi::<AccelTopic>(8).await.unwrap();
+    assert!(topics.is_subscribed(AccelTopic::TOPIC_KEY));
+    assert!(sender.is_subscribed(AccelTopic::TOPIC_KEY));
+
+    // Messages of other topics are not sent
+    sender
+        .publish::<GyroTopic>(VarSeq::Seq4(0), &Accel(0))
+        .await
+        .unwrap();
+    sender
+        .publish::<AccelTopic>(VarSeq::Seq4(1), &Accel(1))
+        .await
+        .unwrap();
+    assert_eq!(sub.recv().await.unwrap(), Accel(1));
+
+    // Unsubscribing tells the server, and ends the subscription
+    cli.unsubscribe(AccelTopic::TOPIC_KEY).await.unwrap();
+    assert!(!sender.is_subscribed(AccelTopic::TOPIC_KEY));
+    assert!(sub.recv().await.is_err());
+}
+
+#[tokio::test]
+async fn subscriptions_are_announced_once_negotiated() {
+    let (topics, sender, cli) = setup_unnegotiated();
+
+    // Without an agreement, the server is not told, and publishes everything
+    let mut sub = cli.subscribe_multi::<AccelTopic>(8).await.unwrap();
+    assert!(!topics.is_subscribed(AccelTopic::TOPIC_KEY));
+
+    // Negotiating tells the server about the existing subscription
+    cli.negotiate_protocol().await.unwrap();
+    assert!(topics.is_subscribed(AccelTopic::TOPIC_KEY));
+    sender
+        .publish::<AccelTopic>(VarSeq::Seq4(1), &Accel(1))
+        .await
+        .unwrap();
+    assert_eq!(sub.recv().await.unwrap(), Accel(1));
+}
+
+#[tokio::test]
+async fn too_many_topics() {
+    let (_topics, _sender, cli) = setup().await;
+    let _sub = cli.subscribe_exclusive::<AccelTopic>(8).await.unwrap();
+
+    let req = TopicSubscriptionRequest::Subscribe(GyroTopic::TOPIC_KEY);
+    let res = cli
+        .send_resp_fallible::<TopicSubscriptionEndpoint>(&req)
+        .await;
+    assert_eq!(
+        res,
+        Err(HostErr::Endpoint(TopicSubscriptionError::TooManyTopics))
+    );
+
+    // Subscribing again is fine
+    let req = TopicSubscriptionRequest::Subscribe(AccelTopic::TOPIC_KEY);
+    let res = cli
+        .send_resp_fallible::<TopicSubscriptionEndpoint>(&req)
+        .await;
+    assert_eq!(res, Ok(()));
+}
+
+#[tokio::test]
+async fn dropping_the_last_subscription_unsubscribes() {
+    let (topics, _sender, cli) = setup().await;
+    let sub1 = cli.subscribe_multi::<AccelTopic>(8).await.unwrap();
+    let sub2 = cli.subscribe_multi::<AccelTopic>(8).await.unwrap();
+    assert!(topics.is_subscribed(AccelTopic::TOPIC_KEY));
+
+    // Another subscriber is left
+    drop(sub1);
+    let req = TopicSubscriptionRequest::Subscribe(GyroTopic::TOPIC_KEY);
+    let res = cli
+        .send_resp_fallible::<TopicSubscriptionEndpoint>(&req)
+        .await;
+    assert_eq!(
+        res,
+        Err(HostErr::Endpoint(TopicSubscriptionError::TooManyTopics))
+    );
+    assert!(topics.is_subscribed(AccelTopic::TOPIC_KEY));
+
+    drop(sub2);
+    wait_subscribed(topics, AccelTopic::TOPIC_KEY, false).await;
+}
+
+#[tokio::test]
+async fn subscriptions_are_announced_on_reconnect() {
+    let mut config = HostClientConfig::new(VarSeqKind::Seq1, ERROR_PATH, 64);
+    config.negotiate_protocol = true;
+    let (servers_tx, mut servers) = mpsc::channel(4);
+    let cli = client::new_reconnecting_from_channels(
+        move || {
+            let servers_tx = servers_tx.clone();
+            async move {
+                let (client_tx, server_rx) = mpsc::channel(16);
+                let (server_tx, client_rx) = mpsc::channel(16);
+                servers_tx
+                    .send(spawn_server(server_tx, server_rx))
+                    .await
+                    .unwrap();
+                (client_tx, client_rx)
+            }
+        },
+        &config,
+    );
+    let mut state = cli.connection_state();
+
+    // Not keeping the sender, so aborting the server closes the connection
+    let (topics, _, handle) = servers.recv().await.unwrap();
+    state
+        .wait_for(|s| *s == ConnectionState::Connected)
+        .await
+        .unwrap();
+    let mut sub = cli.subscribe_exclusive::<AccelTopic>(8).await.unwrap();
+    // Announced once the protocol was negotiated
+    wait_subscribed(topics, AccelTopic::TOPIC_KEY, true).await;
+
+    // The device restarts, and forgets about the subscription
+    handle.abort();
+    let (topics, sender, _handle) = servers.recv().await.unwrap();
+    wait_subscribed(topics, AccelTopic::TOPIC_KEY, true).await;
+    sender
+        .publish::<AccelTopic>(VarSeq::Seq4(1), &Accel(1))
+        .await
+        .unwrap();
+    assert_eq!(sub.recv().await, Some(Accel(1)));
+
+    drop(sub);
+    wait_subscribed(topics, AccelTopic::TOPIC_KEY, false).await;
+    cli.close();
+}
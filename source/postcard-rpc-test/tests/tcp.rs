@@ -0,0 +1,150 @@
+use core::time::Duration;
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpListener, time::timeout};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::{VarHeader, VarSeqKind},
+    host_client::HostClient,
+    server::{
+        impls::tcp::{
+            dispatch_impl::{new_server, spawn_fn, Settings, WireSpawnImpl, WireTxImpl},
+            split, TcpWireSpawn, TcpWireTx,
+        },
+        Dispatch, Sender, SpawnContext,
+    },
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    topics,
+};
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct AReq(pub u8);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct AResp(pub u8);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct BReq(pub Vec<u8>);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct BResp(pub u32);
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+    | AlphaEndpoint     | AReq          | AResp         | "alpha"           |
+    | BetaEndpoint      | BReq          | BResp         | "beta"            |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+pub struct TestContext;
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = TestContext;
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {
+        TestContext
+    }
+}
+
+define_dispatch! {
+    app: TcpDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: crate::ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler                   |
+        | ----------        | ----      | -------                   |
+        | AlphaEndpoint     | blocking  | test_alpha_handler        |
+        | BetaEndpoint      | spawn     | test_beta_handler         |
+    };
+    topics_in: {
+        list: crate::TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler               |
+        | ----------        | ----      | -------               |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn test_alpha_handler(_context: &mut TestContext, _header: VarHeader, body: AReq) -> AResp {
+    AResp(body.0)
+}
+
+async fn test_beta_handler(
+    _context: TestContext,
+    header: VarHeader,
+    body: BReq,
+    out: Sender<TcpWireTx>,
+) {
+    let sum = body.0.iter().map(|b| u32::from(*b)).sum();
+    let _ = out.reply::<BetaEndpoint>(header.seq_no, &BResp(sum)).await;
+}
+
+#[tokio::test]
+async fn end_to_end_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (tx, rx) = split(stream);
+        let app = TcpDispatcher::new(TestContext, TcpWireSpawn);
+        let kkind = app.min_key_len();
+        let mut server = new_server(
+            app,
+            Settings {
+                tx,
+                rx,
+                buf: 1024,
+                kkind,
+            },
+        );
+        server.run().await;
+    });
+
+    let cli = HostClient::<WireError>::try_new_tcp(addr, ERROR_PATH, 8, VarSeqKind::Seq2).unwrap();
+
+    let resp = cli.send_resp::<PingEndpoint>(&42).await.unwrap();
+    assert_eq!(resp, 42);
+    let resp = cli.send_resp::<AlphaEndpoint>(&AReq(42)).await.unwrap();
+    assert_eq!(resp.0, 42);
+
+    // Contains plenty of zeroes, which must survive COBS framing
+    let resp = cli
+        .send_resp::<BetaEndpoint>(&BReq(vec![0, 1, 0, 2, 0, 3]))
+        .await
+        .unwrap();
+    assert_eq!(resp.0, 6);
+
+    // Too large for the server's receive buffer, this is discarded without a reply
+    let resp = timeout(
+        Duration::from_millis(100),
+        cli.send_resp::<BetaEndpoint>(&BReq(vec![1; 2048])),
+    )
+    .await;
+    assert!(resp.is_err());
+
+    // But the connection is still usable afterwards
+    let resp = cli.send_resp::<PingEndpoint>(&43).await.unwrap();
+    assert_eq!(resp, 43);
+}
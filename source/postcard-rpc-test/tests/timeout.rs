@@ -0,0 +1,72 @@
+use core::time::Duration;
+
+use postcard_rpc::{
+    header::{VarHeader, VarKey, VarSeq},
+    host_client::{HostErr, RpcFrame},
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    test_utils::local_setup,
+    Endpoint,
+};
+
+#[tokio::test]
+async fn send_resp_timeout() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+
+    // Nobody answers, so we time out
+    let resp = cli
+        .send_resp_timeout::<PingEndpoint>(&42, Duration::from_millis(50))
+        .await;
+    assert_eq!(resp, Err(HostErr::Timeout));
+    let req = srv.recv_from_client().await.unwrap();
+
+    // A late reply to the timed out request is discarded...
+    let seq_no: u32 = req.header.seq_no.into();
+    srv.reply::<PingEndpoint>(seq_no, &42).await.unwrap();
+
+    // ...and doesn't get mixed up with the next request
+    let fut = cli.send_resp_timeout::<PingEndpoint>(&43, Duration::from_secs(1));
+    let srv_fut = async {
+        let req = srv.recv_from_client().await.unwrap();
+        let seq_no: u32 = req.header.seq_no.into();
+        srv.reply::<PingEndpoint>(seq_no, &43).await.unwrap();
+    };
+    let (resp, ()) = tokio::join!(fut, srv_fut);
+    assert_eq!(resp, Ok(43));
+}
+
+#[tokio::test]
+async fn timeout_releases_waiters() {
+    let (mut srv, cli) = local_setup::<WireError>(8, ERROR_PATH);
+
+    let frame = || RpcFrame {
+        header: VarHeader {
+            key: VarKey::Key8(PingEndpoint::REQ_KEY),
+            seq_no: VarSeq::Seq4(1234),
+        },
+        body: postcard::to_stdvec(&5u32).unwrap(),
+    };
+
+    // If the waiters for the first request were leaked, reusing the same
+    // sequence number would be reported as a duplicate
+    for _ in 0..2 {
+        let resp = cli
+            .send_resp_raw_timeout(frame(), PingEndpoint::RESP_KEY, Duration::from_millis(20))
+            .await;
+        assert!(matches!(resp, Err(HostErr::Timeout)));
+        srv.recv_from_client().await.unwrap();
+    }
+
+    // The same goes for requests that are cancelled by dropping the future
+    let cancelled = tokio::time::timeout(
+        Duration::from_millis(20),
+        cli.send_resp_raw(frame(), PingEndpoint::RESP_KEY),
+    )
+    .await;
+    assert!(cancelled.is_err());
+    srv.recv_from_client().await.unwrap();
+
+    let resp = cli
+        .send_resp_raw_timeout(frame(), PingEndpoint::RESP_KEY, Duration::from_millis(20))
+        .await;
+    assert!(matches!(resp, Err(HostErr::Timeout)));
+}
@@ -0,0 +1,199 @@
+use core::time::Duration;
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, time::timeout};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::{VarHeader, VarKey, VarSeq, VarSeqKind},
+    host_client::{HostClient, HostErr, PublishError, RpcFrame},
+    server::{
+        impls::udp::{
+            dispatch_impl::{new_server, spawn_fn, Settings, WireSpawnImpl, WireTxImpl},
+            split, UdpWireSpawn, UdpWireTx,
+        },
+        Dispatch, Sender, SpawnContext,
+    },
+    standard_icd::{PingEndpoint, WireError, ERROR_PATH},
+    topics, Key,
+};
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct AReq(pub u8);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct AResp(pub u8);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct BReq(pub Vec<u8>);
+#[derive(Serialize, Deserialize, Schema)]
+pub struct BResp(pub u32);
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy     | ResponseTy    | Path              |
+    | ----------        | ---------     | ----------    | ----              |
+    | AlphaEndpoint     | AReq          | AResp         | "alpha"           |
+    | BetaEndpoint      | BReq          | BResp         | "beta"            |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+pub struct TestContext;
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = TestContext;
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {
+        TestContext
+    }
+}
+
+define_dispatch! {
+    app: UdpDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: crate::ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler                   |
+        | ----------        | ----      | -------                   |
+        | AlphaEndpoint     | blocking  | test_alpha_handler        |
+        | BetaEndpoint      | spawn     | test_beta_handler         |
+    };
+    topics_in: {
+        list: crate::TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler               |
+        | ----------        | ----      | -------               |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+fn test_alpha_handler(_context: &mut TestContext, _header: VarHeader, body: AReq) -> AResp {
+    AResp(body.0)
+}
+
+async fn test_beta_handler(
+    _context: TestContext,
+    header: VarHeader,
+    body: BReq,
+    out: Sender<UdpWireTx>,
+) {
+    let sum = body.0.iter().map(|b| u32::from(*b)).sum();
+    let _ = out.reply::<BetaEndpoint>(header.seq_no, &BResp(sum)).await;
+}
+
+#[tokio::test]
+async fn end_to_end_udp() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    tokio::task::spawn(async move {
+        let (tx, rx) = split(socket);
+        let app = UdpDispatcher::new(TestContext, UdpWireSpawn);
+        let kkind = app.min_key_len();
+        let mut server = new_server(
+            app,
+            Settings {
+                tx,
+                rx,
+                buf: 1024,
+                kkind,
+            },
+        );
+        server.run().await;
+    });
+
+    let cli = HostClient::<WireError>::try_new_udp(addr, ERROR_PATH, 8, VarSeqKind::Seq2).unwrap();
+
+    let resp = cli.send_resp::<PingEndpoint>(&42).await.unwrap();
+    assert_eq!(resp, 42);
+    let resp = cli.send_resp::<AlphaEndpoint>(&AReq(42)).await.unwrap();
+    assert_eq!(resp.0, 42);
+    let resp = cli
+        .send_resp::<BetaEndpoint>(&BReq(vec![0, 1, 0, 2, 0, 3]))
+        .await
+        .unwrap();
+    assert_eq!(resp.0, 6);
+
+    // Too large for the server's receive buffer, this is discarded without a reply
+    let resp = timeout(
+        Duration::from_millis(100),
+        cli.send_resp::<BetaEndpoint>(&BReq(vec![1; 2048])),
+    )
+    .await;
+    assert!(resp.is_err());
+
+    // But the server is still usable afterwards
+    let resp = cli.send_resp::<PingEndpoint>(&43).await.unwrap();
+    assert_eq!(resp, 43);
+
+    // Too large for a datagram, this fails without being sent
+    let resp = cli
+        .send_resp::<BetaEndpoint>(&BReq(vec![1; 70_000]))
+        .await;
+    assert!(matches!(resp, Err(HostErr::FrameTooLarge)));
+    let frame = RpcFrame {
+        header: VarHeader {
+            key: VarKey::Key8(Key::for_path::<BReq>("big")),
+            seq_no: VarSeq::Seq2(0),
+        },
+        body: vec![1; 70_000],
+    };
+    let res = cli.publish_raw(frame).await;
+    assert!(matches!(res, Err(PublishError::FrameTooLarge)));
+    let resp = cli.send_resp::<PingEndpoint>(&44).await.unwrap();
+    assert_eq!(resp, 44);
+}
+
+#[tokio::test]
+async fn udp_multiple_devices() {
+    let mut clients = vec![];
+    for _ in 0..2 {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::task::spawn(async move {
+            let (tx, rx) = split(socket);
+            let app = UdpDispatcher::new(TestContext, UdpWireSpawn);
+            let kkind = app.min_key_len();
+            let mut server = new_server(
+                app,
+                Settings {
+                    tx,
+                    rx,
+                    buf: 1024,
+                    kkind,
+                },
+            );
+            server.run().await;
+        });
+        clients.push(
+            HostClient::<WireError>::try_new_udp(addr, ERROR_PATH, 8, VarSeqKind::Seq1).unwrap(),
+        );
+    }
+
+    for (i, cli) in clients.iter().enumerate() {
+        let resp = cli
+            .send_resp::<AlphaEndpoint>(&AReq(i as u8))
+            .await
+            .unwrap();
+        assert_eq!(resp.0, i as u8);
+    }
+}
@@ -0,0 +1,198 @@
+use core::{num::NonZeroUsize, time::Duration};
+
+use postcard_schema::Schema;
+use serde::{Deserialize, Serialize};
+
+use postcard_rpc::{
+    define_dispatch, endpoints,
+    header::VarHeader,
+    host_client::HostErr,
+    server::{
+        impls::test_channels::{
+            dispatch_impl::{WireSpawnImpl, WireTxImpl},
+            ChannelWireSpawn,
+        },
+        SpawnContext,
+    },
+    standard_icd::{UploadChunk, WireError},
+    topics,
+};
+use postcard_rpc_test::spawn_channel_server;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Summary {
+    pub len: u32,
+    pub sum: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum FlashError {
+    Full,
+}
+
+pub type Bytes = Vec<u8>;
+
+endpoints! {
+    list = ENDPOINT_LIST;
+    | EndpointTy        | RequestTy         | ResponseTy        | ErrorTy       | Path              |
+    | ----------        | ---------         | ----------        | -------       | ----              |
+    | SumEndpoint       | UploadChunk<'a>   | Summary upload    | -             | "upload/sum"      |
+    | FlashEndpoint     | UploadChunk<'a>   | u32 upload        | FlashError    | "upload/flash"    |
+    | PlainEndpoint     | Bytes             | u32               | -             | "upload/plain"    |
+}
+
+topics! {
+    list = TOPICS_IN_LIST;
+    direction = postcard_rpc::TopicDirection::ToServer;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+topics! {
+    list = TOPICS_OUT_LIST;
+    direction = postcard_rpc::TopicDirection::ToClient;
+    | TopicTy           | MessageTy     | Path              |
+    | ----------        | ---------     | ----              |
+}
+
+#[derive(Default)]
+pub struct TestContext {
+    sum: u32,
+    flash: Vec<u8>,
+}
+
+impl SpawnContext for TestContext {
+    type SpawnCtxt = ();
+
+    fn spawn_ctxt(&mut self) -> Self::SpawnCtxt {}
+}
+
+define_dispatch! {
+    app: SingleDispatcher;
+    spawn_fn: spawn_fn;
+    tx_impl: WireTxImpl;
+    spawn_impl: WireSpawnImpl;
+    context: TestContext;
+
+    endpoints: {
+        list: ENDPOINT_LIST;
+
+        | EndpointTy        | kind      | handler           |
+        | ----------        | ----      | -------           |
+        | SumEndpoint       | upload    | sum_handler       |
+        | FlashEndpoint     | upload    | flash_handler     |
+        | PlainEndpoint     | blocking  | plain_handler     |
+    };
+    topics_in: {
+        list: TOPICS_IN_LIST;
+
+        | TopicTy           | kind      | handler           |
+        | ----------        | ----      | -------           |
+    };
+    topics_out: {
+        list: TOPICS_OUT_LIST;
+    };
+}
+
+async fn sum_handler(
+    context: &mut TestContext,
+    _header: VarHeader,
+    chunk: UploadChunk<'_>,
+) -> Option<Summary> {
+    if chunk.offset == 0 {
+        context.sum = 0;
+    }
+    context.sum += chunk.data.iter().map(|b| *b as u32).sum::<u32>();
+    let end = chunk.offset as usize + chunk.data.len();
+    (end == chunk.total_len as usize).then_some(Summary {
+        len: chunk.total_len,
+        sum: context.sum,
+    })
+}
+
+async fn flash_handler(
+    context: &mut TestContext,
+    _header: VarHeader,
+    chunk: UploadChunk<'_>,
+) -> Option<Result<u32, FlashError>> {
+    if chunk.offset == 0 {
+        context.flash.clear();
+    }
+    // Pretend the flash only holds 64 bytes
+    if context.flash.len() + chunk.data.len() > 64 {
+        return Some(Err(FlashError::Full));
+    }
+    context.flash.extend_from_slice(chunk.data);
+    (context.flash.len() == chunk.total_len as usize).then_some(Ok(context.flash.len() as u32))
+}
+
+fn plain_handler(_context: &mut TestContext, _header: VarHeader, body: Vec<u8>) -> u32 {
+    body.len() as u32
+}
+
+const SUM_CHUNK_SIZE: NonZeroUsize = NonZeroUsize::new(32).unwrap();
+const FLASH_CHUNK_SIZE: NonZeroUsize = NonZeroUsize::new(16).unwrap();
+
+fn setup() -> postcard_rpc::host_client::HostClient<WireError> {
+    let app = SingleDispatcher::new(TestContext::default(), ChannelWireSpawn {});
+    // Much smaller than the uploads
+    let (_sender, cli) = spawn_channel_server(app, 64);
+    cli
+}
+
+#[tokio::test]
+async fn upload_larger_than_buf() {
+    let cli = setup();
+    let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let sum = data.iter().map(|b| *b as u32).sum::<u32>();
+
+    // A single frame does not fit, and is dropped by the server
+    let res = cli
+        .send_resp_timeout::<PlainEndpoint>(&data[..200].to_vec(), Duration::from_millis(100))
+        .await;
+    assert!(matches!(res, Err(HostErr::Timeout)));
+
+    let mut seen = vec![];
+    let resp = cli
+        .send_upload_with_progress::<SumEndpoint>(&data, SUM_CHUNK_SIZE, |done, total| {
+            seen.push((done, total))
+        })
+        .await
+        .unwrap();
+    assert_eq!(resp, Summary { len: 1000, sum });
+    assert_eq!(seen.len(), 32);
+    assert_eq!(seen[0], (32, 1000));
+    assert_eq!(seen.last(), Some(&(1000, 1000)));
+
+    // Uploads can be repeated, and may be empty
+    let resp = cli
+        .send_upload::<SumEndpoint>(&data[..10], SUM_CHUNK_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(resp.sum, (0..10).sum::<u32>());
+    let resp = cli
+        .send_upload::<SumEndpoint>(&[], SUM_CHUNK_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(resp, Summary { len: 0, sum: 0 });
+}
+
+#[tokio::test]
+async fn upload_stops_on_error() {
+    let cli = setup();
+
+    let resp = cli
+        .send_upload::<FlashEndpoint>(&[1; 40], FLASH_CHUNK_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(resp, Ok(40));
+
+    let mut calls = 0;
+    let resp = cli
+        .send_upload_with_progress::<FlashEndpoint>(&[1; 200], FLASH_CHUNK_SIZE, |_, _| calls += 1)
+        .await
+        .unwrap();
+    assert_eq!(resp, Err(FlashError::Full));
+    // Four chunks were acknowledged, then the fifth one failed
+    assert_eq!(calls, 5);
+}
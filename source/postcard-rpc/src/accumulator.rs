@@ -37,6 +37,12 @@ pub mod raw {
         },
     }
 
+    impl<const N: usize> Default for CobsAccumulator<N> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl<const N: usize> CobsAccumulator<N> {
         /// Create a new accumulator.
         pub const fn new() -> Self {
@@ -0,0 +1,224 @@
+//! A command line tool for exploring postcard-rpc devices
+//!
+//! Works with any device that implements the standard ICD, without needing
+//! the device's ICD crate. Messages are read and printed as JSON, see
+//! [`postcard_rpc::host_client::dynamic`] for how types are represented.
+
+use std::process::ExitCode;
+
+use postcard_rpc::{
+    header::VarSeqKind,
+    host_client::{
+        dynamic::{from_postcard, DynamicErr, Value},
+        HostClient, HostErr, MultiSubRxError, SchemaReport,
+    },
+    standard_icd::{WireError, ERROR_PATH},
+};
+
+const USAGE: &str = "\
+Usage: postcard-rpc-cli <CONNECTION> <COMMAND>
+
+Connections:
+    --usb <VID:PID>         Connect to a raw USB device, VID and PID in hex
+    --serial <PATH>         Connect to a COBS serial port
+    --baud <BAUD>           Baud rate of the serial port [default: 115200]
+
+Commands:
+    schema                  Print the schema reported by the device
+    call <PATH> <JSON>      Call the endpoint PATH, with the JSON request
+    subscribe <PATH> [N]    Print messages of the server to client topic PATH,
+                            stopping after N messages if given
+";
+
+enum Connection {
+    Usb { vid: u16, pid: u16 },
+    Serial { path: String, baud: u32 },
+}
+
+enum Command {
+    Schema,
+    Call { path: String, req: Value },
+    Subscribe { path: String, count: Option<usize> },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let res = match parse_args(std::env::args().skip(1)) {
+        Ok((conn, cmd)) => run(conn, cmd).await,
+        Err(e) => Err(format!("{e}\n\n{USAGE}")),
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Connection, Command), String> {
+    let mut usb = None;
+    let mut serial = None;
+    let mut baud = 115200;
+    let mut rest = vec![];
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--usb" => {
+                let v = value()?;
+                let ids = v
+                    .split_once(':')
+                    .and_then(|(vid, pid)| {
+                        let vid = u16::from_str_radix(vid.trim_start_matches("0x"), 16).ok()?;
+                        let pid = u16::from_str_radix(pid.trim_start_matches("0x"), 16).ok()?;
+                        Some((vid, pid))
+                    })
+                    .ok_or(format!("invalid USB id '{v}'"))?;
+                usb = Some(ids);
+            }
+            "--serial" => serial = Some(value()?),
+            "--baud" => {
+                let v = value()?;
+                baud = v.parse().map_err(|_| format!("invalid baud rate '{v}'"))?;
+            }
+            "-h" | "--help" => return Err("help requested".into()),
+            _ => rest.push(arg),
+        }
+    }
+
+    let conn = match (usb, serial) {
+        (Some((vid, pid)), None) => Connection::Usb { vid, pid },
+        (None, Some(path)) => Connection::Serial { path, baud },
+        _ => return Err("exactly one of --usb or --serial is required".into()),
+    };
+
+    let cmd = match rest
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["schema"] => Command::Schema,
+        ["call", path, req] => Command::Call {
+            path: path.to_string(),
+            req: req
+                .parse()
+                .map_err(|e| format!("invalid JSON request: {e}"))?,
+        },
+        ["subscribe", path] => Command::Subscribe {
+            path: path.to_string(),
+            count: None,
+        },
+        ["subscribe", path, count] => Command::Subscribe {
+            path: path.to_string(),
+            count: Some(
+                count
+                    .parse()
+                    .map_err(|_| format!("invalid count '{count}'"))?,
+            ),
+        },
+        _ => return Err("missing or invalid command".into()),
+    };
+
+    Ok((conn, cmd))
+}
+
+async fn run(conn: Connection, cmd: Command) -> Result<(), String> {
+    let client: HostClient<WireError> = match conn {
+        Connection::Usb { vid, pid } => HostClient::try_new_raw_nusb(
+            |d| d.vendor_id() == vid && d.product_id() == pid,
+            ERROR_PATH,
+            8,
+            VarSeqKind::Seq4,
+        )?,
+        Connection::Serial { path, baud } => {
+            HostClient::try_new_serial_cobs(&path, ERROR_PATH, 8, baud, VarSeqKind::Seq4)?
+        }
+    };
+
+    let report = client
+        .get_schema_report()
+        .await
+        .map_err(|e| format!("failed to get the schema report: {e:?}"))?;
+
+    match cmd {
+        Command::Schema => print_schema(&report),
+        Command::Call { path, req } => {
+            let ep = report
+                .endpoints
+                .iter()
+                .find(|e| e.path == path)
+                .ok_or(format!("the device has no endpoint '{path}'"))?;
+            let resp = client
+                .send_resp_dynamic(ep, &req)
+                .await
+                .map_err(|e| match e {
+                    DynamicErr::Host(HostErr::Wire(e)) => format!("the device replied: {e:?}"),
+                    e => format!("{e:?}"),
+                })?;
+            println!("{resp}");
+        }
+        Command::Subscribe { path, count } => {
+            let tp = report
+                .topics_out
+                .iter()
+                .find(|t| t.path == path)
+                .ok_or(format!("the device has no topic '{path}'"))?;
+            let mut sub = client
+                .subscribe_multi_raw(tp.key, 64)
+                .await
+                .map_err(|_| "the connection was closed".to_string())?;
+            let mut seen = 0;
+            while count.is_none_or(|c| seen < c) {
+                let frame = match sub.recv().await {
+                    Ok(frame) => frame,
+                    Err(MultiSubRxError::Lagged(n)) => {
+                        eprintln!("missed {n} messages");
+                        continue;
+                    }
+                    Err(MultiSubRxError::IoClosed) => {
+                        return Err("the connection was closed".into())
+                    }
+                };
+                match from_postcard(&tp.ty, &frame.body) {
+                    Ok(msg) => println!("{msg}"),
+                    Err(e) => eprintln!("undecodable message: {e:?}"),
+                }
+                seen += 1;
+            }
+        }
+    }
+
+    client.close();
+    Ok(())
+}
+
+fn print_schema(report: &SchemaReport) {
+    println!("Endpoints:");
+    for ep in report.endpoints.iter() {
+        println!("    {}: {} -> {}", ep.path, ep.req_ty.name, ep.resp_ty.name);
+    }
+    println!("Topics (client to server):");
+    for tp in report.topics_in.iter() {
+        println!("    {}: {}", tp.path, tp.ty.name);
+    }
+    println!("Topics (server to client):");
+    for tp in report.topics_out.iter() {
+        println!("    {}: {}", tp.path, tp.ty.name);
+    }
+
+    // Primitives are always part of the report, skip them
+    let primitives = SchemaReport::default().types;
+    let mut types: Vec<_> = report
+        .types
+        .iter()
+        .filter(|t| !primitives.contains(t))
+        .map(|t| t.to_pseudocode())
+        .collect();
+    types.sort();
+    println!("Types:");
+    for ty in types {
+        println!("    {ty}");
+    }
+}
@@ -396,6 +396,46 @@ impl VarHeader {
     /// Mask bits
     pub const VER_MASK_BITS: u8 = 0b00_00_1111;
 
+    /// The protocol versions supported by this crate, bit N is set if version N is supported
+    ///
+    /// Only version zero is defined so far. Frames of other versions are rejected
+    /// when decoding, clients and servers agree on a common version using the
+    /// [`ProtocolEndpoint`][crate::standard_icd::ProtocolEndpoint].
+    pub const SUPPORTED_VERSIONS: u16 = 1 << 0;
+
+    /// Is the protocol version, as encoded in the version bits, supported by this crate?
+    pub const fn is_supported_version(version: u8) -> bool {
+        version <= Self::VER_MASK_BITS && (Self::SUPPORTED_VERSIONS & (1 << version)) != 0
+    }
+
+    /// Get the highest protocol version contained in both sets of versions
+    ///
+    /// Each set has bit N set if version N is supported, see [`Self::SUPPORTED_VERSIONS`].
+    pub const fn highest_common_version(ours: u16, theirs: u16) -> Option<u8> {
+        let common = ours & theirs;
+        if common == 0 {
+            None
+        } else {
+            Some((u16::BITS - 1 - common.leading_zeros()) as u8)
+        }
+    }
+
+    /// Get the protocol version of an encoded frame, from the version bits of its discriminant
+    pub fn version_of(frame: &[u8]) -> Option<u8> {
+        frame.first().map(|disc| disc & Self::VER_MASK_BITS)
+    }
+
+    /// Set the protocol version of an encoded frame, e.g. to the version agreed with
+    /// the [`ProtocolEndpoint`][crate::standard_icd::ProtocolEndpoint]
+    ///
+    /// The header is encoded with version zero, so frames can be written as usual
+    /// and stamped with the agreed version before they are sent.
+    pub fn set_version(frame: &mut [u8], version: u8) {
+        if let Some(disc) = frame.first_mut() {
+            *disc = (*disc & !Self::VER_MASK_BITS) | (version & Self::VER_MASK_BITS);
+        }
+    }
+
     /// Encode the header to a Vec of bytes
     #[cfg(feature = "use-std")]
     pub fn write_to_vec(&self) -> Vec<u8> {
@@ -514,8 +554,7 @@ impl VarHeader {
     pub fn take_from_slice(buf: &[u8]) -> Option<(Self, &[u8])> {
         let (disc, mut remain) = buf.split_first()?;
 
-        // For now, we only trust version zero
-        if (*disc & Self::VER_MASK_BITS) != Self::VER_ZERO_BITS {
+        if !Self::is_supported_version(*disc & Self::VER_MASK_BITS) {
             return None;
         }
 
@@ -684,4 +723,31 @@ mod test {
         assert_ne!(VarSeq::Seq4(val32), VarSeq::Seq2(val32 as u16));
         assert_eq!(VarSeq::Seq4(val32), VarSeq::Seq4(val32));
     }
+
+    #[test]
+    fn versions() {
+        assert!(VarHeader::is_supported_version(0));
+        assert!(!VarHeader::is_supported_version(1));
+        assert!(!VarHeader::is_supported_version(0xFF));
+
+        assert_eq!(VarHeader::highest_common_version(0b0001, 0b0001), Some(0));
+        assert_eq!(VarHeader::highest_common_version(0b0111, 0b1110), Some(2));
+        assert_eq!(VarHeader::highest_common_version(0b0001, 0b0110), None);
+        assert_eq!(VarHeader::highest_common_version(0x8000, 0xFFFF), Some(15));
+
+        // Frames of unsupported versions are rejected
+        let mut frame = [VarHeader::KEY_ONE_BITS | VarHeader::SEQ_ONE_BITS, 1, 2];
+        assert!(VarHeader::take_from_slice(&frame).is_some());
+        frame[0] |= 0b0001;
+        assert!(VarHeader::take_from_slice(&frame).is_none());
+
+        // Stamping the agreed version only touches the version bits
+        assert_eq!(VarHeader::version_of(&frame), Some(1));
+        VarHeader::set_version(&mut frame, 0);
+        assert_eq!(VarHeader::version_of(&frame), Some(0));
+        assert_eq!(frame[0], VarHeader::KEY_ONE_BITS | VarHeader::SEQ_ONE_BITS);
+        VarHeader::set_version(&mut frame, 0xF5);
+        assert_eq!(VarHeader::version_of(&frame), Some(5));
+        assert_eq!(VarHeader::version_of(&[]), None);
+    }
 }
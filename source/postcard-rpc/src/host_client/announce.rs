@@ -0,0 +1,234 @@
+//! Telling the server which topics the client subscribed to
+//!
+//! See `postcard_rpc::server::subscriptions` for the server side. Only servers
+//! that agreed on [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`] when negotiating the
+//! protocol are told, all other servers keep publishing all topics.
+
+use core::{marker::PhantomData, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::{
+    select,
+    sync::{mpsc, watch, Mutex},
+};
+
+use crate::{
+    header::VarSeqKind,
+    host_client::{
+        sched::Priority,
+        util::{Stopper, Subscriptions},
+        ConnectionState, HostClient, HostContext, HostErr, RpcFrame,
+    },
+    standard_icd::{ProtocolInfo, TopicSubscriptionEndpoint, TopicSubscriptionRequest},
+    Key,
+};
+
+/// How long to wait for the server to acknowledge an announcement, if
+/// [`HostClientConfig::resp_timeout`][crate::host_client::HostClientConfig::resp_timeout]
+/// is not set
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Held by every subscription, tells the [`topic_worker`] when it is dropped
+pub(crate) struct TopicGuard {
+    key: Key,
+    release: mpsc::UnboundedSender<Key>,
+}
+
+impl Drop for TopicGuard {
+    fn drop(&mut self) {
+        // An error means the worker is gone, and there is nobody to tell
+        let _ = self.release.send(self.key);
+    }
+}
+
+/// A [`HostClient`] that does not keep the outgoing queue open
+pub(crate) struct WeakHostClient {
+    ctx: Arc<HostContext>,
+    out: mpsc::WeakSender<RpcFrame>,
+    topic_release: mpsc::WeakUnboundedSender<Key>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    err_key: Key,
+    stopper: Stopper,
+    seq_kind: VarSeqKind,
+    priorities: Arc<RwLock<HashMap<Key, Priority>>>,
+}
+
+impl WeakHostClient {
+    /// Get a client again, unless all clients were dropped
+    ///
+    /// Wire errors are only used to tell that the server does not serve the
+    /// subscription endpoint, so the client does not need to know their type.
+    fn upgrade(&self) -> Option<HostClient<()>> {
+        Some(HostClient {
+            ctx: self.ctx.clone(),
+            out: self.out.upgrade()?,
+            topic_release: self.topic_release.upgrade()?,
+            subscriptions: self.subscriptions.clone(),
+            err_key: self.err_key,
+            stopper: self.stopper.clone(),
+            seq_kind: self.seq_kind,
+            retries: Arc::new(RwLock::new(HashMap::new())),
+            priorities: self.priorities.clone(),
+            _pd: PhantomData,
+        })
+    }
+}
+
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Create a guard that tells the [`topic_worker`] when a subscription to `key` ends
+    pub(crate) fn topic_guard(&self, key: Key) -> TopicGuard {
+        TopicGuard {
+            key,
+            release: self.topic_release.clone(),
+        }
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakHostClient {
+        WeakHostClient {
+            ctx: self.ctx.clone(),
+            out: self.out.downgrade(),
+            topic_release: self.topic_release.downgrade(),
+            subscriptions: self.subscriptions.clone(),
+            err_key: self.err_key,
+            stopper: self.stopper.clone(),
+            seq_kind: self.seq_kind,
+            priorities: self.priorities.clone(),
+        }
+    }
+
+    /// Tell the server that we subscribed to or unsubscribed from a topic
+    pub(crate) async fn announce_topic(&self, req: TopicSubscriptionRequest) {
+        let _announcing = self.ctx.announce_lock.lock().await;
+        self.announce_topic_locked(req).await;
+    }
+
+    /// Like [`Self::announce_topic`], the caller must hold the announce lock
+    ///
+    /// The lock makes sure the server learns about changes to the same topic in
+    /// the order they were made.
+    async fn announce_topic_locked(&self, req: TopicSubscriptionRequest) {
+        // All topics are announced again by the topic worker once connected
+        if *self.ctx.state.borrow() != ConnectionState::Connected {
+            return;
+        }
+        // The server publishes all topics
+        if !self.agreed(ProtocolInfo::CAP_TOPIC_SUBSCRIPTION) {
+            return;
+        }
+        let timeout = self.ctx.resp_timeout.unwrap_or(ANNOUNCE_TIMEOUT);
+        match self
+            .send_resp_timeout::<TopicSubscriptionEndpoint>(&req, timeout)
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("The server rejected {req:?}: {e:?}"),
+            Err(HostErr::Timeout) => tracing::warn!("The server did not acknowledge {req:?}"),
+            // The client is closed, or the reply is malformed
+            Err(_) => {}
+        }
+    }
+
+    /// Unsubscribe from `key` if the last subscription to it was dropped
+    async fn release_topic(&self, key: Key) {
+        let _announcing = self.ctx.announce_lock.lock().await;
+        {
+            let mut guard = self.subscriptions.lock().await;
+            let live = guard
+                .exclusive_list
+                .iter()
+                .any(|(k, tx)| *k == key && !tx.is_closed())
+                || guard
+                    .broadcast_list
+                    .iter()
+                    .any(|(k, tx)| *k == key && tx.receiver_count() != 0);
+            if live {
+                return;
+            }
+            guard.exclusive_list.retain(|(k, _)| *k != key);
+            guard.broadcast_list.retain(|(k, _)| *k != key);
+        }
+        self.announce_topic_locked(TopicSubscriptionRequest::Unsubscribe(key))
+            .await;
+    }
+
+    /// Announce all topics that are currently subscribed, e.g. after negotiating the protocol
+    pub(crate) async fn reannounce_topics(&self) {
+        let _announcing = self.ctx.announce_lock.lock().await;
+        let mut keys = vec![];
+        {
+            let guard = self.subscriptions.lock().await;
+            let exclusive = guard
+                .exclusive_list
+                .iter()
+                .filter(|(_, tx)| !tx.is_closed())
+                .map(|(k, _)| *k);
+            let broadcast = guard
+                .broadcast_list
+                .iter()
+                .filter(|(_, tx)| tx.receiver_count() != 0)
+                .map(|(k, _)| *k);
+            for key in exclusive.chain(broadcast) {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        for key in keys {
+            self.announce_topic_locked(TopicSubscriptionRequest::Subscribe(key))
+                .await;
+        }
+    }
+}
+
+/// Topic worker, announcing subscriptions that ended, and negotiating the
+/// protocol whenever the client (re)connects, if configured to
+///
+/// Runs until the client is closed, or all clients and subscriptions were dropped.
+pub(crate) async fn topic_worker(
+    client: WeakHostClient,
+    mut released: mpsc::UnboundedReceiver<Key>,
+    mut state: watch::Receiver<ConnectionState>,
+) {
+    let stopper = client.stopper.clone();
+    let operate_fut = async move {
+        // Clients that do not reconnect start out connected
+        let connected = *state.borrow_and_update() == ConnectionState::Connected;
+        if let Some(client) = client.upgrade().filter(|_| connected) {
+            client.negotiate_on_connect().await;
+        }
+        loop {
+            select! {
+                key = released.recv() => {
+                    let Some(key) = key else {
+                        return;
+                    };
+                    if let Some(client) = client.upgrade() {
+                        client.release_topic(key).await;
+                    }
+                },
+                res = state.changed() => {
+                    if res.is_err() {
+                        return;
+                    }
+                    let connected = *state.borrow_and_update() == ConnectionState::Connected;
+                    if let Some(client) = client.upgrade().filter(|_| connected) {
+                        client.negotiate_on_connect().await;
+                    }
+                },
+            }
+        }
+    };
+    select! {
+        biased;
+        _ = stopper.wait_stopped() => {},
+        _ = operate_fut => {},
+    }
+}
@@ -0,0 +1,159 @@
+//! Host side of the blob transfer endpoints
+
+use core::num::NonZeroUsize;
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    host_client::{HostClient, HostErr},
+    standard_icd::{
+        BlobClose, BlobCloseEndpoint, BlobCrc, BlobData, BlobError, BlobMode, BlobOpen,
+        BlobOpenEndpoint, BlobRead, BlobReadEndpoint, BlobSummary, BlobWrite, BlobWriteEndpoint,
+        ProtocolInfo,
+    },
+    Endpoint,
+};
+
+/// # Blob Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Write `data` to the blob `name`, in chunks of at most `chunk_size` bytes
+    ///
+    /// If a previous upload of the same data was interrupted, and the server
+    /// kept what it received, only the remaining data is sent. Once all data was
+    /// written, the checksum of the stored blob is compared to the checksum of
+    /// `data`, returning [`BlobError::CrcMismatch`] if they differ.
+    ///
+    /// The server must handle the blob endpoints, see [`crate::server::blob`].
+    /// Fails with [`HostErr::Unsupported`] if the negotiated protocol lacks
+    /// [`ProtocolInfo::CAP_BLOB`].
+    pub async fn upload_blob(
+        &self,
+        name: &str,
+        data: &[u8],
+        chunk_size: NonZeroUsize,
+    ) -> Result<BlobSummary, HostErr<WireErr, BlobError>> {
+        self.require(ProtocolInfo::CAP_BLOB)?;
+        let Ok(len) = u32::try_from(data.len()) else {
+            return Err(HostErr::Endpoint(BlobError::TooLarge));
+        };
+        let crc = BlobCrc::checksum(data);
+
+        let info = self
+            .send_resp_fallible::<BlobOpenEndpoint>(&BlobOpen {
+                name,
+                mode: BlobMode::Write { len, crc },
+            })
+            .await?;
+        let handle = info.handle;
+
+        let mut offset = info.offset as usize;
+        while offset < data.len() {
+            let end = data.len().min(offset.saturating_add(chunk_size.get()));
+            let res = self
+                .send_resp_fallible::<BlobWriteEndpoint>(&BlobWrite {
+                    handle,
+                    offset: offset as u32,
+                    data: &data[offset..end],
+                })
+                .await;
+            if let Err(e) = res {
+                // Try to close the handle, the data written so far can still be resumed
+                let _ = self.close_blob(handle).await;
+                return Err(e);
+            }
+            offset = end;
+        }
+
+        let summary = self.close_blob(handle).await?;
+        if summary.crc != crc || summary.len != len {
+            return Err(HostErr::Endpoint(BlobError::CrcMismatch {
+                expected: crc,
+                actual: summary.crc,
+            }));
+        }
+        Ok(summary)
+    }
+
+    /// Read the blob `name`, in chunks of at most `chunk_size` bytes
+    ///
+    /// The checksum of the received data is compared to the checksum of the
+    /// stored blob, returning [`BlobError::CrcMismatch`] if they differ.
+    ///
+    /// The server must handle the blob endpoints, see [`crate::server::blob`].
+    /// Fails with [`HostErr::Unsupported`] if the negotiated protocol lacks
+    /// [`ProtocolInfo::CAP_BLOB`].
+    pub async fn download_blob(
+        &self,
+        name: &str,
+        chunk_size: NonZeroUsize,
+    ) -> Result<Vec<u8>, HostErr<WireErr, BlobError>> {
+        self.require(ProtocolInfo::CAP_BLOB)?;
+        let chunk_size = u32::try_from(chunk_size.get()).unwrap_or(u32::MAX);
+        let info = self
+            .send_resp_fallible::<BlobOpenEndpoint>(&BlobOpen {
+                name,
+                mode: BlobMode::Read,
+            })
+            .await?;
+        let handle = info.handle;
+
+        let mut out = Vec::with_capacity(info.len as usize);
+        let mut crc = BlobCrc::new();
+        while out.len() < info.len as usize {
+            let req = BlobRead {
+                handle,
+                offset: out.len() as u32,
+                len: chunk_size,
+            };
+            let res = self.read_blob_chunk(&req).await.and_then(|data| {
+                if data.is_empty() {
+                    // The blob is shorter than announced
+                    Err(HostErr::Endpoint(BlobError::BadOffset))
+                } else {
+                    crc = crc.update(&data);
+                    out.extend_from_slice(&data);
+                    Ok(())
+                }
+            });
+            if let Err(e) = res {
+                let _ = self.close_blob(handle).await;
+                return Err(e);
+            }
+        }
+
+        let summary = self.close_blob(handle).await?;
+        let crc = crc.finish();
+        if summary.crc != crc {
+            return Err(HostErr::Endpoint(BlobError::CrcMismatch {
+                expected: summary.crc,
+                actual: crc,
+            }));
+        }
+        Ok(out)
+    }
+
+    /// Read a single chunk, the response borrows from the frame so can't use `send_resp`
+    async fn read_blob_chunk(
+        &self,
+        req: &BlobRead,
+    ) -> Result<Vec<u8>, HostErr<WireErr, BlobError>> {
+        let (frame, _seq) = self.request_frame::<BlobReadEndpoint>(req).await;
+        let resp = self
+            .send_resp_raw_inner(frame, BlobReadEndpoint::RESP_KEY, self.ctx.resp_timeout)
+            .await
+            .map_err(HostErr::with_endpoint_err)?;
+        match postcard::from_bytes::<Result<BlobData<'_>, BlobError>>(&resp.body)? {
+            Ok(data) => Ok(data.data.to_vec()),
+            Err(e) => Err(HostErr::Endpoint(e)),
+        }
+    }
+
+    async fn close_blob(&self, handle: u32) -> Result<BlobSummary, HostErr<WireErr, BlobError>> {
+        self.send_resp_fallible::<BlobCloseEndpoint>(&BlobClose { handle })
+            .await
+    }
+}
@@ -0,0 +1,455 @@
+//! Recording and replaying the frames of a connection
+//!
+//! [`Capture`] wraps the [`WireTx`] and [`WireRx`] of a connection, and writes
+//! every frame to a file. [`Replay`] reads such a file back, and plays the
+//! device side of the session to a [`HostClient`], so that problems seen in
+//! the field can be reproduced without hardware.
+//!
+//! ```rust,no_run
+//! # use postcard_rpc::host_client::{HostClient, WireRx, WireSpawn, WireTx};
+//! # use postcard_rpc::host_client::capture::{Capture, Replay};
+//! # use postcard_rpc::header::VarSeqKind;
+//! # use postcard_rpc::standard_icd::{WireError, ERROR_PATH};
+//! # fn example(tx: impl WireTx, rx: impl WireRx, sp: impl WireSpawn) -> std::io::Result<()> {
+//! // Record a session
+//! let capture = Capture::create("session.prpc")?;
+//! let client = HostClient::<WireError>::new_with_wire(
+//!     capture.tap_tx(tx),
+//!     capture.tap_rx(rx),
+//!     sp,
+//!     VarSeqKind::Seq2,
+//!     ERROR_PATH,
+//!     8,
+//! );
+//!
+//! // ...and later, without the device
+//! let replay = Replay::open("session.prpc")?;
+//! let client = HostClient::<WireError>::new_replay(replay, ERROR_PATH, 8, VarSeqKind::Seq2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## File format
+//!
+//! All integers are little endian. The file starts with the 8 byte magic
+//! `PRPCCAP1`, followed by one record per frame:
+//!
+//! | Field     | Size  | Description                                       |
+//! | -----     | ----  | -----------                                       |
+//! | timestamp | 8     | Microseconds since the capture was started        |
+//! | direction | 1     | `0` for host to device, `1` for device to host    |
+//! | len       | 4     | Length of the frame                               |
+//! | frame     | len   | The frame, including the [`VarHeader`]            |
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    future::Future,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::sync::{oneshot, watch};
+
+use crate::{
+    header::{VarHeader, VarSeqKind},
+    host_client::{HostClient, WireRx, WireSpawn, WireTx},
+};
+
+/// The magic bytes at the start of every capture file
+pub const CAPTURE_MAGIC: [u8; 8] = *b"PRPCCAP1";
+
+/// The direction of a captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the host to the device
+    ToDevice,
+    /// Sent by the device to the host
+    ToHost,
+}
+
+/// A single frame of a capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    /// The time since the capture was started
+    pub timestamp: Duration,
+    /// Who sent the frame
+    pub direction: Direction,
+    /// The frame, including the header
+    pub frame: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// Decode the header of the frame, returning the header and the body
+    ///
+    /// Returns `None` if the frame doesn't start with a valid header.
+    pub fn header(&self) -> Option<(VarHeader, &[u8])> {
+        VarHeader::take_from_slice(&self.frame)
+    }
+}
+
+/// Read all frames of a capture
+///
+/// A record that was cut short, e.g. because the program making the capture
+/// crashed, ends the capture without an error.
+pub fn read_capture(mut reader: impl Read) -> io::Result<Vec<CapturedFrame>> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != CAPTURE_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a postcard-rpc capture",
+        ));
+    }
+
+    let mut frames = vec![];
+    loop {
+        let mut hdr = [0u8; 13];
+        if !read_record_part(&mut reader, &mut hdr)? {
+            return Ok(frames);
+        }
+        let timestamp = Duration::from_micros(u64::from_le_bytes(hdr[..8].try_into().unwrap()));
+        let direction = match hdr[8] {
+            0 => Direction::ToDevice,
+            1 => Direction::ToHost,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid frame direction",
+                ))
+            }
+        };
+        let len = u32::from_le_bytes(hdr[9..].try_into().unwrap());
+        let mut frame = vec![0u8; len as usize];
+        if !read_record_part(&mut reader, &mut frame)? {
+            return Ok(frames);
+        }
+        frames.push(CapturedFrame {
+            timestamp,
+            direction,
+            frame,
+        });
+    }
+}
+
+/// Returns `false` if the capture ended
+fn read_record_part(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// CAPTURE
+//////////////////////////////////////////////////////////////////////////////
+
+/// A recording of the frames of a connection
+///
+/// Frames are written by a background thread, so recording never blocks the
+/// I/O workers of the client. Cloning a `Capture` gives another handle to the
+/// same recording, the thread stops once all handles are dropped.
+#[derive(Clone)]
+pub struct Capture {
+    records: mpsc::Sender<CaptureMsg>,
+    start: Instant,
+}
+
+enum CaptureMsg {
+    Record {
+        timestamp: u64,
+        direction: Direction,
+        frame: Vec<u8>,
+    },
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+impl Capture {
+    /// Create a new capture file at `path`, replacing any existing file
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Create a new capture, writing to `out`
+    pub fn new(mut out: impl Write + Send + 'static) -> io::Result<Self> {
+        out.write_all(&CAPTURE_MAGIC)?;
+        out.flush()?;
+        let (records, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("postcard-rpc-capture".into())
+            .spawn(move || capture_writer(out, rx))?;
+        Ok(Self {
+            records,
+            start: Instant::now(),
+        })
+    }
+
+    /// Record all frames sent by `tx`
+    pub fn tap_tx<T: WireTx>(&self, tx: T) -> CaptureTx<T> {
+        CaptureTx {
+            inner: tx,
+            capture: self.clone(),
+        }
+    }
+
+    /// Record all frames received by `rx`
+    pub fn tap_rx<R: WireRx>(&self, rx: R) -> CaptureRx<R> {
+        CaptureRx {
+            inner: rx,
+            capture: self.clone(),
+        }
+    }
+
+    /// Wait until all frames recorded so far are written
+    ///
+    /// Returns an error if writing failed, which also stopped the recording.
+    pub async fn flush(&self) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        let stopped = || io::Error::other("the capture writer has stopped");
+        self.records
+            .send(CaptureMsg::Flush(tx))
+            .map_err(|_| stopped())?;
+        rx.await.map_err(|_| stopped())?
+    }
+
+    /// Queue a single record
+    fn record(&self, direction: Direction, frame: &[u8]) {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        // If the writer stopped, the error was already logged
+        let _ = self.records.send(CaptureMsg::Record {
+            timestamp,
+            direction,
+            frame: frame.to_vec(),
+        });
+    }
+}
+
+/// Writes the queued records until all [`Capture`] handles are dropped
+///
+/// A failing capture must not break the connection, so errors are only
+/// logged, and stop the recording.
+fn capture_writer(mut out: impl Write, rx: mpsc::Receiver<CaptureMsg>) {
+    let mut failed = false;
+    for msg in rx {
+        match msg {
+            CaptureMsg::Record { .. } if failed => {}
+            CaptureMsg::Record {
+                timestamp,
+                direction,
+                frame,
+            } => {
+                let direction = match direction {
+                    Direction::ToDevice => 0u8,
+                    Direction::ToHost => 1u8,
+                };
+                let res = (|| {
+                    out.write_all(&timestamp.to_le_bytes())?;
+                    out.write_all(&[direction])?;
+                    out.write_all(&(frame.len() as u32).to_le_bytes())?;
+                    out.write_all(&frame)?;
+                    // Flush every frame, the capture is most useful if something crashes
+                    out.flush()
+                })();
+                if let Err(e) = res {
+                    tracing::warn!("Capture write failed: {e:?}, stopping capture");
+                    failed = true;
+                }
+            }
+            CaptureMsg::Flush(done) => {
+                let res = if failed {
+                    Err(io::Error::other("a capture write failed"))
+                } else {
+                    Ok(())
+                };
+                let _ = done.send(res);
+            }
+        }
+    }
+}
+
+/// A [`WireTx`] that records all sent frames, created by [`Capture::tap_tx`]
+pub struct CaptureTx<T> {
+    inner: T,
+    capture: Capture,
+}
+
+impl<T: WireTx> WireTx for CaptureTx<T> {
+    type Error = T::Error;
+
+    #[inline]
+    fn send(&mut self, data: Vec<u8>) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.capture.record(Direction::ToDevice, &data);
+        self.inner.send(data)
+    }
+}
+
+/// A [`WireRx`] that records all received frames, created by [`Capture::tap_rx`]
+pub struct CaptureRx<R> {
+    inner: R,
+    capture: Capture,
+}
+
+impl<R: WireRx> WireRx for CaptureRx<R> {
+    type Error = R::Error;
+
+    async fn receive(&mut self) -> Result<Vec<u8>, Self::Error> {
+        let frame = self.inner.receive().await?;
+        self.capture.record(Direction::ToHost, &frame);
+        Ok(frame)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// REPLAY
+//////////////////////////////////////////////////////////////////////////////
+
+/// A recorded session, to be played back to a [`HostClient`]
+///
+/// The device's frames are replayed in order, each one only after the host
+/// sent as many frames as it had at that point of the recording. For the
+/// replayed responses to match, the host must make the same requests in the
+/// same order as when recording, using a new client.
+///
+/// Once all of the device's frames were replayed, the connection is closed
+/// as soon as the host sends more frames than were recorded.
+pub struct Replay {
+    frames: Vec<CapturedFrame>,
+}
+
+/// The replayed session has ended
+#[derive(thiserror::Error, Debug)]
+#[error("The replayed session has ended")]
+pub struct ReplayFinished;
+
+impl Replay {
+    /// Load the capture file at `path`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let frames = read_capture(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(frames))
+    }
+
+    /// Replay the given frames
+    pub fn new(frames: Vec<CapturedFrame>) -> Self {
+        Self { frames }
+    }
+
+    /// Split into a wire that can be used with [`HostClient::new_with_wire`]
+    pub fn into_wire(self) -> (ReplayTx, ReplayRx) {
+        let (sent_tx, sent_rx) = watch::channel(0);
+        let mut expected = VecDeque::new();
+        let mut replies = VecDeque::new();
+        for frame in self.frames {
+            match frame.direction {
+                Direction::ToDevice => expected.push_back(frame.frame),
+                Direction::ToHost => replies.push_back((expected.len(), frame.frame)),
+            }
+        }
+        let recorded_sent = expected.len();
+        (
+            ReplayTx {
+                expected,
+                sent: sent_tx,
+            },
+            ReplayRx {
+                recorded_sent,
+                replies,
+                sent: sent_rx,
+            },
+        )
+    }
+}
+
+/// The host side of a [`Replay`], counting the frames sent by the host
+pub struct ReplayTx {
+    expected: VecDeque<Vec<u8>>,
+    sent: watch::Sender<usize>,
+}
+
+impl WireTx for ReplayTx {
+    type Error = ReplayFinished;
+
+    async fn send(&mut self, data: Vec<u8>) -> Result<(), Self::Error> {
+        match self.expected.pop_front() {
+            Some(exp) if exp == data => {}
+            Some(exp) => {
+                tracing::warn!("Replay: host sent {data:?}, but {exp:?} was recorded");
+            }
+            None => tracing::warn!("Replay: host sent {data:?} after the end of the recording"),
+        }
+        self.sent.send_modify(|s| *s += 1);
+        Ok(())
+    }
+}
+
+/// The device side of a [`Replay`], feeding the recorded frames to the host
+pub struct ReplayRx {
+    /// Frames, and how many frames the host must have sent before them
+    replies: VecDeque<(usize, Vec<u8>)>,
+    /// How many frames the host sent in the recording
+    recorded_sent: usize,
+    sent: watch::Receiver<usize>,
+}
+
+impl WireRx for ReplayRx {
+    type Error = ReplayFinished;
+
+    async fn receive(&mut self) -> Result<Vec<u8>, Self::Error> {
+        let Some((after, frame)) = self.replies.pop_front() else {
+            // Don't close right after the last frame, the host may not have
+            // processed it yet
+            let recorded = self.recorded_sent;
+            _ = self.sent.wait_for(|sent| *sent > recorded).await;
+            return Err(ReplayFinished);
+        };
+        self.sent
+            .wait_for(|sent| *sent >= after)
+            .await
+            .map_err(|_| ReplayFinished)?;
+        Ok(frame)
+    }
+}
+
+/// # Replay Constructor Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Create a new [HostClient] that talks to a recorded session instead of
+    /// a device, see [`Replay`] for details.
+    ///
+    /// `err_uri_path` and `seq_no_kind` should be the same as when recording.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new_replay(
+        replay: Replay,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        let (tx, rx) = replay.into_wire();
+        HostClient::new_with_wire(
+            tx,
+            rx,
+            ReplaySpawn,
+            seq_no_kind,
+            err_uri_path,
+            outgoing_depth,
+        )
+    }
+}
+
+struct ReplaySpawn;
+
+impl WireSpawn for ReplaySpawn {
+    fn spawn(&mut self, fut: impl Future<Output = ()> + Send + 'static) {
+        // Explicitly drop the joinhandle as it impls Future and this makes
+        // clippy mad if you just let it drop implicitly
+        core::mem::drop(tokio::task::spawn(fut));
+    }
+}
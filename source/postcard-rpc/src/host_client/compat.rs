@@ -0,0 +1,272 @@
+//! Checking that a connected device speaks the same ICD as the host
+
+use postcard_schema::{schema::owned::OwnedNamedType, Schema};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    header::{VarKey, VarKeyKind},
+    host_client::{HostClient, SchemaError, SchemaReport},
+    DeviceMap, Key,
+};
+
+/// The kind of message a [`TypeMismatch`] or [`KeyCollision`] refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// The request of an endpoint
+    Request,
+    /// The response of an endpoint
+    Response,
+    /// A client to server topic
+    TopicIn,
+    /// A server to client topic
+    TopicOut,
+}
+
+/// A path known to both the host and the device, but with different types
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeMismatch {
+    /// The path of the endpoint or topic
+    pub path: String,
+    /// Which message of the path differs
+    pub kind: MessageKind,
+    /// The type the host expects, if it is a primitive or part of the [`DeviceMap::types`]
+    pub expected: Option<OwnedNamedType>,
+    /// The type reported by the device
+    pub actual: OwnedNamedType,
+}
+
+/// Two different messages whose keys are the same when shrunk to `key_kind`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCollision {
+    /// The key size that was checked
+    pub key_kind: VarKeyKind,
+    /// The first message
+    pub first: (String, MessageKind),
+    /// The second message
+    pub second: (String, MessageKind),
+}
+
+/// The differences between a [`SchemaReport`] and a [`DeviceMap`]
+///
+/// "Missing" items are known to the host but not reported by the device,
+/// "extra" items are reported by the device but unknown to the host.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SchemaDiff {
+    /// Endpoints the device does not serve
+    pub missing_endpoints: Vec<String>,
+    /// Endpoints unknown to the host
+    pub extra_endpoints: Vec<String>,
+    /// Client to server topics the device does not handle
+    pub missing_topics_in: Vec<String>,
+    /// Client to server topics unknown to the host
+    pub extra_topics_in: Vec<String>,
+    /// Server to client topics the device does not send
+    pub missing_topics_out: Vec<String>,
+    /// Server to client topics unknown to the host
+    pub extra_topics_out: Vec<String>,
+    /// Paths that use different types on the host and the device
+    pub type_mismatches: Vec<TypeMismatch>,
+    /// Keys that can't be told apart at [`DeviceMap::min_key_len`]
+    pub key_collisions: Vec<KeyCollision>,
+}
+
+impl SchemaDiff {
+    /// Are the host and device schemas exactly the same?
+    pub fn is_empty(&self) -> bool {
+        self.is_compatible()
+            && self.extra_endpoints.is_empty()
+            && self.extra_topics_in.is_empty()
+            && self.extra_topics_out.is_empty()
+    }
+
+    /// Can the host talk to the device?
+    ///
+    /// This is the case if the device knows everything the host does, with the
+    /// same types. Extra endpoints and topics on the device are fine.
+    pub fn is_compatible(&self) -> bool {
+        self.missing_endpoints.is_empty()
+            && self.missing_topics_in.is_empty()
+            && self.missing_topics_out.is_empty()
+            && self.type_mismatches.is_empty()
+            && self.key_collisions.is_empty()
+    }
+}
+
+impl SchemaReport {
+    /// Compare the schema reported by a device against the host's [`DeviceMap`]
+    pub fn diff(&self, map: &DeviceMap) -> SchemaDiff {
+        let mut diff = SchemaDiff::default();
+
+        for (path, req_key, resp_key) in map.endpoints {
+            let Some(ep) = self.endpoints.iter().find(|e| e.path == *path) else {
+                diff.missing_endpoints.push(path.to_string());
+                continue;
+            };
+            if ep.req_key != *req_key {
+                diff.type_mismatches.push(mismatch(
+                    map,
+                    path,
+                    *req_key,
+                    MessageKind::Request,
+                    &ep.req_ty,
+                ));
+            }
+            if ep.resp_key != *resp_key {
+                diff.type_mismatches.push(mismatch(
+                    map,
+                    path,
+                    *resp_key,
+                    MessageKind::Response,
+                    &ep.resp_ty,
+                ));
+            }
+        }
+        diff.extra_endpoints = self
+            .endpoints
+            .iter()
+            .filter(|e| !map.endpoints.iter().any(|(p, _, _)| *p == e.path))
+            .map(|e| e.path.clone())
+            .collect();
+
+        for (kind, host, device, missing, extra) in [
+            (
+                MessageKind::TopicIn,
+                map.topics_in,
+                &self.topics_in,
+                &mut diff.missing_topics_in,
+                &mut diff.extra_topics_in,
+            ),
+            (
+                MessageKind::TopicOut,
+                map.topics_out,
+                &self.topics_out,
+                &mut diff.missing_topics_out,
+                &mut diff.extra_topics_out,
+            ),
+        ] {
+            for (path, key) in host {
+                let Some(tp) = device.iter().find(|t| t.path == *path) else {
+                    missing.push(path.to_string());
+                    continue;
+                };
+                if tp.key != *key {
+                    diff.type_mismatches
+                        .push(mismatch(map, path, *key, kind, &tp.ty));
+                }
+            }
+            *extra = device
+                .iter()
+                .filter(|t| !host.iter().any(|(p, _)| *p == t.path))
+                .map(|t| t.path.clone())
+                .collect();
+        }
+
+        // Messages in each direction must be distinguishable by their shrunk keys,
+        // taking the messages of both the host and the device into account
+        let mut incoming = vec![];
+        let mut outgoing = vec![];
+        for (path, req_key, resp_key) in map.endpoints {
+            incoming.push((path.to_string(), MessageKind::Request, *req_key));
+            outgoing.push((path.to_string(), MessageKind::Response, *resp_key));
+        }
+        for ep in self.endpoints.iter() {
+            incoming.push((ep.path.clone(), MessageKind::Request, ep.req_key));
+            outgoing.push((ep.path.clone(), MessageKind::Response, ep.resp_key));
+        }
+        for (path, key) in map.topics_in {
+            incoming.push((path.to_string(), MessageKind::TopicIn, *key));
+        }
+        for tp in self.topics_in.iter() {
+            incoming.push((tp.path.clone(), MessageKind::TopicIn, tp.key));
+        }
+        for (path, key) in map.topics_out {
+            outgoing.push((path.to_string(), MessageKind::TopicOut, *key));
+        }
+        for tp in self.topics_out.iter() {
+            outgoing.push((tp.path.clone(), MessageKind::TopicOut, tp.key));
+        }
+        collisions(&mut diff.key_collisions, incoming, map.min_key_len);
+        collisions(&mut diff.key_collisions, outgoing, map.min_key_len);
+
+        diff
+    }
+}
+
+fn mismatch(
+    map: &DeviceMap,
+    path: &str,
+    key: Key,
+    kind: MessageKind,
+    actual: &OwnedNamedType,
+) -> TypeMismatch {
+    // `DeviceMap::types` doesn't contain primitives, use the ones every report starts with
+    let expected = map
+        .types
+        .iter()
+        .map(|ty| OwnedNamedType::from(*ty))
+        .chain(SchemaReport::default().types)
+        .find(|ty| Key::for_owned_schema_path(path, ty) == key);
+    TypeMismatch {
+        path: path.to_string(),
+        kind,
+        expected,
+        actual: actual.clone(),
+    }
+}
+
+fn collisions(
+    out: &mut Vec<KeyCollision>,
+    msgs: Vec<(String, MessageKind, Key)>,
+    key_kind: VarKeyKind,
+) {
+    // The same message is usually known to both sides
+    let mut unique: Vec<(String, MessageKind, Key)> = vec![];
+    for msg in msgs {
+        if !unique.iter().any(|u| u.2 == msg.2) {
+            unique.push(msg);
+        }
+    }
+
+    for (i, a) in unique.iter().enumerate() {
+        let mut a_key = VarKey::Key8(a.2);
+        a_key.shrink_to(key_kind);
+        for b in unique.iter().skip(i + 1) {
+            let mut b_key = VarKey::Key8(b.2);
+            b_key.shrink_to(key_kind);
+            if a_key == b_key {
+                out.push(KeyCollision {
+                    key_kind,
+                    first: (a.0.clone(), a.1),
+                    second: (b.0.clone(), b.1),
+                });
+            }
+        }
+    }
+}
+
+/// # Compatibility Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Fetch the [`SchemaReport`] of the connected device, and compare it to `map`
+    ///
+    /// Returns [`SchemaError::Incompatible`] if the host can't talk to the device,
+    /// see [`SchemaDiff::is_compatible`]. Otherwise, the (possibly empty) diff is
+    /// returned.
+    ///
+    /// This is typically called right after creating the client, to fail fast
+    /// when the connected firmware uses a different ICD.
+    pub async fn check_compatibility(
+        &self,
+        map: &DeviceMap,
+    ) -> Result<SchemaDiff, SchemaError<WireErr>> {
+        let report = self.get_schema_report().await?;
+        let diff = report.diff(map);
+        if diff.is_compatible() {
+            Ok(diff)
+        } else {
+            Err(SchemaError::Incompatible(diff))
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Decoding raw frames into a human readable form
+//!
+//! Keys are resolved using the [`SchemaReport`] of the device, including
+//! shortened keys, and bodies are decoded with [`dynamic`][super::dynamic].
+//! This is useful for logging, debugging, or building dissectors for tools
+//! like Wireshark.
+//!
+//! ```rust
+//! # use postcard_rpc::host_client::{decode::decode_frame, SchemaReport};
+//! # fn example(report: &SchemaReport, frame: &[u8]) {
+//! match decode_frame(frame, report) {
+//!     Ok(decoded) => println!("{decoded}"),
+//!     Err(e) => println!("Bad frame: {e:?}"),
+//! }
+//! # }
+//! ```
+
+use core::fmt;
+
+use postcard_schema::schema::owned::OwnedNamedType;
+
+use crate::{
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq, VarSeqKind},
+    host_client::{
+        dynamic::{from_postcard, Value, ValueError},
+        MessageKind, SchemaReport,
+    },
+    Key,
+};
+
+/// A frame, decoded by [`decode_frame`]
+///
+/// The [`Display`][fmt::Display] impl gives a multi-line description of the frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedFrame {
+    /// The discriminant byte of the header
+    pub discriminant: u8,
+    /// The decoded header
+    pub header: VarHeader,
+    /// All messages of the report with a key matching the header
+    ///
+    /// With shortened keys, more than one message may match. If no message
+    /// matches, this is empty.
+    pub matches: Vec<KeyMatch>,
+    /// The undecoded body of the frame
+    pub body: Vec<u8>,
+}
+
+/// A message matching the key of a [`DecodedFrame`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMatch {
+    /// The path of the endpoint or topic
+    pub path: String,
+    /// The kind of message
+    pub kind: MessageKind,
+    /// The full (eight byte) key of the message
+    pub key: Key,
+    /// The type of the message
+    pub ty: OwnedNamedType,
+    /// The body, decoded as `ty`
+    pub value: Result<Value, ValueError>,
+}
+
+/// Errors returned by [`decode_frame`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The frame was empty
+    Empty,
+    /// The header uses a protocol version other than zero
+    UnsupportedVersion(u8),
+    /// The header is invalid, or the frame is too short for it
+    BadHeader,
+}
+
+impl DecodedFrame {
+    /// The length of the key in the header
+    pub fn key_kind(&self) -> VarKeyKind {
+        self.header.key.kind()
+    }
+
+    /// The length of the sequence number in the header
+    pub fn seq_kind(&self) -> VarSeqKind {
+        match self.header.seq_no {
+            VarSeq::Seq1(_) => VarSeqKind::Seq1,
+            VarSeq::Seq2(_) => VarSeqKind::Seq2,
+            VarSeq::Seq4(_) => VarSeqKind::Seq4,
+        }
+    }
+
+    /// The protocol version in the header
+    pub fn version(&self) -> u8 {
+        self.discriminant & VarHeader::VER_MASK_BITS
+    }
+}
+
+/// Decode a frame (header and body) sent by or to the device described by `report`
+pub fn decode_frame(frame: &[u8], report: &SchemaReport) -> Result<DecodedFrame, DecodeError> {
+    let Some(discriminant) = frame.first().copied() else {
+        return Err(DecodeError::Empty);
+    };
+    let version = discriminant & VarHeader::VER_MASK_BITS;
+    if !VarHeader::is_supported_version(version) {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let (header, body) = VarHeader::take_from_slice(frame).ok_or(DecodeError::BadHeader)?;
+
+    let endpoints = report.endpoints.iter().flat_map(|ep| {
+        [
+            (&ep.path, MessageKind::Request, ep.req_key, &ep.req_ty),
+            (&ep.path, MessageKind::Response, ep.resp_key, &ep.resp_ty),
+        ]
+    });
+    let topics_in = report
+        .topics_in
+        .iter()
+        .map(|tp| (&tp.path, MessageKind::TopicIn, tp.key, &tp.ty));
+    let topics_out = report
+        .topics_out
+        .iter()
+        .map(|tp| (&tp.path, MessageKind::TopicOut, tp.key, &tp.ty));
+
+    // `VarKey`'s `PartialEq` shrinks the full keys to the size used in the header
+    let matches = endpoints
+        .chain(topics_in)
+        .chain(topics_out)
+        .filter(|(_, _, key, _)| VarKey::Key8(*key) == header.key)
+        .map(|(path, kind, key, ty)| KeyMatch {
+            path: path.clone(),
+            kind,
+            key,
+            ty: ty.clone(),
+            value: from_postcard(ty, body),
+        })
+        .collect();
+
+    Ok(DecodedFrame {
+        discriminant,
+        header,
+        matches,
+        body: body.to_vec(),
+    })
+}
+
+impl fmt::Display for DecodedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key_len = match self.key_kind() {
+            VarKeyKind::Key1 => 1,
+            VarKeyKind::Key2 => 2,
+            VarKeyKind::Key4 => 4,
+            VarKeyKind::Key8 => 8,
+        };
+        let seq_len = match self.seq_kind() {
+            VarSeqKind::Seq1 => 1,
+            VarSeqKind::Seq2 => 2,
+            VarSeqKind::Seq4 => 4,
+        };
+        writeln!(
+            f,
+            "Header:  0x{:02X} (key: {key_len} bytes, seq: {seq_len} bytes, version: {})",
+            self.discriminant,
+            self.version(),
+        )?;
+        write!(f, "Key:     ")?;
+        match self.header.key {
+            VarKey::Key1(k) => write_hex(f, &[k.to_bytes()])?,
+            VarKey::Key2(k) => write_hex(f, &k.to_bytes())?,
+            VarKey::Key4(k) => write_hex(f, &k.to_bytes())?,
+            VarKey::Key8(k) => write_hex(f, &k.to_bytes())?,
+        }
+        writeln!(f)?;
+        let seq_no: u32 = self.header.seq_no.into();
+        writeln!(f, "Seq:     {seq_no}")?;
+
+        if self.matches.is_empty() {
+            writeln!(f, "Message: unknown key")?;
+        }
+        for m in self.matches.iter() {
+            write!(f, "Message: {:?} {:?} (", m.kind, m.path)?;
+            write_hex(f, &m.key.to_bytes())?;
+            writeln!(f, "), {}", m.ty.name)?;
+            match &m.value {
+                Ok(v) => writeln!(f, "  Value: {v}")?,
+                Err(e) => writeln!(f, "  Value: undecodable, {e:?}")?,
+            }
+        }
+
+        write!(f, "Body:    {} bytes", self.body.len())?;
+        if !self.body.is_empty() {
+            write!(f, ", ")?;
+            write_hex(f, &self.body)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for b in bytes {
+        write!(f, "{b:02X}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,335 @@
+//! Managing many `nusb` devices at once
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use nusb::{hotplug::HotplugEvent, DeviceId, DeviceInfo};
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::{select, sync::broadcast};
+
+use crate::{
+    header::VarSeqKind,
+    host_client::{
+        raw_nusb::next_hotplug_event, util::Stopper, HostClient, HostClientConfig, RawNusbConfig,
+    },
+};
+
+/// How many events are buffered for each receiver of [`DeviceManager::events()`]
+const EVENT_DEPTH: usize = 64;
+
+/// An event emitted by [`DeviceManager`] when a device is added or removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    /// A matching device was connected, and a client for it is available
+    /// under this serial number
+    Added(String),
+    /// The device with this serial number was disconnected, or its client
+    /// was closed, and it is no longer managed
+    Removed(String),
+}
+
+/// Holds one [`HostClient`] for each connected `nusb` device matching a predicate
+///
+/// Clients are keyed by the serial number of the device, devices without a
+/// serial number are ignored. Devices plugged in later are added, and devices
+/// unplugged or whose client was closed are removed, see [`Self::events()`] to
+/// be notified of both.
+///
+/// Cloning a `DeviceManager` gives another handle to the same set of clients.
+///
+/// **Requires feature**: `raw-nusb`
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use postcard_rpc::host_client::{DeviceEvent, DeviceManager};
+/// use postcard_rpc::header::VarSeqKind;
+/// use postcard_rpc::standard_icd::{PingEndpoint, WireError, ERROR_PATH};
+///
+/// # async fn run() {
+/// let manager = DeviceManager::<WireError>::try_new(
+///     // Manage all devices with this VID and PID
+///     |d| d.vendor_id() == 0x16c0 && d.product_id() == 0x27DD,
+///     ERROR_PATH,
+///     8,
+///     VarSeqKind::Seq1,
+/// )
+/// .unwrap();
+///
+/// let mut events = manager.events();
+/// for (serial, client) in manager.clients() {
+///     let resp = client.send_resp::<PingEndpoint>(&42).await;
+///     println!("{serial}: {resp:?}");
+/// }
+/// while let Ok(event) = events.recv().await {
+///     match event {
+///         DeviceEvent::Added(serial) => println!("{serial} added"),
+///         DeviceEvent::Removed(serial) => println!("{serial} removed"),
+///     }
+/// }
+/// # }
+/// ```
+pub struct DeviceManager<WireErr> {
+    state: Arc<Mutex<ManagerState<WireErr>>>,
+    events: broadcast::Sender<DeviceEvent>,
+    stopper: Stopper,
+}
+
+struct ManagerState<WireErr> {
+    clients: HashMap<String, HostClient<WireErr>>,
+    serials: HashMap<DeviceId, String>,
+}
+
+/// The settings used for each client, owned so the watcher task can keep them
+struct ClientSettings {
+    nusb: RawNusbConfig,
+    err_uri_path: String,
+    // `err_uri_path` is not used, see `config()`
+    config: HostClientConfig<'static>,
+}
+
+// Manual impl, as `WireErr` does not need to be `Clone`
+impl<WireErr> Clone for DeviceManager<WireErr> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            events: self.events.clone(),
+            stopper: self.stopper.clone(),
+        }
+    }
+}
+
+impl<WireErr> DeviceManager<WireErr>
+where
+    WireErr: DeserializeOwned + Schema + 'static,
+{
+    /// Connect to all devices matching `func`, and keep watching for more
+    ///
+    /// The arguments are used for each client as in [`HostClient::try_new_raw_nusb()`].
+    /// Devices that fail to open are skipped with a warning.
+    ///
+    /// Returns an error if the devices could not be listed or watched. Must be
+    /// called from within a tokio runtime.
+    pub fn try_new<F: FnMut(&DeviceInfo) -> bool + Send + 'static>(
+        func: F,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Result<Self, String> {
+        Self::try_new_with_config(
+            func,
+            &RawNusbConfig::new(),
+            &HostClientConfig::new(seq_no_kind, err_uri_path, outgoing_depth),
+        )
+    }
+
+    /// Connect to all devices matching `func`, using the interface and endpoints
+    /// selected by `nusb_config`, and keep watching for more
+    ///
+    /// Each client is created with `config`. See [`Self::try_new()`] and
+    /// [`HostClient::try_new_raw_nusb_with_config()`].
+    pub fn try_new_with_config<F: FnMut(&DeviceInfo) -> bool + Send + 'static>(
+        mut func: F,
+        nusb_config: &RawNusbConfig,
+        config: &HostClientConfig<'_>,
+    ) -> Result<Self, String> {
+        // Start watching before listing, so a device plugged in between is not missed
+        let watch = nusb::watch_devices().map_err(|e| format!("Error watching devices: {e:?}"))?;
+        let devices = nusb::list_devices().map_err(|e| format!("Error listing devices: {e:?}"))?;
+
+        let settings = ClientSettings {
+            nusb: nusb_config.clone(),
+            err_uri_path: config.err_uri_path.to_string(),
+            config: config.with_err_uri_path(""),
+        };
+        let (events, _) = broadcast::channel(EVENT_DEPTH);
+        let me = Self {
+            state: Arc::new(Mutex::new(ManagerState {
+                clients: HashMap::new(),
+                serials: HashMap::new(),
+            })),
+            events,
+            stopper: Stopper::new(),
+        };
+        for info in devices.filter(&mut func) {
+            let Some(serial) = me.state.lock().unwrap().check_new(&info) else {
+                continue;
+            };
+            match HostClient::try_new_raw_nusb_device(&info, &settings.nusb, &settings.config()) {
+                Ok(client) => me.insert(&info, serial, client),
+                Err(error) => tracing::warn!(?info, error, "Failed opening device"),
+            }
+        }
+
+        // Explicitly drop the joinhandle, the worker stops when the manager is closed
+        core::mem::drop(tokio::task::spawn(watch_worker(
+            watch,
+            func,
+            settings,
+            me.clone(),
+        )));
+        Ok(me)
+    }
+
+    /// Get the client for the device with the given serial number
+    pub fn get(&self, serial: &str) -> Option<HostClient<WireErr>> {
+        self.state.lock().unwrap().clients.get(serial).cloned()
+    }
+
+    /// The serial numbers of all current devices, sorted
+    pub fn serials(&self) -> Vec<String> {
+        let mut serials: Vec<String> = self.state.lock().unwrap().clients.keys().cloned().collect();
+        serials.sort();
+        serials
+    }
+
+    /// All current devices and their clients, sorted by serial number
+    pub fn clients(&self) -> Vec<(String, HostClient<WireErr>)> {
+        let mut clients: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .clients
+            .iter()
+            .map(|(serial, client)| (serial.clone(), client.clone()))
+            .collect();
+        clients.sort_by(|a, b| a.0.cmp(&b.0));
+        clients
+    }
+
+    /// Receive an event each time a device is added or removed
+    ///
+    /// Only events after this call are received, devices that are already
+    /// present can be found with [`Self::clients()`].
+    pub fn events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stop watching for devices, and close all clients
+    pub fn close(&self) {
+        self.stopper.stop();
+        let mut state = self.state.lock().unwrap();
+        state.serials.clear();
+        for (_serial, client) in state.clients.drain() {
+            client.close();
+        }
+    }
+
+    /// Has this manager been closed?
+    pub fn is_closed(&self) -> bool {
+        self.stopper.is_stopped()
+    }
+
+    /// Add a client, and remove it again once it is closed
+    fn insert(&self, info: &DeviceInfo, serial: String, client: HostClient<WireErr>) {
+        let id = info.id();
+        let manager = self.clone();
+        let closed = client.clone();
+        self.state.lock().unwrap().insert(info, serial, client);
+        // Explicitly drop the joinhandle, the task ends with the client or the manager
+        core::mem::drop(tokio::task::spawn(async move {
+            select! {
+                _ = manager.stopper.wait_stopped() => {}
+                _ = closed.wait_closed() => manager.remove(id),
+            }
+        }));
+    }
+
+    /// Remove and close the client of the device `id`, if it is still managed
+    fn remove(&self, id: DeviceId) {
+        let removed = {
+            let mut state = self.state.lock().unwrap();
+            state
+                .serials
+                .remove(&id)
+                .and_then(|serial| state.clients.remove_entry(&serial))
+        };
+        if let Some((serial, client)) = removed {
+            client.close();
+            tracing::info!(serial, "Device removed");
+            let _ = self.events.send(DeviceEvent::Removed(serial));
+        }
+    }
+}
+
+impl<WireErr> ManagerState<WireErr> {
+    /// Get the serial number of a device we could add
+    fn check_new(&self, info: &DeviceInfo) -> Option<String> {
+        let Some(serial) = info.serial_number() else {
+            tracing::warn!(?info, "Ignoring device without serial number");
+            return None;
+        };
+        if self.clients.contains_key(serial) {
+            tracing::warn!(serial, "Ignoring device with duplicate serial number");
+            return None;
+        }
+        Some(serial.to_string())
+    }
+
+    fn insert(&mut self, info: &DeviceInfo, serial: String, client: HostClient<WireErr>) {
+        self.serials.insert(info.id(), serial.clone());
+        self.clients.insert(serial, client);
+    }
+}
+
+impl ClientSettings {
+    fn config(&self) -> HostClientConfig<'_> {
+        self.config.with_err_uri_path(&self.err_uri_path)
+    }
+}
+
+/// Adds and removes clients as devices come and go, until the manager is closed
+async fn watch_worker<WireErr, F>(
+    mut watch: nusb::hotplug::HotplugWatch,
+    mut func: F,
+    settings: ClientSettings,
+    manager: DeviceManager<WireErr>,
+) where
+    WireErr: DeserializeOwned + Schema + 'static,
+    F: FnMut(&DeviceInfo) -> bool,
+{
+    loop {
+        let event = select! {
+            _ = manager.stopper.wait_stopped() => return,
+            event = next_hotplug_event(&mut watch) => event,
+        };
+        match event {
+            Some(HotplugEvent::Connected(info)) => {
+                if !func(&info) {
+                    continue;
+                }
+                let Some(serial) = manager.state.lock().unwrap().check_new(&info) else {
+                    continue;
+                };
+                let client = match HostClient::try_new_raw_nusb_connected(
+                    &info,
+                    &settings.nusb,
+                    &settings.config(),
+                )
+                .await
+                {
+                    Ok(client) => client,
+                    Err(error) => {
+                        tracing::warn!(?info, error, "Failed opening device");
+                        continue;
+                    }
+                };
+                if manager.stopper.is_stopped() {
+                    client.close();
+                    return;
+                }
+                manager.insert(&info, serial.clone(), client);
+                tracing::info!(serial, "Device added");
+                let _ = manager.events.send(DeviceEvent::Added(serial));
+            }
+            Some(HotplugEvent::Disconnected(id)) => manager.remove(id),
+            None => {
+                tracing::warn!("Hotplug watch ended");
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,589 @@
+//! Dynamic, schema-driven encoding of messages
+//!
+//! This allows talking to a device without depending on its ICD crate, using
+//! the types reported in its [`SchemaReport`][crate::host_client::SchemaReport].
+//! Messages are represented as JSON [`Value`]s:
+//!
+//! | Schema                        | Value                                                     |
+//! | ------                        | -----                                                     |
+//! | bool                          | `true`                                                    |
+//! | integers                      | `123`, or `"123"` for 128-bit integers                    |
+//! | floats                        | `1.5`                                                     |
+//! | char, String                  | `"text"`                                                  |
+//! | byte array, sequences, tuples | `[1, 2, 3]`                                               |
+//! | Option                        | `null` or the value                                       |
+//! | unit, unit structs            | `null`                                                    |
+//! | newtype structs               | the inner value                                           |
+//! | maps                          | `{"key": value}`, or `[[key, value]]` for non-string keys |
+//! | structs                       | `{"field": value}`                                        |
+//! | enums                         | `"Unit"`, or `{"Variant": value}`                         |
+//! | schemas                       | the serialized [`OwnedNamedType`]                         |
+
+use postcard_schema::{
+    schema::owned::{OwnedDataModelType, OwnedDataModelVariant, OwnedNamedType, OwnedNamedValue},
+    Schema,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Number};
+
+pub use serde_json::Value;
+
+use crate::{
+    header::{VarHeader, VarKey},
+    host_client::{EndpointReport, HostClient, HostErr, RpcFrame},
+};
+
+/// An error converting between a [`Value`] and its serialized form
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    /// The value does not match the schema
+    Mismatch {
+        /// Where in the value the mismatch is, like `.field[3]`
+        path: String,
+        /// A description of what the schema expects
+        expected: String,
+    },
+    /// The serialized data is not valid for the schema
+    Invalid {
+        /// Where in the value the invalid data is, like `.field[3]`
+        path: String,
+    },
+    /// The serialized data ended before the value was complete
+    UnexpectedEnd,
+    /// The serialized data contained more than the value
+    TrailingBytes,
+}
+
+impl ValueError {
+    fn mismatch(expected: impl Into<String>) -> Self {
+        Self::Mismatch {
+            path: String::new(),
+            expected: expected.into(),
+        }
+    }
+
+    fn invalid() -> Self {
+        Self::Invalid {
+            path: String::new(),
+        }
+    }
+
+    /// Prepend a path segment, used when returning from nested values
+    fn at(mut self, segment: impl core::fmt::Display) -> Self {
+        match &mut self {
+            Self::Mismatch { path, .. } | Self::Invalid { path } => {
+                *path = format!("{segment}{path}");
+            }
+            Self::UnexpectedEnd | Self::TrailingBytes => {}
+        }
+        self
+    }
+}
+
+/// Errors of [`HostClient::send_resp_dynamic`]
+#[derive(Debug, PartialEq)]
+pub enum DynamicErr<WireErr> {
+    /// Sending the request or receiving the response failed
+    Host(HostErr<WireErr>),
+    /// The request didn't match the schema, or the response could not be decoded
+    Value(ValueError),
+}
+
+impl<WireErr> From<HostErr<WireErr>> for DynamicErr<WireErr> {
+    fn from(value: HostErr<WireErr>) -> Self {
+        Self::Host(value)
+    }
+}
+
+impl<WireErr> From<ValueError> for DynamicErr<WireErr> {
+    fn from(value: ValueError) -> Self {
+        Self::Value(value)
+    }
+}
+
+/// Serialize `value` as the type `ty`
+pub fn to_postcard(ty: &OwnedNamedType, value: &Value) -> Result<Vec<u8>, ValueError> {
+    let mut out = vec![];
+    encode(&ty.ty, value, &mut out)?;
+    Ok(out)
+}
+
+/// Deserialize `bytes` as the type `ty`
+///
+/// All of `bytes` must be used by the value.
+pub fn from_postcard(ty: &OwnedNamedType, bytes: &[u8]) -> Result<Value, ValueError> {
+    let mut dec = Decoder { buf: bytes };
+    let value = dec.decode(&ty.ty)?;
+    if !dec.buf.is_empty() {
+        return Err(ValueError::TrailingBytes);
+    }
+    Ok(value)
+}
+
+/// # Dynamic Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Perform an endpoint request/response, using the types of an [`EndpointReport`]
+    /// instead of an [`Endpoint`][crate::Endpoint] type.
+    ///
+    /// `req` is serialized as [`EndpointReport::req_ty`], and the response is
+    /// deserialized as [`EndpointReport::resp_ty`]. See the [module docs][self]
+    /// for how values are represented.
+    pub async fn send_resp_dynamic(
+        &self,
+        ep: &EndpointReport,
+        req: &Value,
+    ) -> Result<Value, DynamicErr<WireErr>> {
+        let body = to_postcard(&ep.req_ty, req)?;
+        let seq = self.ctx.seqs.allocate().await;
+        let frame = RpcFrame {
+            header: VarHeader {
+                key: VarKey::Key8(ep.req_key),
+                seq_no: seq.seq_no(),
+            },
+            body,
+        };
+        let resp = self
+            .send_resp_raw_inner(frame, ep.resp_key, self.ctx.resp_timeout)
+            .await?;
+        Ok(from_postcard(&ep.resp_ty, &resp.body)?)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// ENCODING
+//////////////////////////////////////////////////////////////////////////////
+
+fn encode(ty: &OwnedDataModelType, value: &Value, out: &mut Vec<u8>) -> Result<(), ValueError> {
+    use OwnedDataModelType as T;
+    match ty {
+        T::Bool => {
+            let b = value
+                .as_bool()
+                .ok_or_else(|| ValueError::mismatch("a bool"))?;
+            out.push(b as u8);
+        }
+        T::U8 => out.push(uint(value, u8::MAX.into(), "a u8")? as u8),
+        T::U16 => put_varint(out, uint(value, u16::MAX.into(), "a u16")?),
+        T::U32 => put_varint(out, uint(value, u32::MAX.into(), "a u32")?),
+        T::U64 | T::Usize => put_varint(out, uint(value, u64::MAX.into(), "a u64")?),
+        T::U128 => put_varint(out, uint(value, u128::MAX, "a u128")?),
+        T::I8 => out.push(int(value, i8::MIN.into(), i8::MAX.into(), "an i8")? as u8),
+        T::I16 => put_varint(
+            out,
+            zigzag(int(value, i16::MIN.into(), i16::MAX.into(), "an i16")?),
+        ),
+        T::I32 => put_varint(
+            out,
+            zigzag(int(value, i32::MIN.into(), i32::MAX.into(), "an i32")?),
+        ),
+        T::I64 | T::Isize => put_varint(
+            out,
+            zigzag(int(value, i64::MIN.into(), i64::MAX.into(), "an i64")?),
+        ),
+        T::I128 => put_varint(out, zigzag(int(value, i128::MIN, i128::MAX, "an i128")?)),
+        T::F32 => {
+            let f = value
+                .as_f64()
+                .ok_or_else(|| ValueError::mismatch("an f32"))?;
+            out.extend_from_slice(&(f as f32).to_le_bytes());
+        }
+        T::F64 => {
+            let f = value
+                .as_f64()
+                .ok_or_else(|| ValueError::mismatch("an f64"))?;
+            out.extend_from_slice(&f.to_le_bytes());
+        }
+        T::Char => {
+            let mut chars = value.as_str().unwrap_or_default().chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(ValueError::mismatch("a string with a single char"));
+            };
+            put_bytes(out, c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        T::String => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| ValueError::mismatch("a string"))?;
+            put_bytes(out, s.as_bytes());
+        }
+        T::ByteArray => {
+            let arr = value
+                .as_array()
+                .ok_or_else(|| ValueError::mismatch("an array of bytes"))?;
+            put_varint(out, arr.len() as u128);
+            for (i, b) in arr.iter().enumerate() {
+                let b = uint(b, u8::MAX.into(), "a u8").map_err(|e| e.at(format!("[{i}]")))?;
+                out.push(b as u8);
+            }
+        }
+        T::Option(inner) => {
+            if value.is_null() {
+                out.push(0);
+            } else {
+                out.push(1);
+                encode(&inner.ty, value, out)?;
+            }
+        }
+        T::Unit | T::UnitStruct => {
+            if !value.is_null() {
+                return Err(ValueError::mismatch("null"));
+            }
+        }
+        T::NewtypeStruct(inner) => encode(&inner.ty, value, out)?,
+        T::Seq(inner) => {
+            let arr = value
+                .as_array()
+                .ok_or_else(|| ValueError::mismatch("an array"))?;
+            put_varint(out, arr.len() as u128);
+            for (i, v) in arr.iter().enumerate() {
+                encode(&inner.ty, v, out).map_err(|e| e.at(format!("[{i}]")))?;
+            }
+        }
+        T::Tuple(tys) | T::TupleStruct(tys) => encode_tuple(tys, value, out)?,
+        T::Map { key, val } => match value {
+            Value::Object(map) => {
+                put_varint(out, map.len() as u128);
+                for (k, v) in map {
+                    let seg = format!("[{k:?}]");
+                    encode(&key.ty, &Value::String(k.clone()), out).map_err(|e| e.at(&seg))?;
+                    encode(&val.ty, v, out).map_err(|e| e.at(&seg))?;
+                }
+            }
+            Value::Array(pairs) => {
+                put_varint(out, pairs.len() as u128);
+                for (i, pair) in pairs.iter().enumerate() {
+                    let seg = format!("[{i}]");
+                    let Some([k, v]) = pair.as_array().map(Vec::as_slice) else {
+                        return Err(ValueError::mismatch("a [key, value] pair").at(seg));
+                    };
+                    encode(&key.ty, k, out).map_err(|e| e.at(&seg))?;
+                    encode(&val.ty, v, out).map_err(|e| e.at(&seg))?;
+                }
+            }
+            _ => {
+                return Err(ValueError::mismatch(
+                    "an object, or an array of [key, value] pairs",
+                ))
+            }
+        },
+        T::Struct(fields) => encode_struct(fields, value, out)?,
+        T::Enum(variants) => {
+            let (name, inner) = match value {
+                Value::String(name) => (name, &Value::Null),
+                Value::Object(map) if map.len() == 1 => map.iter().next().unwrap(),
+                _ => {
+                    return Err(ValueError::mismatch(
+                        "a variant name, or an object with a single variant",
+                    ))
+                }
+            };
+            let Some(idx) = variants.iter().position(|v| v.name == *name) else {
+                let names: Vec<&str> = variants.iter().map(|v| v.name.as_str()).collect();
+                return Err(ValueError::mismatch(format!("one of {names:?}")));
+            };
+            put_varint(out, idx as u128);
+            let seg = format!(".{name}");
+            let res = match &variants[idx].ty {
+                OwnedDataModelVariant::UnitVariant if inner.is_null() => Ok(()),
+                OwnedDataModelVariant::UnitVariant => Err(ValueError::mismatch("null")),
+                OwnedDataModelVariant::NewtypeVariant(ty) => encode(&ty.ty, inner, out),
+                OwnedDataModelVariant::TupleVariant(tys) => encode_tuple(tys, inner, out),
+                OwnedDataModelVariant::StructVariant(fields) => encode_struct(fields, inner, out),
+            };
+            res.map_err(|e| e.at(seg))?;
+        }
+        T::Schema => {
+            let nt = serde_json::from_value::<OwnedNamedType>(value.clone())
+                .map_err(|_| ValueError::mismatch("a schema"))?;
+            out.extend(postcard::to_stdvec(&nt).expect("Allocations should not ever fail"));
+        }
+    }
+    Ok(())
+}
+
+fn encode_tuple(
+    tys: &[OwnedNamedType],
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ValueError> {
+    let arr = match value.as_array() {
+        Some(arr) if arr.len() == tys.len() => arr,
+        _ => {
+            return Err(ValueError::mismatch(format!(
+                "an array of length {}",
+                tys.len()
+            )))
+        }
+    };
+    for (i, (ty, v)) in tys.iter().zip(arr).enumerate() {
+        encode(&ty.ty, v, out).map_err(|e| e.at(format!("[{i}]")))?;
+    }
+    Ok(())
+}
+
+fn encode_struct(
+    fields: &[OwnedNamedValue],
+    value: &Value,
+    out: &mut Vec<u8>,
+) -> Result<(), ValueError> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| ValueError::mismatch("an object"))?;
+    // Catch typos, instead of only reporting the field as missing
+    if let Some(k) = obj.keys().find(|k| !fields.iter().any(|f| f.name == **k)) {
+        return Err(ValueError::mismatch("no such field").at(format!(".{k}")));
+    }
+    for field in fields {
+        let seg = format!(".{}", field.name);
+        let v = obj
+            .get(&field.name)
+            .ok_or_else(|| ValueError::mismatch("a value").at(&seg))?;
+        encode(&field.ty.ty, v, out).map_err(|e| e.at(&seg))?;
+    }
+    Ok(())
+}
+
+/// Get an unsigned integer, from a number or a string
+fn uint(value: &Value, max: u128, expected: &str) -> Result<u128, ValueError> {
+    let v = match value {
+        Value::Number(n) => n.as_u64().map(u128::from),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    v.filter(|v| *v <= max)
+        .ok_or_else(|| ValueError::mismatch(expected))
+}
+
+/// Get a signed integer, from a number or a string
+fn int(value: &Value, min: i128, max: i128, expected: &str) -> Result<i128, ValueError> {
+    let v = match value {
+        Value::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from)),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    };
+    v.filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| ValueError::mismatch(expected))
+}
+
+fn zigzag(v: i128) -> u128 {
+    ((v << 1) ^ (v >> 127)) as u128
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u128) {
+    loop {
+        let byte = (v & 0x7F) as u8;
+        v >>= 7;
+        if v == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(out, bytes.len() as u128);
+    out.extend_from_slice(bytes);
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// DECODING
+//////////////////////////////////////////////////////////////////////////////
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ValueError> {
+        if self.buf.len() < n {
+            return Err(ValueError::UnexpectedEnd);
+        }
+        let (now, later) = self.buf.split_at(n);
+        self.buf = later;
+        Ok(now)
+    }
+
+    fn byte(&mut self) -> Result<u8, ValueError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self, max: u128) -> Result<u128, ValueError> {
+        let mut v = 0u128;
+        // A u128 takes at most 19 bytes, the last one only holding two bits
+        for i in 0..19 {
+            let byte = self.byte()?;
+            if i == 18 && byte > 0x03 {
+                break;
+            }
+            v |= u128::from(byte & 0x7F) << (7 * i);
+            if byte & 0x80 == 0 {
+                return if v <= max {
+                    Ok(v)
+                } else {
+                    Err(ValueError::invalid())
+                };
+            }
+        }
+        Err(ValueError::invalid())
+    }
+
+    fn signed(&mut self, bits: u32) -> Result<Value, ValueError> {
+        let v = self.varint(u128::MAX >> (128 - bits))?;
+        let v = ((v >> 1) as i128) ^ -((v & 1) as i128);
+        Ok(match i64::try_from(v) {
+            Ok(v) => Value::from(v),
+            Err(_) => Value::String(v.to_string()),
+        })
+    }
+
+    fn float(&mut self, f: f64) -> Result<Value, ValueError> {
+        // JSON can't represent NaN or infinities
+        Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(ValueError::invalid)
+    }
+
+    fn len(&mut self) -> Result<usize, ValueError> {
+        let len = self.varint(u64::MAX.into())?;
+        usize::try_from(len).map_err(|_| ValueError::invalid())
+    }
+
+    fn str(&mut self) -> Result<&'a str, ValueError> {
+        let len = self.len()?;
+        core::str::from_utf8(self.take(len)?).map_err(|_| ValueError::invalid())
+    }
+
+    fn decode(&mut self, ty: &OwnedDataModelType) -> Result<Value, ValueError> {
+        use OwnedDataModelType as T;
+        Ok(match ty {
+            T::Bool => match self.byte()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err(ValueError::invalid()),
+            },
+            T::U8 => Value::from(self.byte()?),
+            T::U16 => Value::from(self.varint(u16::MAX.into())? as u16),
+            T::U32 => Value::from(self.varint(u32::MAX.into())? as u32),
+            T::U64 | T::Usize => Value::from(self.varint(u64::MAX.into())? as u64),
+            T::U128 => {
+                let v = self.varint(u128::MAX)?;
+                match u64::try_from(v) {
+                    Ok(v) => Value::from(v),
+                    Err(_) => Value::String(v.to_string()),
+                }
+            }
+            T::I8 => Value::from(self.byte()? as i8),
+            T::I16 => self.signed(16)?,
+            T::I32 => self.signed(32)?,
+            T::I64 | T::Isize => self.signed(64)?,
+            T::I128 => self.signed(128)?,
+            T::F32 => {
+                let bytes = self.take(4)?.try_into().unwrap();
+                self.float(f32::from_le_bytes(bytes).into())?
+            }
+            T::F64 => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                self.float(f64::from_le_bytes(bytes))?
+            }
+            T::Char => {
+                let s = self.str()?;
+                if s.chars().count() != 1 {
+                    return Err(ValueError::invalid());
+                }
+                Value::String(s.to_string())
+            }
+            T::String => Value::String(self.str()?.to_string()),
+            T::ByteArray => {
+                let len = self.len()?;
+                self.take(len)?.iter().copied().map(Value::from).collect()
+            }
+            T::Option(inner) => match self.byte()? {
+                0 => Value::Null,
+                1 => self.decode(&inner.ty)?,
+                _ => return Err(ValueError::invalid()),
+            },
+            T::Unit | T::UnitStruct => Value::Null,
+            T::NewtypeStruct(inner) => self.decode(&inner.ty)?,
+            T::Seq(inner) => {
+                let len = self.len()?;
+                let mut arr = vec![];
+                for i in 0..len {
+                    arr.push(self.decode(&inner.ty).map_err(|e| e.at(format!("[{i}]")))?);
+                }
+                Value::Array(arr)
+            }
+            T::Tuple(tys) | T::TupleStruct(tys) => self.decode_tuple(tys)?,
+            T::Map { key, val } => {
+                let len = self.len()?;
+                let string_keys = matches!(key.ty, T::String | T::Char);
+                let mut map = Map::new();
+                let mut pairs = vec![];
+                for i in 0..len {
+                    let seg = format!("[{i}]");
+                    let k = self.decode(&key.ty).map_err(|e| e.at(&seg))?;
+                    let v = self.decode(&val.ty).map_err(|e| e.at(&seg))?;
+                    match k {
+                        Value::String(k) if string_keys => {
+                            map.insert(k, v);
+                        }
+                        k => pairs.push(Value::Array(vec![k, v])),
+                    }
+                }
+                if string_keys {
+                    Value::Object(map)
+                } else {
+                    Value::Array(pairs)
+                }
+            }
+            T::Struct(fields) => self.decode_struct(fields)?,
+            T::Enum(variants) => {
+                let idx = self.varint(u32::MAX.into())?;
+                let variant = variants.get(idx as usize).ok_or_else(ValueError::invalid)?;
+                let seg = format!(".{}", variant.name);
+                let inner = match &variant.ty {
+                    OwnedDataModelVariant::UnitVariant => {
+                        return Ok(Value::String(variant.name.clone()))
+                    }
+                    OwnedDataModelVariant::NewtypeVariant(ty) => self.decode(&ty.ty),
+                    OwnedDataModelVariant::TupleVariant(tys) => self.decode_tuple(tys),
+                    OwnedDataModelVariant::StructVariant(fields) => self.decode_struct(fields),
+                };
+                let mut map = Map::new();
+                map.insert(variant.name.clone(), inner.map_err(|e| e.at(seg))?);
+                Value::Object(map)
+            }
+            T::Schema => {
+                let (nt, rest) = postcard::take_from_bytes::<OwnedNamedType>(self.buf)
+                    .map_err(|_| ValueError::invalid())?;
+                self.buf = rest;
+                serde_json::to_value(nt).expect("Schemas should always be representable")
+            }
+        })
+    }
+
+    fn decode_tuple(&mut self, tys: &[OwnedNamedType]) -> Result<Value, ValueError> {
+        let mut arr = vec![];
+        for (i, ty) in tys.iter().enumerate() {
+            arr.push(self.decode(&ty.ty).map_err(|e| e.at(format!("[{i}]")))?);
+        }
+        Ok(Value::Array(arr))
+    }
+
+    fn decode_struct(&mut self, fields: &[OwnedNamedValue]) -> Result<Value, ValueError> {
+        let mut map = Map::new();
+        for field in fields {
+            let v = self
+                .decode(&field.ty.ty)
+                .map_err(|e| e.at(format!(".{}", field.name)))?;
+            map.insert(field.name.clone(), v);
+        }
+        Ok(Value::Object(map))
+    }
+}
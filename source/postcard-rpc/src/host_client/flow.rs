@@ -0,0 +1,215 @@
+//! Host side of topic flow control
+
+use std::time::Duration;
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+
+use crate::{
+    host_client::{HostClient, HostErr, SubscribeError, Subscription},
+    standard_icd::{
+        ProtocolInfo, TopicFlowEndpoint, TopicFlowError, TopicFlowRequest, TopicFlowStats,
+    },
+    Key, Topic,
+};
+
+/// The time to wait for the server to acknowledge a grant of credits, and
+/// between retries of a grant the server rejected
+const GRANT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Counters of the messages received for an exclusive subscription
+///
+/// Kept per topic for the lifetime of the [`HostClient`], across re-subscribing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TopicStats {
+    /// Messages passed to the subscription
+    pub received: u64,
+    /// Messages passed to the subscription only after waiting for room, see
+    /// [`HostClientConfig::subscriber_timeout_if_full`][crate::host_client::HostClientConfig::subscriber_timeout_if_full]
+    pub late: u64,
+    /// Messages dropped, because the subscription was full or closed
+    pub dropped: u64,
+}
+
+/// An error returned by [`HostClient::subscribe_flow()`]
+#[derive(Debug)]
+pub enum FlowSubscribeError<WireErr> {
+    /// The window was zero, which would never allow a message
+    ZeroWindow,
+    /// Subscribing failed
+    Subscribe(SubscribeError),
+    /// Granting the initial credits failed
+    Grant(HostErr<WireErr, TopicFlowError>),
+}
+
+/// # Flow Control Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Subscribe to a flow-controlled [Topic]
+    ///
+    /// Like [`Self::subscribe_exclusive()`] with a depth of `window`, but the server
+    /// only publishes up to `window` messages that have not been received yet,
+    /// dropping the rest on the server side. Messages are never dropped by the
+    /// client. Credits are granted again as messages are received, in the
+    /// background, retrying until the server acknowledges them.
+    ///
+    /// The server must handle the flow endpoint, and publish the topic with
+    /// `Sender::publish_flow()`, see `postcard_rpc::server::flow`. Fails with
+    /// [`HostErr::Unsupported`] if the negotiated protocol lacks
+    /// [`ProtocolInfo::CAP_TOPIC_FLOW`].
+    pub async fn subscribe_flow<T: Topic>(
+        &self,
+        window: u32,
+    ) -> Result<FlowSubscription<T::Message, WireErr>, FlowSubscribeError<WireErr>>
+    where
+        T::Message: DeserializeOwned,
+        WireErr: Send + 'static,
+    {
+        if window == 0 {
+            return Err(FlowSubscribeError::ZeroWindow);
+        }
+        self.require(ProtocolInfo::CAP_TOPIC_FLOW)
+            .map_err(FlowSubscribeError::Grant)?;
+        let sub = self
+            .subscribe_exclusive::<T>(window as usize)
+            .await
+            .map_err(FlowSubscribeError::Subscribe)?;
+        self.send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Grant {
+            key: T::TOPIC_KEY,
+            limit: window,
+        })
+        .await
+        .map_err(FlowSubscribeError::Grant)?;
+        let (limit, rx) = watch::channel(window);
+        // Explicitly drop the joinhandle, the worker stops with the subscription
+        core::mem::drop(tokio::task::spawn(grant_worker(
+            self.clone(),
+            T::TOPIC_KEY,
+            rx,
+        )));
+        Ok(FlowSubscription {
+            client: self.clone(),
+            sub,
+            key: T::TOPIC_KEY,
+            window,
+            consumed: 0,
+            limit,
+        })
+    }
+
+    /// Get the counters of the exclusive subscription to the topic with the given key
+    ///
+    /// Returns all zeros if there never was a subscription.
+    pub async fn topic_stats(&self, key: Key) -> TopicStats {
+        self.subscriptions
+            .lock()
+            .await
+            .topic_stats
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, stats)| *stats)
+            .unwrap_or_default()
+    }
+}
+
+/// A subscription to a flow-controlled topic, created by [`HostClient::subscribe_flow()`]
+pub struct FlowSubscription<M, WireErr> {
+    client: HostClient<WireErr>,
+    sub: Subscription<M>,
+    key: Key,
+    window: u32,
+    /// Messages received, wrapping around like the limits of grants
+    consumed: u32,
+    /// The latest limit, granted by the [`grant_worker`]
+    limit: watch::Sender<u32>,
+}
+
+impl<M, WireErr> FlowSubscription<M, WireErr>
+where
+    M: DeserializeOwned,
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Await a message for the given subscription.
+    ///
+    /// Once half of the window has been received, the received messages are
+    /// granted to the server again. The grant is sent in the background, the
+    /// message is returned without waiting for the server's response.
+    ///
+    /// Returns [None]` if the subscription was closed
+    pub async fn recv(&mut self) -> Option<M> {
+        let msg = self.sub.recv().await?;
+        self.consumed = self.consumed.wrapping_add(1);
+        let limit = self.consumed.wrapping_add(self.window);
+        if limit.wrapping_sub(*self.limit.borrow()) >= self.window.div_ceil(2) {
+            self.limit.send_replace(limit);
+        }
+        Some(msg)
+    }
+
+    /// Get the counters of the server
+    pub async fn server_stats(&self) -> Result<TopicFlowStats, HostErr<WireErr, TopicFlowError>> {
+        self.client
+            .send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Stats { key: self.key })
+            .await
+    }
+
+    /// Get the counters of the client, see [`HostClient::topic_stats()`]
+    pub async fn stats(&self) -> TopicStats {
+        self.client.topic_stats(self.key).await
+    }
+
+    /// Disable flow control on the server, and end the subscription
+    pub async fn close(self) -> Result<TopicFlowStats, HostErr<WireErr, TopicFlowError>> {
+        let Self {
+            client,
+            sub,
+            key,
+            limit,
+            ..
+        } = self;
+        drop(sub);
+        drop(limit);
+        client
+            .send_resp_fallible::<TopicFlowEndpoint>(&TopicFlowRequest::Disable { key })
+            .await
+    }
+}
+
+/// Grant worker, sending the latest limit of a [`FlowSubscription`] to the server
+///
+/// Grants are retried until the server acknowledges them. As they are absolute,
+/// a grant the server applied without the client noticing does no harm. Runs
+/// until the subscription or the client is closed.
+async fn grant_worker<WireErr>(
+    client: HostClient<WireErr>,
+    key: Key,
+    mut limit: watch::Receiver<u32>,
+) where
+    WireErr: DeserializeOwned + Schema,
+{
+    while limit.changed().await.is_ok() {
+        loop {
+            let req = TopicFlowRequest::Grant {
+                key,
+                limit: *limit.borrow_and_update(),
+            };
+            let grant = client.send_resp_fallible::<TopicFlowEndpoint>(&req);
+            match tokio::time::timeout(GRANT_TIMEOUT, grant).await {
+                Ok(Ok(_)) => break,
+                Ok(Err(HostErr::Closed)) => return,
+                Ok(Err(_)) => {
+                    tracing::warn!("The server rejected {req:?}, retrying");
+                    tokio::time::sleep(GRANT_TIMEOUT).await;
+                }
+                Err(_) => tracing::warn!("The server did not acknowledge {req:?}, retrying"),
+            }
+            // The subscription was dropped
+            if limit.has_changed().is_err() {
+                return;
+            }
+        }
+    }
+}
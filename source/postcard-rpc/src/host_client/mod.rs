@@ -3,15 +3,12 @@
 //! This library is meant to be used with the `Dispatch` type and the
 //! postcard-rpc wire protocol.
 
-use core::time::Duration;
+use core::{convert::Infallible, time::Duration};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     future::Future,
     marker::PhantomData,
-    sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc, RwLock,
-    },
+    sync::{Arc, RwLock},
 };
 
 use maitake_sync::{
@@ -22,62 +19,153 @@ use postcard_schema::{schema::owned::OwnedNamedType, Schema};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::{
     select,
-    sync::{broadcast, mpsc, Mutex},
+    sync::{broadcast, mpsc, watch, Mutex},
 };
 use util::Subscriptions;
 
 use crate::{
     header::{VarHeader, VarKey, VarKeyKind, VarSeq, VarSeqKind},
-    standard_icd::{GetAllSchemaDataTopic, GetAllSchemasEndpoint, OwnedSchemaData},
-    Endpoint, Key, Topic, TopicDirection,
+    standard_icd::{
+        GetAllSchemaDataTopic, GetAllSchemasEndpoint, OwnedSchemaData, ProtocolAgreement,
+        TopicSubscriptionRequest,
+    },
+    Endpoint, FallibleEndpoint, Key, Topic, TopicDirection,
 };
 
-use self::util::Stopper;
+use self::{
+    announce::TopicGuard,
+    sched::Scheduler,
+    seq::{SeqAllocator, SeqGuard},
+    util::Stopper,
+};
+pub use crate::host_client::compat::{KeyCollision, MessageKind, SchemaDiff, TypeMismatch};
+pub use crate::host_client::flow::{FlowSubscribeError, FlowSubscription, TopicStats};
+pub use crate::host_client::retry::RetryPolicy;
+pub use crate::host_client::sched::{Priority, QueueStats};
+pub use crate::host_client::stream::ResponseStream;
 pub use crate::host_client::util::HostClientConfig;
 
+#[cfg(all(feature = "raw-nusb", not(target_family = "wasm")))]
+pub use crate::host_client::device_manager::{DeviceEvent, DeviceManager};
+
+#[cfg(all(feature = "raw-nusb", not(target_family = "wasm")))]
+pub use crate::host_client::raw_nusb::RawNusbConfig;
+
 #[cfg(all(feature = "raw-nusb", not(target_family = "wasm")))]
 mod raw_nusb;
 
+#[cfg(all(feature = "raw-nusb", not(target_family = "wasm")))]
+mod device_manager;
+
 #[cfg(all(feature = "cobs-serial", not(target_family = "wasm")))]
 mod serial;
 
+#[cfg(all(feature = "tcp", not(target_family = "wasm")))]
+mod tcp;
+
+#[cfg(all(feature = "udp", not(target_family = "wasm")))]
+mod udp;
+
 #[cfg(all(feature = "webusb", target_family = "wasm"))]
 pub mod webusb;
 
+mod announce;
+
+mod blob;
+
+#[cfg(not(target_family = "wasm"))]
+pub mod capture;
+
+mod compat;
+
+mod flow;
+
+#[cfg(feature = "dynamic")]
+pub mod decode;
+
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
+
+mod protocol;
+
+mod retry;
+
+mod sched;
+
+mod seq;
+
+mod stream;
+
+mod upload;
+
+#[cfg(not(target_family = "wasm"))]
+mod reconnect;
+
 pub(crate) mod util;
 
 #[cfg(feature = "test-utils")]
 pub mod test_channels;
 
 /// Host Error Kind
+///
+/// `EndpointErr` is the typed error of a [`FallibleEndpoint`], and is only
+/// used by [`HostClient::send_resp_fallible`].
 #[derive(Debug, PartialEq)]
-pub enum HostErr<WireErr> {
+#[non_exhaustive]
+pub enum HostErr<WireErr, EndpointErr = Infallible> {
     /// An error of the user-specified wire error type
     Wire(WireErr),
+    /// The endpoint handler returned its typed error
+    Endpoint(EndpointErr),
     /// We got a response that didn't match the expected value or the
     /// user specified wire error type
-    ///
-    /// This is also (misused) to report when duplicate sequence numbers
-    /// in-flight at the same time are detected.
     BadResponse,
+    /// The sequence number of a raw request is already used by another
+    /// request in flight
+    DuplicateSeq,
+    /// The request frame is larger than the transport can carry, see
+    /// [`HostClientConfig::max_frame_len`], or an upload is larger than 4GiB
+    FrameTooLarge,
+    /// The server does not support the capability, according to the agreement
+    /// of [`HostClient::negotiate_protocol()`]
+    Unsupported,
     /// Deserialization of the message failed
     Postcard(postcard::Error),
     /// The interface has been closed, and no further messages are possible
     Closed,
+    /// No response was received before the timeout elapsed
+    Timeout,
 }
 
-impl<T> From<postcard::Error> for HostErr<T> {
+impl<T, E> From<postcard::Error> for HostErr<T, E> {
     fn from(value: postcard::Error) -> Self {
         Self::Postcard(value)
     }
 }
 
-impl<T> From<WaitError> for HostErr<T> {
+impl<T, E> From<WaitError> for HostErr<T, E> {
     fn from(_: WaitError) -> Self {
         Self::Closed
     }
 }
 
+impl<T> HostErr<T> {
+    /// Convert to a `HostErr` with a typed endpoint error
+    fn with_endpoint_err<E>(self) -> HostErr<T, E> {
+        match self {
+            HostErr::Wire(e) => HostErr::Wire(e),
+            HostErr::Endpoint(never) => match never {},
+            HostErr::BadResponse => HostErr::BadResponse,
+            HostErr::Postcard(e) => HostErr::Postcard(e),
+            HostErr::Closed => HostErr::Closed,
+            HostErr::Timeout => HostErr::Timeout,
+            HostErr::DuplicateSeq => HostErr::DuplicateSeq,
+            HostErr::FrameTooLarge => HostErr::FrameTooLarge,
+            HostErr::Unsupported => HostErr::Unsupported,
+        }
+    }
+}
+
 /// Wire Transmit Interface
 ///
 /// Responsible for taking a serialized frame (including header and payload),
@@ -170,17 +258,22 @@ pub trait WireSpawn: 'static {
 ///
 /// [HostClient]s can be cloned, and used across multiple tasks/threads.
 ///
-/// There are currently two ways to create one, based on the transport used:
+/// There are currently four ways to create one, based on the transport used:
 ///
 /// 1. With raw USB Bulk transfers: [`HostClient::new_raw_nusb()`] (**recommended**)
 /// 2. With cobs CDC-ACM transfers: [`HostClient::new_serial_cobs()`]
+/// 3. With cobs over TCP: [`HostClient::new_tcp()`]
+/// 4. With one datagram per frame over UDP: [`HostClient::new_udp()`]
 pub struct HostClient<WireErr> {
     ctx: Arc<HostContext>,
     out: mpsc::Sender<RpcFrame>,
+    topic_release: mpsc::UnboundedSender<Key>,
     subscriptions: Arc<Mutex<Subscriptions>>,
     err_key: Key,
     stopper: Stopper,
     seq_kind: VarSeqKind,
+    retries: Arc<RwLock<HashMap<Key, RetryPolicy<WireErr>>>>,
+    priorities: Arc<RwLock<HashMap<Key, Priority>>>,
     _pd: PhantomData<fn() -> WireErr>,
 }
 
@@ -190,14 +283,26 @@ where
     WireErr: DeserializeOwned + Schema,
 {
     /// Private method for creating internal context
-    pub(crate) fn new_manual_priv(config: &HostClientConfig) -> (Self, WireContext) {
+    ///
+    /// Also returns the receiving end of the ended subscriptions, for the topic worker.
+    pub(crate) fn new_manual_priv(
+        config: &HostClientConfig,
+    ) -> (Self, WireContext, mpsc::UnboundedReceiver<Key>) {
         let (tx_pc, rx_pc) = tokio::sync::mpsc::channel(config.outgoing_depth);
+        let (release_tx, release_rx) = mpsc::unbounded_channel();
 
         let ctx = Arc::new(HostContext {
             kkind: RwLock::new(VarKeyKind::Key8),
             map: WaitMap::new(),
-            seq: AtomicU32::new(0),
+            seqs: SeqAllocator::new(config.seq_kind),
+            sched: Scheduler::new(config.max_in_flight),
+            protocol: RwLock::new(None),
+            negotiate_protocol: config.negotiate_protocol,
+            announce_lock: Mutex::new(()),
             subscription_timeout: config.subscriber_timeout_if_full,
+            resp_timeout: config.resp_timeout,
+            max_frame_len: config.max_frame_len,
+            state: watch::Sender::new(ConnectionState::Connected),
         });
 
         let err_key = Key::for_path::<WireErr>(config.err_uri_path);
@@ -205,11 +310,14 @@ where
         let me = HostClient {
             ctx: ctx.clone(),
             out: tx_pc,
+            topic_release: release_tx,
             err_key,
             _pd: PhantomData,
             subscriptions: Arc::new(Mutex::new(Subscriptions::default())),
             stopper: Stopper::new(),
             seq_kind: config.seq_kind,
+            retries: Arc::new(RwLock::new(HashMap::new())),
+            priorities: Arc::new(RwLock::new(HashMap::new())),
         };
 
         let wire = WireContext {
@@ -217,12 +325,13 @@ where
             incoming: ctx,
         };
 
-        (me, wire)
+        (me, wire, release_rx)
     }
 }
 
 /// Errors related to retrieving the schema
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum SchemaError<WireErr> {
     /// Some kind of communication error occurred
     Comms(HostErr<WireErr>),
@@ -234,6 +343,8 @@ pub enum SchemaError<WireErr> {
     /// Data was lost while transmitting. If a retry does not solve
     /// this, please open an issue.
     LostData,
+    /// The device does not speak the ICD expected by the host
+    Incompatible(SchemaDiff),
 }
 
 impl<WireErr> From<UnableToFindType> for SchemaError<WireErr> {
@@ -323,7 +434,14 @@ where
     /// Send a message of type [Endpoint::Request][Endpoint] to `path`, and await
     /// a response of type [Endpoint::Response][Endpoint] (or WireErr) to `path`.
     ///
-    /// This function will wait potentially forever. Consider using with a timeout.
+    /// If [`HostClientConfig::resp_timeout`] was set, this returns [`HostErr::Timeout`]
+    /// if no response arrives in time. Otherwise, this function will wait potentially
+    /// forever. Consider using [`Self::send_resp_timeout`] instead.
+    ///
+    /// If a [`RetryPolicy`] was registered for `E` with [`Self::set_retry_policy`],
+    /// the request is retried according to that policy.
+    ///
+    /// For a [`FallibleEndpoint`], consider [`Self::send_resp_fallible`] instead.
     pub async fn send_resp<E: Endpoint>(
         &self,
         t: &E::Request,
@@ -332,33 +450,169 @@ where
         E::Request: Serialize + Schema,
         E::Response: DeserializeOwned + Schema,
     {
-        let seq_no = self.ctx.seq.fetch_add(1, Ordering::Relaxed);
+        if let Some(policy) = self.retry_policy::<E>() {
+            return self.send_resp_with_retry::<E>(t, &policy).await;
+        }
+
+        let (frame, _seq) = self.request_frame::<E>(t).await;
+        let frame = self
+            .send_resp_raw_inner(frame, E::RESP_KEY, self.ctx.resp_timeout)
+            .await?;
+        let r = postcard::from_bytes::<E::Response>(&frame.body)?;
+        Ok(r)
+    }
+
+    /// Like [`Self::send_resp`], but for a [`FallibleEndpoint`]
+    ///
+    /// If the endpoint handler fails, its typed error is returned as
+    /// [`HostErr::Endpoint`], distinct from the protocol-level [`HostErr::Wire`].
+    pub async fn send_resp_fallible<E: FallibleEndpoint>(
+        &self,
+        t: &E::Request,
+    ) -> Result<E::Ok, HostErr<WireErr, E::Error>>
+    where
+        E::Request: Serialize + Schema,
+        E::Ok: DeserializeOwned,
+        E::Error: DeserializeOwned,
+    {
+        match self.send_resp::<E>(t).await {
+            Ok(Ok(resp)) => Ok(resp),
+            Ok(Err(e)) => Err(HostErr::Endpoint(e)),
+            Err(e) => Err(e.with_endpoint_err()),
+        }
+    }
+
+    /// Like [`Self::send_resp`], but returns [`HostErr::Timeout`] if no response
+    /// arrives within `timeout`.
+    ///
+    /// This overrides [`HostClientConfig::resp_timeout`] for this request. On timeout,
+    /// a late response from the server is discarded.
+    pub async fn send_resp_timeout<E: Endpoint>(
+        &self,
+        t: &E::Request,
+        timeout: Duration,
+    ) -> Result<E::Response, HostErr<WireErr>>
+    where
+        E::Request: Serialize + Schema,
+        E::Response: DeserializeOwned + Schema,
+    {
+        let (frame, _seq) = self.request_frame::<E>(t).await;
+        let frame = self
+            .send_resp_raw_inner(frame, E::RESP_KEY, Some(timeout))
+            .await?;
+        let r = postcard::from_bytes::<E::Response>(&frame.body)?;
+        Ok(r)
+    }
+
+    /// Serialize a request, using the next free sequence number
+    ///
+    /// The sequence number stays reserved until the returned guard is dropped.
+    async fn request_frame<E: Endpoint>(&self, t: &E::Request) -> (RpcFrame, SeqGuard)
+    where
+        E::Request: Serialize + Schema,
+    {
+        let seq = self.ctx.seqs.allocate().await;
 
         let msg = postcard::to_stdvec(&t).expect("Allocations should not ever fail");
         let frame = RpcFrame {
-            // NOTE: send_resp_raw automatically shrinks down key and sequence
-            // kinds to the appropriate amount
+            // NOTE: send_resp_raw automatically shrinks down the key kind
+            // to the appropriate amount
             header: VarHeader {
                 key: VarKey::Key8(E::REQ_KEY),
-                seq_no: VarSeq::Seq4(seq_no),
+                seq_no: seq.seq_no(),
             },
             body: msg,
         };
-        let frame = self.send_resp_raw(frame, E::RESP_KEY).await?;
-        let r = postcard::from_bytes::<E::Response>(&frame.body)?;
-        Ok(r)
+        (frame, seq)
+    }
+
+    /// Is the frame larger than [`HostClientConfig::max_frame_len`]?
+    fn frame_too_large(&self, frame: &RpcFrame) -> bool {
+        self.ctx
+            .max_frame_len
+            .is_some_and(|max| frame.header.write_to_vec().len() + frame.body.len() > max)
+    }
+
+    /// Reserve the sequence number of a raw request, for as long as the guard is held
+    async fn reserve_seq<E>(&self, seq_no: VarSeq) -> Result<SeqGuard, HostErr<WireErr, E>> {
+        self.ctx.seqs.reserve(seq_no).await.ok_or_else(|| {
+            tracing::error!("Sequence number {seq_no:?} is already used by a request in flight");
+            HostErr::DuplicateSeq
+        })
     }
 
     /// Perform an endpoint request/response,but without handling the
     /// Ser/De automatically
+    ///
+    /// This uses [`HostClientConfig::resp_timeout`], if set.
+    ///
+    /// Returns [`HostErr::DuplicateSeq`] if the sequence number of `rqst` is
+    /// already used by another request in flight.
     pub async fn send_resp_raw(
+        &self,
+        rqst: RpcFrame,
+        resp_key: Key,
+    ) -> Result<RpcFrame, HostErr<WireErr>> {
+        let _seq = self.reserve_seq(rqst.header.seq_no).await?;
+        self.send_resp_raw_inner(rqst, resp_key, self.ctx.resp_timeout)
+            .await
+    }
+
+    /// Like [`Self::send_resp_raw`], but returns [`HostErr::Timeout`] if no response
+    /// arrives within `timeout`.
+    pub async fn send_resp_raw_timeout(
+        &self,
+        rqst: RpcFrame,
+        resp_key: Key,
+        timeout: Duration,
+    ) -> Result<RpcFrame, HostErr<WireErr>> {
+        let _seq = self.reserve_seq(rqst.header.seq_no).await?;
+        self.send_resp_raw_inner(rqst, resp_key, Some(timeout))
+            .await
+    }
+
+    /// Inner function version of [Self::send_resp_raw]
+    ///
+    /// The caller must hold the [`SeqGuard`] of the request's sequence number.
+    async fn send_resp_raw_inner(
+        &self,
+        rqst: RpcFrame,
+        resp_key: Key,
+        timeout: Option<Duration>,
+    ) -> Result<RpcFrame, HostErr<WireErr>> {
+        let operate_fut = self.send_resp_raw_untimed(rqst, resp_key);
+        let Some(timeout) = timeout else {
+            return operate_fut.await;
+        };
+        // On timeout, `operate_fut` is dropped, which also removes the pending
+        // waits for the response and error keys from the wait map, so there is
+        // no need to clean up manually. The same applies if the caller drops
+        // this future early.
+        match tokio::time::timeout(timeout, operate_fut).await {
+            Ok(res) => res,
+            Err(_) => Err(HostErr::Timeout),
+        }
+    }
+
+    /// Inner function version of [Self::send_resp_raw], without a timeout
+    async fn send_resp_raw_untimed(
         &self,
         mut rqst: RpcFrame,
         resp_key: Key,
     ) -> Result<RpcFrame, HostErr<WireErr>> {
+        // Wait for our turn, if the number of requests in flight is limited
+        let prio = self.priority_of_frame(&rqst.header.key);
+        let _slot = select! {
+            _ = self.stopper.wait_stopped() => return Err(HostErr::Closed),
+            slot = self.ctx.sched.acquire(prio) => slot,
+        };
+
         let cancel_fut = self.stopper.wait_stopped();
         let kkind: VarKeyKind = *self.ctx.kkind.read().unwrap();
         rqst.header.key.shrink_to(kkind);
+        if self.frame_too_large(&rqst) {
+            return Err(HostErr::FrameTooLarge);
+        }
         let mut resp_key = VarKey::Key8(resp_key);
         let mut err_key = VarKey::Key8(self.err_key);
         resp_key.shrink_to(kkind);
@@ -390,9 +644,7 @@ where
                 WaitError::Closed => HostErr::Closed,
                 WaitError::Duplicate => {
                     tracing::error!("Attempted to register a duplicate wait for a reply. This can happen if sequence numbers are reused.");
-                    // TODO: This is the wrong kind of error, but we don't want to report closed.
-                    // Fix this in the next breaking change of postcard-rpc, or make HostErr non-exhaustive
-                    HostErr::BadResponse
+                    HostErr::DuplicateSeq
                 }
 
                 // These should never happen: NeverAdded and AlreadyConsumed
@@ -429,8 +681,12 @@ where
     /// Publish a [Topic] [Message][Topic::Message].
     ///
     /// There is no feedback if the server received our message. If the I/O worker is
-    /// closed, an error is returned.
-    pub async fn publish<T: Topic>(&self, seq_no: VarSeq, msg: &T::Message) -> Result<(), IoClosed>
+    /// closed, or the message is too large, an error is returned.
+    pub async fn publish<T: Topic>(
+        &self,
+        seq_no: VarSeq,
+        msg: &T::Message,
+    ) -> Result<(), PublishError>
     where
         T::Message: Serialize,
     {
@@ -446,16 +702,22 @@ where
     }
 
     /// Publish the given raw frame
-    pub async fn publish_raw(&self, mut frame: RpcFrame) -> Result<(), IoClosed> {
+    ///
+    /// Frames larger than [`HostClientConfig::max_frame_len`] are not sent, and
+    /// [`PublishError::FrameTooLarge`] is returned.
+    pub async fn publish_raw(&self, mut frame: RpcFrame) -> Result<(), PublishError> {
         let kkind: VarKeyKind = *self.ctx.kkind.read().unwrap();
         frame.header.key.shrink_to(kkind);
+        if self.frame_too_large(&frame) {
+            return Err(PublishError::FrameTooLarge);
+        }
 
         let cancel_fut = self.stopper.wait_stopped();
         let operate_fut = self.out.send(frame);
 
         select! {
-            _ = cancel_fut => Err(IoClosed),
-            res = operate_fut => res.map_err(|_| IoClosed),
+            _ = cancel_fut => Err(PublishError::IoClosed),
+            res = operate_fut => res.map_err(|_| PublishError::IoClosed),
         }
     }
 
@@ -467,6 +729,11 @@ where
     /// stream of [Message][Topic::Message]s. Unlike `subscribe`, multiple subscribers
     /// to the same stream are allowed, and behave as a broadcast channel.
     ///
+    /// If the negotiated protocol includes
+    /// [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`][crate::standard_icd::ProtocolInfo::CAP_TOPIC_SUBSCRIPTION],
+    /// the server is told about the subscription, and when the last subscription
+    /// to the topic is dropped, see [`Self::unsubscribe()`].
+    ///
     /// Returns an Error if the I/O worker is closed.
     pub async fn subscribe_multi<T: Topic>(
         &self,
@@ -508,8 +775,11 @@ where
                 rx
             }
         };
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(T::TOPIC_KEY))
+            .await;
         Ok(MultiSubscription {
             rx,
+            _guard: self.topic_guard(T::TOPIC_KEY),
             _pd: PhantomData,
         })
     }
@@ -547,7 +817,12 @@ where
                 rx
             }
         };
-        Ok(RawMultiSubscription { rx })
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(key))
+            .await;
+        Ok(RawMultiSubscription {
+            rx,
+            _guard: self.topic_guard(key),
+        })
     }
 
     ///////////////////////////////////////////////////////////////////////////
@@ -606,8 +881,11 @@ where
                 guard.exclusive_list.push((T::TOPIC_KEY, tx));
             }
         }
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(T::TOPIC_KEY))
+            .await;
         Ok(Subscription {
             rx,
+            _guard: self.topic_guard(T::TOPIC_KEY),
             _pd: PhantomData,
         })
     }
@@ -650,7 +928,12 @@ where
                 guard.exclusive_list.push((key, tx));
             }
         }
-        Ok(RawSubscription { rx })
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(key))
+            .await;
+        Ok(RawSubscription {
+            rx,
+            _guard: self.topic_guard(key),
+        })
     }
 
     ///////////////////////////////////////////////////////////////////////////
@@ -664,6 +947,11 @@ where
     /// [`SubscribeError::AlreadySubscribed`] (there can be only one).
     /// This does not apply to subscriptions created with `subscribe_multi`.
     ///
+    /// If the negotiated protocol includes
+    /// [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`][crate::standard_icd::ProtocolInfo::CAP_TOPIC_SUBSCRIPTION],
+    /// the server is told about the subscription, and when the last subscription
+    /// to the topic is dropped, see [`Self::unsubscribe()`].
+    ///
     /// Returns an Error if the I/O worker is closed.
     pub async fn subscribe_exclusive<T: Topic>(
         &self,
@@ -707,8 +995,11 @@ where
                 guard.exclusive_list.push((T::TOPIC_KEY, tx));
             }
         }
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(T::TOPIC_KEY))
+            .await;
         Ok(Subscription {
             rx,
+            _guard: self.topic_guard(T::TOPIC_KEY),
             _pd: PhantomData,
         })
     }
@@ -754,7 +1045,37 @@ where
                 guard.exclusive_list.push((key, tx));
             }
         }
-        Ok(RawSubscription { rx })
+        self.announce_topic(TopicSubscriptionRequest::Subscribe(key))
+            .await;
+        Ok(RawSubscription {
+            rx,
+            _guard: self.topic_guard(key),
+        })
+    }
+
+    /// End all subscriptions to the topic with the given key
+    ///
+    /// The server is told to stop publishing the topic, like it is told to start
+    /// publishing when subscribing, see `postcard_rpc::server::subscriptions`.
+    /// This requires a negotiated protocol that includes
+    /// [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`][crate::standard_icd::ProtocolInfo::CAP_TOPIC_SUBSCRIPTION].
+    ///
+    /// Returns an Error if the I/O worker is closed.
+    pub async fn unsubscribe(&self, key: Key) -> Result<(), IoClosed> {
+        let cancel_fut = self.stopper.wait_stopped();
+        let operate_fut = async {
+            {
+                let mut guard = self.subscriptions.lock().await;
+                guard.exclusive_list.retain(|(k, _)| *k != key);
+                guard.broadcast_list.retain(|(k, _)| *k != key);
+            }
+            self.announce_topic(TopicSubscriptionRequest::Unsubscribe(key))
+                .await;
+        };
+        select! {
+            _ = cancel_fut => Err(IoClosed),
+            _ = operate_fut => Ok(()),
+        }
     }
 
     /// Permanently close the connection to the client
@@ -777,12 +1098,21 @@ where
     pub async fn wait_closed(&self) {
         self.stopper.wait_stopped().await;
     }
+
+    /// Watch the state of the connection to the device
+    ///
+    /// Clients that are not reconnecting start out [`ConnectionState::Connected`],
+    /// and become [`ConnectionState::Closed`] once the connection is lost.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.ctx.state.subscribe()
+    }
 }
 
 /// Like Subscription, but receives Raw frames that are not
 /// automatically deserialized
 pub struct RawSubscription {
     rx: mpsc::Receiver<RpcFrame>,
+    _guard: TopicGuard,
 }
 
 impl RawSubscription {
@@ -797,6 +1127,7 @@ impl RawSubscription {
 /// A structure that represents a subscription to the given topic
 pub struct Subscription<M> {
     rx: mpsc::Receiver<RpcFrame>,
+    _guard: TopicGuard,
     _pd: PhantomData<M>,
 }
 
@@ -821,6 +1152,7 @@ where
 /// automatically deserialized
 pub struct RawMultiSubscription {
     rx: broadcast::Receiver<RpcFrame>,
+    _guard: TopicGuard,
 }
 
 impl RawMultiSubscription {
@@ -839,6 +1171,7 @@ impl RawMultiSubscription {
 /// A structure that represents a subscription to the given topic
 pub struct MultiSubscription<M> {
     rx: broadcast::Receiver<RpcFrame>,
+    _guard: TopicGuard,
     _pd: PhantomData<M>,
 }
 
@@ -880,11 +1213,14 @@ impl<WireErr> Clone for HostClient<WireErr> {
         Self {
             ctx: self.ctx.clone(),
             out: self.out.clone(),
+            topic_release: self.topic_release.clone(),
             err_key: self.err_key,
             _pd: PhantomData,
             subscriptions: self.subscriptions.clone(),
             stopper: self.stopper.clone(),
             seq_kind: self.seq_kind,
+            retries: self.retries.clone(),
+            priorities: self.priorities.clone(),
         }
     }
 }
@@ -921,8 +1257,26 @@ impl RpcFrame {
 pub struct HostContext {
     kkind: RwLock<VarKeyKind>,
     map: WaitMap<VarHeader, (VarHeader, Vec<u8>)>,
-    seq: AtomicU32,
+    seqs: SeqAllocator,
+    sched: Scheduler,
+    protocol: RwLock<Option<ProtocolAgreement>>,
+    negotiate_protocol: bool,
+    announce_lock: Mutex<()>,
     subscription_timeout: Duration,
+    resp_timeout: Option<Duration>,
+    max_frame_len: Option<usize>,
+    state: watch::Sender<ConnectionState>,
+}
+
+/// The state of the connection to the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Trying to (re)connect to the device
+    Connecting,
+    /// Connected to the device
+    Connected,
+    /// The client has been closed, and will not reconnect
+    Closed,
 }
 
 /// The I/O worker has closed.
@@ -938,6 +1292,16 @@ pub enum SubscribeError {
     IoClosed,
 }
 
+/// An error returned by [`HostClient::publish()`] and [`HostClient::publish_raw()`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PublishError {
+    /// The frame is larger than [`HostClientConfig::max_frame_len`], and was not sent
+    FrameTooLarge,
+    /// The I/O worker has closed.
+    IoClosed,
+}
+
 /// Error for [HostContext::process].
 #[derive(Debug, PartialEq)]
 pub enum ProcessError {
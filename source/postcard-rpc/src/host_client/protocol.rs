@@ -0,0 +1,112 @@
+//! Host side of protocol version negotiation
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    header::VarHeader,
+    host_client::{HostClient, HostContext, HostErr},
+    standard_icd::{ProtocolAgreement, ProtocolEndpoint, ProtocolError, ProtocolInfo},
+};
+
+/// # Protocol Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Agree on a protocol version and capabilities with the server
+    ///
+    /// Sends the protocol versions supported by this crate to the
+    /// [`ProtocolEndpoint`], the server picks the highest version both sides
+    /// support. Once agreed, all frames are sent with the agreed version,
+    /// features the server lacks fail with [`HostErr::Unsupported`], see
+    /// [`HostClient::supports()`], and current subscriptions are announced if the
+    /// server agreed on [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`]. The agreement is kept until the connection is
+    /// lost, see [`HostClient::protocol()`], or negotiated again on every
+    /// (re)connect with
+    /// [`HostClientConfig::negotiate_protocol`][crate::host_client::HostClientConfig::negotiate_protocol].
+    ///
+    /// The negotiation itself uses version zero frames, and a failed negotiation
+    /// leaves no agreement. Servers that do not serve the endpoint reply with a
+    /// wire error, they only speak version zero.
+    pub async fn negotiate_protocol(
+        &self,
+    ) -> Result<ProtocolAgreement, HostErr<WireErr, ProtocolError>> {
+        *self.ctx.protocol.write().unwrap() = None;
+        let info = ProtocolInfo {
+            versions: VarHeader::SUPPORTED_VERSIONS,
+            capabilities: ProtocolInfo::CAP_ALL,
+        };
+        let agreement = self.send_resp_fallible::<ProtocolEndpoint>(&info).await?;
+        // The server must pick a version we offered
+        if !VarHeader::is_supported_version(agreement.version) {
+            return Err(HostErr::BadResponse);
+        }
+        *self.ctx.protocol.write().unwrap() = Some(agreement);
+        // The server may not know about subscriptions made before
+        self.reannounce_topics().await;
+        Ok(agreement)
+    }
+
+    /// Negotiate the protocol after connecting, if configured to
+    pub(crate) async fn negotiate_on_connect(&self) {
+        if !self.ctx.negotiate_protocol {
+            return;
+        }
+        match self.negotiate_protocol().await {
+            Ok(_) => {}
+            // The server does not serve the endpoint, and only speaks version zero
+            Err(HostErr::Wire(_)) => {}
+            Err(_) => tracing::warn!("Negotiating the protocol failed, using version zero"),
+        }
+    }
+
+    /// The protocol version agreed with the server, zero if none was negotiated
+    pub fn protocol_version(&self) -> u8 {
+        self.ctx.protocol_version()
+    }
+
+    /// The agreement of the last successful [`HostClient::negotiate_protocol()`]
+    ///
+    /// This is reset to `None` when a reconnecting client connects to a device.
+    pub fn protocol(&self) -> Option<ProtocolAgreement> {
+        *self.ctx.protocol.read().unwrap()
+    }
+
+    /// Does the server support all of the `capabilities`, see the `CAP_*` flags of [`ProtocolInfo`]?
+    ///
+    /// Without an agreement, e.g. with servers that do not serve the
+    /// [`ProtocolEndpoint`], all capabilities are assumed to be supported.
+    pub fn supports(&self, capabilities: u32) -> bool {
+        self.protocol()
+            .is_none_or(|p| p.capabilities & capabilities == capabilities)
+    }
+
+    /// Was the protocol negotiated, and does the agreement include all of the `capabilities`?
+    ///
+    /// Unlike [`Self::supports()`], this is `false` without an agreement.
+    pub(crate) fn agreed(&self, capabilities: u32) -> bool {
+        self.protocol()
+            .is_some_and(|p| p.capabilities & capabilities == capabilities)
+    }
+
+    /// Fail with [`HostErr::Unsupported`] unless the server supports the `capabilities`
+    pub(crate) fn require<E>(&self, capabilities: u32) -> Result<(), HostErr<WireErr, E>> {
+        if self.supports(capabilities) {
+            Ok(())
+        } else {
+            Err(HostErr::Unsupported)
+        }
+    }
+}
+
+impl HostContext {
+    /// The protocol version frames are sent with, zero if none was negotiated
+    pub(crate) fn protocol_version(&self) -> u8 {
+        self.protocol
+            .read()
+            .unwrap()
+            .map(|p| p.version)
+            .unwrap_or(0)
+    }
+}
@@ -1,32 +1,183 @@
 //! Implementation of transport using nusb
 
+use core::{pin::Pin, time::Duration};
 use std::future::Future;
 
+use futures_core::Stream;
 use nusb::{
+    hotplug::{HotplugEvent, HotplugWatch},
     transfer::{Queue, RequestBuffer, TransferError},
-    DeviceInfo, InterfaceInfo,
+    Device, DeviceId, DeviceInfo, InterfaceInfo,
 };
 use postcard_schema::Schema;
 use serde::de::DeserializeOwned;
+use tokio::select;
 
 use crate::{
     header::VarSeqKind,
-    host_client::{HostClient, WireRx, WireSpawn, WireTx},
+    host_client::{HostClient, HostClientConfig, WireRx, WireSpawn, WireTx},
 };
 
-// TODO: These should all be configurable, PRs welcome
-
-/// The Bulk Out Endpoint (0x00 | 0x01): Out EP 1
+/// The default Bulk Out Endpoint (0x00 | 0x01): Out EP 1
 pub(crate) const BULK_OUT_EP: u8 = 0x01;
-/// The Bulk In Endpoint (0x80 | 0x01): In EP 1
+/// The default Bulk In Endpoint (0x80 | 0x01): In EP 1
 pub(crate) const BULK_IN_EP: u8 = 0x81;
-/// The size in bytes of the largest possible IN transfer
+/// The default size in bytes of the largest possible IN transfer
 pub(crate) const MAX_TRANSFER_SIZE: usize = 1024;
-/// How many in-flight requests at once - allows nusb to keep pulling frames
-/// even if we haven't processed them host-side yet.
+/// The default number of in-flight requests at once - allows nusb to keep pulling
+/// frames even if we haven't processed them host-side yet.
 pub(crate) const IN_FLIGHT_REQS: usize = 4;
 /// How many consecutive IN errors will we try to recover from before giving up?
 pub(crate) const MAX_STALL_RETRIES: usize = 10;
+/// How long to wait between attempts to find the device again
+pub(crate) const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+/// How many times to try opening a newly connected device before giving up
+pub(crate) const OPEN_ATTEMPTS: usize = 3;
+
+/// Which interface and endpoints of a device to use with `nusb`
+///
+/// The default matches the postcard-rpc `embassy-usb` server: the first interface
+/// with the "Vendor Specific" class 0xFF, using endpoints 0x01 (OUT) and 0x81 (IN).
+/// Devices exposing several postcard-rpc interfaces can use one [`HostClient`] for
+/// each, by selecting the interface and endpoints of each:
+///
+/// ```rust
+/// use postcard_rpc::host_client::RawNusbConfig;
+///
+/// let config = RawNusbConfig::new()
+///     .with_interface_number(1)
+///     .with_endpoints(0x02, 0x82);
+/// ```
+///
+/// **Requires feature**: `raw-nusb`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawNusbConfig {
+    /// The number of the interface to use.
+    ///
+    /// If `None`, the first interface matching `interface_class` and
+    /// `interface_subclass` is used.
+    ///
+    /// ## Platform specific support
+    ///
+    /// When using Windows, the WinUSB driver does not allow enumerating interfaces,
+    /// so if this is `None`, interface zero is ALWAYS used. Selecting another
+    /// class or a subclass instead fails there, Windows requires
+    /// [`Self::with_interface_number`] for any other interface.
+    pub interface_number: Option<u8>,
+    /// The class of the interface to use, if `interface_number` is `None`
+    pub interface_class: u8,
+    /// The subclass of the interface to use, if `interface_number` is `None`.
+    ///
+    /// If `None`, any subclass matches.
+    pub interface_subclass: Option<u8>,
+    /// The address of the bulk OUT endpoint
+    pub bulk_out_ep: u8,
+    /// The address of the bulk IN endpoint
+    pub bulk_in_ep: u8,
+    /// The size in bytes of the largest possible IN transfer
+    pub max_transfer_size: usize,
+    /// How many IN transfers to keep in flight at once, at least one
+    pub in_flight_reqs: usize,
+}
+
+impl RawNusbConfig {
+    /// Create the default configuration
+    pub fn new() -> Self {
+        Self {
+            interface_number: None,
+            interface_class: 0xFF,
+            interface_subclass: None,
+            bulk_out_ep: BULK_OUT_EP,
+            bulk_in_ep: BULK_IN_EP,
+            max_transfer_size: MAX_TRANSFER_SIZE,
+            in_flight_reqs: IN_FLIGHT_REQS,
+        }
+    }
+
+    /// Use the interface with the given number
+    pub fn with_interface_number(self, interface_number: u8) -> Self {
+        Self {
+            interface_number: Some(interface_number),
+            ..self
+        }
+    }
+
+    /// Use the first interface with the given class, and subclass if not `None`
+    ///
+    /// Not supported on Windows, which can not enumerate interfaces, use
+    /// [`Self::with_interface_number`] there.
+    pub fn with_interface_class(self, class: u8, subclass: Option<u8>) -> Self {
+        Self {
+            interface_number: None,
+            interface_class: class,
+            interface_subclass: subclass,
+            ..self
+        }
+    }
+
+    /// Use the given bulk OUT and IN endpoint addresses
+    pub fn with_endpoints(self, bulk_out_ep: u8, bulk_in_ep: u8) -> Self {
+        Self {
+            bulk_out_ep,
+            bulk_in_ep,
+            ..self
+        }
+    }
+
+    /// Allow IN transfers (and so incoming frames) of up to `max_transfer_size` bytes
+    pub fn with_max_transfer_size(self, max_transfer_size: usize) -> Self {
+        Self {
+            max_transfer_size,
+            ..self
+        }
+    }
+
+    /// Keep `in_flight_reqs` IN transfers in flight at once
+    pub fn with_in_flight_reqs(self, in_flight_reqs: usize) -> Self {
+        Self {
+            in_flight_reqs,
+            ..self
+        }
+    }
+
+    /// Find the number of the interface to use on device `x`
+    fn find_interface(&self, x: &DeviceInfo) -> Result<u8, String> {
+        if let Some(number) = self.interface_number {
+            return Ok(number);
+        }
+
+        // NOTE: We can't enumerate interfaces on Windows. For now, just use
+        // a hardcoded interface of zero instead of trying to find the right one
+        #[cfg(not(target_os = "windows"))]
+        let interface_id = x
+            .interfaces()
+            .find(|i| {
+                i.class() == self.interface_class
+                    && self.interface_subclass.is_none_or(|sc| i.subclass() == sc)
+            })
+            .map(|i| i.interface_number())
+            .ok_or_else(|| String::from("Failed to find matching interface!!"))?;
+
+        #[cfg(target_os = "windows")]
+        let interface_id = {
+            let _ = x;
+            if self.interface_class != 0xFF || self.interface_subclass.is_some() {
+                return Err(String::from(
+                    "Interfaces can not be selected by class on Windows, use with_interface_number",
+                ));
+            }
+            0
+        };
+
+        Ok(interface_id)
+    }
+}
+
+impl Default for RawNusbConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// # `nusb` Constructor Methods
 ///
@@ -88,61 +239,72 @@ where
         outgoing_depth: usize,
         seq_no_kind: VarSeqKind,
     ) -> Result<Self, String> {
-        let x = nusb::list_devices()
-            .map_err(|e| format!("Error listing devices: {e:?}"))?
-            .find(func)
-            .ok_or_else(|| String::from("Failed to find matching nusb device!"))?;
-
-        // NOTE: We can't enumerate interfaces on Windows. For now, just use
-        // a hardcoded interface of zero instead of trying to find the right one
-        #[cfg(not(target_os = "windows"))]
-        let interface_id = x
-            .interfaces()
-            .position(|i| i.class() == 0xFF)
-            .ok_or_else(|| String::from("Failed to find matching interface!!"))?;
-
-        #[cfg(target_os = "windows")]
-        let interface_id = 0;
-
-        let dev = x
-            .open()
-            .map_err(|e| format!("Failed opening device: {e:?}"))?;
-        let interface = dev
-            .claim_interface(interface_id as u8)
-            .map_err(|e| format!("Failed claiming interface: {e:?}"))?;
-
-        let mut mps: Option<usize> = None;
-        if let Ok(config) = dev.active_configuration() {
-            for ias in config.interface_alt_settings() {
-                for ep in ias.endpoints() {
-                    if ep.address() == BULK_OUT_EP {
-                        mps = Some(match mps.take() {
-                            Some(old) => old.min(ep.max_packet_size()),
-                            None => ep.max_packet_size(),
-                        });
-                    }
-                }
-            }
-        }
-
-        if let Some(max_packet_size) = &mps {
-            tracing::debug!(max_packet_size, "Detected max packet size");
-        } else {
-            tracing::warn!("Unable to detect Max Packet Size!");
-        };
-
-        let boq = interface.bulk_out_queue(BULK_OUT_EP);
-        let biq = interface.bulk_in_queue(BULK_IN_EP);
+        Self::try_new_raw_nusb_with_config(
+            func,
+            &RawNusbConfig::new(),
+            err_uri_path,
+            outgoing_depth,
+            seq_no_kind,
+        )
+    }
 
+    /// Try to create a new link using [`nusb`] for connectivity, using the given
+    /// interface and endpoints
+    ///
+    /// Like [`Self::try_new_raw_nusb()`], but the interface, endpoints and transfer
+    /// sizes are selected by `config`. This allows connecting one [`HostClient`] to
+    /// each postcard-rpc interface of a device exposing several.
+    ///
+    /// This constructor is available when the `raw-nusb` feature is enabled.
+    ///
+    /// ## Platform specific support
+    ///
+    /// When using Windows, the WinUSB driver does not allow enumerating interfaces,
+    /// so any interface but zero must be selected with
+    /// [`RawNusbConfig::with_interface_number`]. Selecting it by class or subclass
+    /// returns an error.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use postcard_rpc::host_client::{HostClient, RawNusbConfig};
+    /// use postcard_rpc::header::VarSeqKind;
+    /// use serde::{Serialize, Deserialize};
+    /// use postcard_schema::Schema;
+    ///
+    /// /// A "wire error" type your server can use to respond to any
+    /// /// kind of request, for example if deserializing a request fails
+    /// #[derive(Debug, PartialEq, Schema, Serialize, Deserialize)]
+    /// pub enum Error {
+    ///    SomethingBad
+    /// }
+    ///
+    /// let client = HostClient::<Error>::try_new_raw_nusb_with_config(
+    ///     // Find the first device with the serial 12345678
+    ///     |d| d.serial_number() == Some("12345678"),
+    ///     // Use the second interface, with endpoints 2 OUT and 2 IN
+    ///     &RawNusbConfig::new()
+    ///         .with_interface_number(1)
+    ///         .with_endpoints(0x02, 0x82),
+    ///     // the URI/path for `Error` messages
+    ///     "error",
+    ///     // Outgoing queue depth in messages
+    ///     8,
+    ///     // Use one-byte sequence numbers
+    ///     VarSeqKind::Seq1,
+    /// ).unwrap();
+    /// ```
+    pub fn try_new_raw_nusb_with_config<F: FnMut(&DeviceInfo) -> bool>(
+        func: F,
+        config: &RawNusbConfig,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Result<Self, String> {
+        let (tx, rx) = open_raw_nusb(func, config)?;
         Ok(HostClient::new_with_wire(
-            NusbWireTx {
-                boq,
-                max_packet_size: mps,
-            },
-            NusbWireRx {
-                biq,
-                consecutive_errs: 0,
-            },
+            tx,
+            rx,
             NusbSpawn,
             seq_no_kind,
             err_uri_path,
@@ -200,7 +362,7 @@ where
         F2: FnMut(&InterfaceInfo) -> bool,
     >(
         device_func: F1,
-        interface_func: F2,
+        mut interface_func: F2,
         err_uri_path: &str,
         outgoing_depth: usize,
         seq_no_kind: VarSeqKind,
@@ -211,47 +373,17 @@ where
             .ok_or_else(|| String::from("Failed to find matching nusb device!"))?;
         let interface_id = x
             .interfaces()
-            .position(interface_func)
+            .find(|i| interface_func(i))
+            .map(|i| i.interface_number())
             .ok_or_else(|| String::from("Failed to find matching interface!!"))?;
         let dev = x
             .open()
             .map_err(|e| format!("Failed opening device: {e:?}"))?;
-        let interface = dev
-            .claim_interface(interface_id as u8)
-            .map_err(|e| format!("Failed claiming interface: {e:?}"))?;
-
-        let mut mps: Option<usize> = None;
-        if let Ok(config) = dev.active_configuration() {
-            for ias in config.interface_alt_settings() {
-                for ep in ias.endpoints() {
-                    if ep.address() == BULK_OUT_EP {
-                        mps = Some(match mps.take() {
-                            Some(old) => old.min(ep.max_packet_size()),
-                            None => ep.max_packet_size(),
-                        });
-                    }
-                }
-            }
-        }
-
-        if let Some(max_packet_size) = &mps {
-            tracing::debug!(max_packet_size, "Detected max packet size");
-        } else {
-            tracing::warn!("Unable to detect Max Packet Size!");
-        };
-
-        let boq = interface.bulk_out_queue(BULK_OUT_EP);
-        let biq = interface.bulk_in_queue(BULK_IN_EP);
+        let (tx, rx) = open_raw_nusb_interface(&dev, interface_id, &RawNusbConfig::new())?;
 
         Ok(HostClient::new_with_wire(
-            NusbWireTx {
-                boq,
-                max_packet_size: mps,
-            },
-            NusbWireRx {
-                biq,
-                consecutive_errs: 0,
-            },
+            tx,
+            rx,
             NusbSpawn,
             seq_no_kind,
             err_uri_path,
@@ -300,6 +432,293 @@ where
         Self::try_new_raw_nusb(func, err_uri_path, outgoing_depth, seq_no_kind)
             .expect("should have found nusb device")
     }
+
+    /// Create a new link using [`nusb`] for connectivity, that reconnects when
+    /// the device is unplugged and plugged back in
+    ///
+    /// The provided function will be used to find a matching device, both at
+    /// first and each time the connection is lost. Unlike [`Self::try_new_raw_nusb()`],
+    /// this does not fail if no device is present yet, see [`Self::new_reconnecting()`]
+    /// and [`Self::connection_state()`] for more details.
+    ///
+    /// This constructor is available when the `raw-nusb` feature is enabled.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use postcard_rpc::host_client::{ConnectionState, HostClient};
+    /// use postcard_rpc::header::VarSeqKind;
+    /// use serde::{Serialize, Deserialize};
+    /// use postcard_schema::Schema;
+    ///
+    /// /// A "wire error" type your server can use to respond to any
+    /// /// kind of request, for example if deserializing a request fails
+    /// #[derive(Debug, PartialEq, Schema, Serialize, Deserialize)]
+    /// pub enum Error {
+    ///    SomethingBad
+    /// }
+    ///
+    /// # async fn run() {
+    /// let client = HostClient::<Error>::new_raw_nusb_reconnecting(
+    ///     // Find the first device with the serial 12345678
+    ///     |d| d.serial_number() == Some("12345678"),
+    ///     // the URI/path for `Error` messages
+    ///     "error",
+    ///     // Outgoing queue depth in messages
+    ///     8,
+    ///     // Use one-byte sequence numbers
+    ///     VarSeqKind::Seq1,
+    /// );
+    ///
+    /// let mut state = client.connection_state();
+    /// state.wait_for(|s| *s == ConnectionState::Connected).await.unwrap();
+    /// # }
+    /// ```
+    pub fn new_raw_nusb_reconnecting<F: FnMut(&DeviceInfo) -> bool + Send + 'static>(
+        mut func: F,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        let config = HostClientConfig::new(seq_no_kind, err_uri_path, outgoing_depth);
+        let nusb_config = RawNusbConfig::new();
+        let connect = move || core::future::ready(open_raw_nusb(&mut func, &nusb_config));
+        Self::new_reconnecting(connect, NusbSpawn, &config, RECONNECT_INTERVAL)
+    }
+
+    /// Create a new link using [`nusb`] for connectivity, waiting for a matching
+    /// device to be plugged in if there is none yet
+    ///
+    /// The provided function will be used to find a matching device. If a matching
+    /// device is already present, the first one will be connected to, otherwise this
+    /// waits until one is connected. `err_uri_path` is the path associated with the
+    /// `WireErr` message type.
+    ///
+    /// The returned client is closed as soon as the device is unplugged, use
+    /// [`Self::wait_closed()`] to be notified of this. Unlike
+    /// [`Self::new_raw_nusb_reconnecting()`], the client does not reconnect,
+    /// call this method again to wait for the device to come back.
+    ///
+    /// Returns an error if devices could not be listed or watched, or if there was
+    /// an error connecting to the device. To stop waiting, drop the returned future.
+    ///
+    /// This constructor is available when the `raw-nusb` feature is enabled.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use postcard_rpc::host_client::HostClient;
+    /// use postcard_rpc::header::VarSeqKind;
+    /// use serde::{Serialize, Deserialize};
+    /// use postcard_schema::Schema;
+    ///
+    /// /// A "wire error" type your server can use to respond to any
+    /// /// kind of request, for example if deserializing a request fails
+    /// #[derive(Debug, PartialEq, Schema, Serialize, Deserialize)]
+    /// pub enum Error {
+    ///    SomethingBad
+    /// }
+    ///
+    /// # async fn run() {
+    /// loop {
+    ///     let client = HostClient::<Error>::wait_new_raw_nusb(
+    ///         // Find the first device with the serial 12345678
+    ///         |d| d.serial_number() == Some("12345678"),
+    ///         // the URI/path for `Error` messages
+    ///         "error",
+    ///         // Outgoing queue depth in messages
+    ///         8,
+    ///         // Use one-byte sequence numbers
+    ///         VarSeqKind::Seq1,
+    ///     ).await.unwrap();
+    ///
+    ///     println!("Connected!");
+    ///     client.wait_closed().await;
+    ///     println!("Disconnected!");
+    /// }
+    /// # }
+    /// ```
+    pub async fn wait_new_raw_nusb<F: FnMut(&DeviceInfo) -> bool>(
+        mut func: F,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Result<Self, String> {
+        // Start watching before listing, so a device plugged in between is not missed
+        let mut watch =
+            nusb::watch_devices().map_err(|e| format!("Error watching devices: {e:?}"))?;
+        let found = nusb::list_devices()
+            .map_err(|e| format!("Error listing devices: {e:?}"))?
+            .find(&mut func);
+
+        let (info, (tx, rx)) = match found {
+            Some(info) => {
+                let wire = open_raw_nusb_device(&info, &RawNusbConfig::new())?;
+                (info, wire)
+            }
+            None => loop {
+                match next_hotplug_event(&mut watch).await {
+                    Some(HotplugEvent::Connected(info)) if func(&info) => {
+                        let wire =
+                            open_raw_nusb_device_retrying(&info, &RawNusbConfig::new()).await?;
+                        break (info, wire);
+                    }
+                    Some(_) => {}
+                    None => return Err(String::from("Hotplug watch ended!")),
+                }
+            },
+        };
+
+        let me =
+            HostClient::new_with_wire(tx, rx, NusbSpawn, seq_no_kind, err_uri_path, outgoing_depth);
+        me.close_on_nusb_disconnect(info.id(), watch);
+        Ok(me)
+    }
+
+    /// Create a new link to the already enumerated device `info`
+    pub(crate) fn try_new_raw_nusb_device(
+        info: &DeviceInfo,
+        nusb_config: &RawNusbConfig,
+        config: &HostClientConfig<'_>,
+    ) -> Result<Self, String> {
+        let (tx, rx) = open_raw_nusb_device(info, nusb_config)?;
+        Ok(Self::new_with_wire_and_config(tx, rx, NusbSpawn, config))
+    }
+
+    /// Create a new link to the device `info`, which has just been connected
+    pub(crate) async fn try_new_raw_nusb_connected(
+        info: &DeviceInfo,
+        nusb_config: &RawNusbConfig,
+        config: &HostClientConfig<'_>,
+    ) -> Result<Self, String> {
+        let (tx, rx) = open_raw_nusb_device_retrying(info, nusb_config).await?;
+        Ok(Self::new_with_wire_and_config(tx, rx, NusbSpawn, config))
+    }
+
+    /// Close this client once the device `id` is unplugged
+    fn close_on_nusb_disconnect(&self, id: DeviceId, mut watch: HotplugWatch) {
+        let stopper = self.stopper.clone();
+        NusbSpawn.spawn(async move {
+            loop {
+                let event = select! {
+                    _ = stopper.wait_stopped() => return,
+                    event = next_hotplug_event(&mut watch) => event,
+                };
+                match event {
+                    Some(HotplugEvent::Disconnected(gone)) if gone == id => {
+                        tracing::info!("Device disconnected, closing");
+                        stopper.stop();
+                        return;
+                    }
+                    Some(_) => {}
+                    None => {
+                        tracing::warn!("Hotplug watch ended");
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Wait for the next device to be connected or disconnected
+pub(crate) async fn next_hotplug_event(watch: &mut HotplugWatch) -> Option<HotplugEvent> {
+    core::future::poll_fn(|cx| Pin::new(&mut *watch).poll_next(cx)).await
+}
+
+/// Find and open the first device matching `func`, returning the wire halves
+fn open_raw_nusb<F: FnMut(&DeviceInfo) -> bool>(
+    func: F,
+    cfg: &RawNusbConfig,
+) -> Result<(NusbWireTx, NusbWireRx), String> {
+    let x = nusb::list_devices()
+        .map_err(|e| format!("Error listing devices: {e:?}"))?
+        .find(func)
+        .ok_or_else(|| String::from("Failed to find matching nusb device!"))?;
+    open_raw_nusb_device(&x, cfg)
+}
+
+/// Open the device `x`, which has just been connected, returning the wire halves
+///
+/// The interfaces may not be ready yet when the device is reported as connected
+/// (notably on Windows), so this retries a few times.
+async fn open_raw_nusb_device_retrying(
+    x: &DeviceInfo,
+    cfg: &RawNusbConfig,
+) -> Result<(NusbWireTx, NusbWireRx), String> {
+    let mut attempt = 1;
+    loop {
+        match open_raw_nusb_device(x, cfg) {
+            Err(error) if attempt < OPEN_ATTEMPTS => {
+                tracing::debug!(attempt, error, "Failed opening device, retrying");
+                attempt += 1;
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Open the device described by `x`, returning the wire halves
+fn open_raw_nusb_device(
+    x: &DeviceInfo,
+    cfg: &RawNusbConfig,
+) -> Result<(NusbWireTx, NusbWireRx), String> {
+    let interface_id = cfg.find_interface(x)?;
+    let dev = x
+        .open()
+        .map_err(|e| format!("Failed opening device: {e:?}"))?;
+    open_raw_nusb_interface(&dev, interface_id, cfg)
+}
+
+/// Claim the interface `interface_id` of the opened device `dev`, returning the wire halves
+fn open_raw_nusb_interface(
+    dev: &Device,
+    interface_id: u8,
+    cfg: &RawNusbConfig,
+) -> Result<(NusbWireTx, NusbWireRx), String> {
+    let interface = dev
+        .claim_interface(interface_id)
+        .map_err(|e| format!("Failed claiming interface: {e:?}"))?;
+
+    let mut mps: Option<usize> = None;
+    if let Ok(config) = dev.active_configuration() {
+        let alt_settings = config
+            .interface_alt_settings()
+            .filter(|ias| ias.interface_number() == interface_id);
+        for ias in alt_settings {
+            for ep in ias.endpoints() {
+                if ep.address() == cfg.bulk_out_ep {
+                    mps = Some(match mps.take() {
+                        Some(old) => old.min(ep.max_packet_size()),
+                        None => ep.max_packet_size(),
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(max_packet_size) = &mps {
+        tracing::debug!(max_packet_size, "Detected max packet size");
+    } else {
+        tracing::warn!("Unable to detect Max Packet Size!");
+    };
+
+    let boq = interface.bulk_out_queue(cfg.bulk_out_ep);
+    let biq = interface.bulk_in_queue(cfg.bulk_in_ep);
+
+    Ok((
+        NusbWireTx {
+            boq,
+            max_packet_size: mps,
+        },
+        NusbWireRx {
+            biq,
+            consecutive_errs: 0,
+            max_transfer_size: cfg.max_transfer_size,
+            in_flight_reqs: cfg.in_flight_reqs.max(1),
+        },
+    ))
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -377,6 +796,8 @@ impl NusbWireTx {
 struct NusbWireRx {
     biq: Queue<RequestBuffer>,
     consecutive_errs: usize,
+    max_transfer_size: usize,
+    in_flight_reqs: usize,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -399,8 +820,8 @@ impl NusbWireRx {
         loop {
             // Rehydrate the queue
             let pending = self.biq.pending();
-            for _ in 0..(IN_FLIGHT_REQS.saturating_sub(pending)) {
-                self.biq.submit(RequestBuffer::new(MAX_TRANSFER_SIZE));
+            for _ in 0..(self.in_flight_reqs.saturating_sub(pending)) {
+                self.biq.submit(RequestBuffer::new(self.max_transfer_size));
             }
 
             let res = self.biq.next_complete().await;
@@ -439,7 +860,7 @@ impl NusbWireRx {
                     tracing::info!("Cancelled all in-flight requests");
 
                     // Now we need to join all in flight requests
-                    for _ in 0..(IN_FLIGHT_REQS - 1) {
+                    for _ in 0..(self.in_flight_reqs - 1) {
                         let res = self.biq.next_complete().await;
                         tracing::info!("Drain state: {:?}", res.status);
                     }
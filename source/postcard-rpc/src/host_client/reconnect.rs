@@ -0,0 +1,158 @@
+//! A [`HostClient`] that re-opens its connection when it is lost
+
+use core::time::Duration;
+use std::{fmt::Debug, future::Future, sync::Arc};
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::{
+    select,
+    sync::{mpsc, Mutex},
+};
+
+use crate::{
+    header::VarKeyKind,
+    host_client::{
+        announce::topic_worker,
+        util::{in_worker_inner, out_worker_inner, purge_subscriptions, Stopper, Subscriptions},
+        ConnectionState, HostClient, HostClientConfig, HostContext, RpcFrame, WireContext, WireRx,
+        WireSpawn, WireTx,
+    },
+};
+
+/// # Reconnecting Constructor Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Generic reconnecting HostClient logic, using the various Wire traits
+    ///
+    /// Instead of taking an already opened wire, `connect` is called to open the
+    /// wire. Whenever the connection is lost, e.g. because the device was unplugged,
+    /// `connect` is called again every `retry_interval` until it succeeds. The
+    /// client is only closed by [`Self::close`].
+    ///
+    /// On each new connection, the negotiated key size and protocol are reset,
+    /// and the protocol is negotiated again if
+    /// [`HostClientConfig::negotiate_protocol`] is set. Subscriptions are kept,
+    /// announced to the new connection once negotiated, and continue to receive
+    /// messages once the device is back.
+    /// Requests that were in flight when the connection was lost are not
+    /// resent, consider using [`HostClientConfig::resp_timeout`] so that they
+    /// don't wait forever.
+    ///
+    /// The client starts out in the [`ConnectionState::Connecting`] state, use
+    /// [`Self::connection_state`] to watch for changes.
+    pub fn new_reconnecting<C, Fut, E, WTX, WRX, WSP>(
+        connect: C,
+        mut sp: WSP,
+        config: &HostClientConfig<'_>,
+        retry_interval: Duration,
+    ) -> Self
+    where
+        C: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(WTX, WRX), E>> + Send + 'static,
+        E: Debug + 'static,
+        WTX: WireTx,
+        WRX: WireRx,
+        WSP: WireSpawn,
+    {
+        let (me, wire_ctx, released) = Self::new_manual_priv(config);
+        me.ctx.state.send_replace(ConnectionState::Connecting);
+
+        let WireContext { outgoing, incoming } = wire_ctx;
+
+        sp.spawn(topic_worker(
+            me.downgrade(),
+            released,
+            me.connection_state(),
+        ));
+        sp.spawn(reconnect_worker(
+            connect,
+            retry_interval,
+            outgoing,
+            incoming,
+            me.subscriptions.clone(),
+            me.stopper.clone(),
+        ));
+
+        me
+    }
+}
+
+/// Connection worker, (re)opening the wire and feeding frames in both directions
+async fn reconnect_worker<C, Fut, E, WTX, WRX>(
+    connect: C,
+    retry_interval: Duration,
+    mut outgoing: mpsc::Receiver<RpcFrame>,
+    host_ctx: Arc<HostContext>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    stop: Stopper,
+) where
+    C: FnMut() -> Fut,
+    Fut: Future<Output = Result<(WTX, WRX), E>>,
+    E: Debug,
+    WTX: WireTx,
+    WRX: WireRx,
+{
+    let cancel_fut = stop.wait_stopped();
+    let operate_fut = reconnect_worker_inner(
+        connect,
+        retry_interval,
+        &mut outgoing,
+        host_ctx.clone(),
+        subscriptions.clone(),
+    );
+    select! {
+        biased;
+        _ = cancel_fut => {},
+        _ = operate_fut => {
+            // The outgoing queue was closed, nothing more to do
+            stop.stop();
+        },
+    }
+    purge_subscriptions(&subscriptions).await;
+    host_ctx.state.send_replace(ConnectionState::Closed);
+}
+
+async fn reconnect_worker_inner<C, Fut, E, WTX, WRX>(
+    mut connect: C,
+    retry_interval: Duration,
+    outgoing: &mut mpsc::Receiver<RpcFrame>,
+    host_ctx: Arc<HostContext>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+) where
+    C: FnMut() -> Fut,
+    Fut: Future<Output = Result<(WTX, WRX), E>>,
+    E: Debug,
+    WTX: WireTx,
+    WRX: WireRx,
+{
+    loop {
+        host_ctx.state.send_replace(ConnectionState::Connecting);
+        let (tx, rx) = loop {
+            match connect().await {
+                Ok(wire) => break wire,
+                Err(e) => tracing::debug!("Connecting failed: {e:?}, retrying"),
+            }
+            tokio::time::sleep(retry_interval).await;
+        };
+
+        // The new device may not have the same set of keys
+        *host_ctx.kkind.write().unwrap() = VarKeyKind::Key8;
+        // ...and needs to negotiate the protocol again
+        *host_ctx.protocol.write().unwrap() = None;
+        host_ctx.state.send_replace(ConnectionState::Connected);
+        tracing::info!("Connected");
+
+        select! {
+            queue_closed = out_worker_inner(tx, outgoing, &host_ctx) => {
+                if queue_closed {
+                    return;
+                }
+            },
+            _ = in_worker_inner(rx, host_ctx.clone(), subscriptions.clone()) => {},
+        }
+        tracing::warn!("Connection lost, reconnecting");
+    }
+}
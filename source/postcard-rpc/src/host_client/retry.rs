@@ -0,0 +1,150 @@
+//! Automatic retries for idempotent endpoints
+
+use core::time::Duration;
+
+use postcard_schema::Schema;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    host_client::{HostClient, HostErr},
+    Endpoint,
+};
+
+/// A policy for retrying requests to an endpoint
+///
+/// Each attempt is sent with a fresh sequence number, so a late response to
+/// an earlier attempt is never mistaken for the response to a later one.
+///
+/// Requests may be received and handled more than once by the server, so this
+/// should only be used with idempotent endpoints.
+pub struct RetryPolicy<WireErr> {
+    /// The maximum number of attempts, including the first one
+    pub max_attempts: usize,
+    /// The time to wait for a response to each attempt.
+    ///
+    /// If `None`, [`HostClientConfig::resp_timeout`][crate::host_client::HostClientConfig::resp_timeout]
+    /// is used. If neither is set, attempts never time out, and are only retried
+    /// on wire errors.
+    pub timeout: Option<Duration>,
+    /// The delay before the first retry
+    pub backoff_initial: Duration,
+    /// The delay is doubled after each retry, up to this limit
+    pub backoff_max: Duration,
+    /// Which `WireErr` responses should be retried, e.g.
+    /// `|e| matches!(e, WireError::FailedToSpawn)`
+    pub retry_on: fn(&WireErr) -> bool,
+}
+
+impl<WireErr> RetryPolicy<WireErr> {
+    /// Create a new policy, retrying only on timeouts, without backoff
+    pub fn new(max_attempts: usize, timeout: Duration) -> Self {
+        Self {
+            max_attempts,
+            timeout: Some(timeout),
+            backoff_initial: Duration::ZERO,
+            backoff_max: Duration::ZERO,
+            retry_on: |_| false,
+        }
+    }
+
+    /// Wait before retrying, starting at `initial` and doubling up to `max`
+    pub fn with_backoff(self, initial: Duration, max: Duration) -> Self {
+        Self {
+            backoff_initial: initial,
+            backoff_max: max,
+            ..self
+        }
+    }
+
+    /// Also retry when the server responds with a `WireErr` matching `retry_on`
+    pub fn with_retry_on(self, retry_on: fn(&WireErr) -> bool) -> Self {
+        Self { retry_on, ..self }
+    }
+
+    /// Should this result be retried?
+    fn should_retry<T>(&self, res: &Result<T, HostErr<WireErr>>) -> bool {
+        match res {
+            Err(HostErr::Timeout) => true,
+            Err(HostErr::Wire(e)) => (self.retry_on)(e),
+            _ => false,
+        }
+    }
+}
+
+impl<WireErr> Clone for RetryPolicy<WireErr> {
+    fn clone(&self) -> Self {
+        Self {
+            max_attempts: self.max_attempts,
+            timeout: self.timeout,
+            backoff_initial: self.backoff_initial,
+            backoff_max: self.backoff_max,
+            retry_on: self.retry_on,
+        }
+    }
+}
+
+/// # Retry Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Register a [`RetryPolicy`] for the endpoint `E`
+    ///
+    /// Once registered, all calls to [`Self::send_resp`] for this endpoint, on this
+    /// client and any clones of it, use the policy. This replaces any policy
+    /// previously registered for `E`.
+    pub fn set_retry_policy<E: Endpoint>(&self, policy: RetryPolicy<WireErr>) {
+        self.retries.write().unwrap().insert(E::REQ_KEY, policy);
+    }
+
+    /// Remove the [`RetryPolicy`] registered for the endpoint `E`, if any
+    pub fn clear_retry_policy<E: Endpoint>(&self) {
+        self.retries.write().unwrap().remove(&E::REQ_KEY);
+    }
+
+    /// Like [`Self::send_resp`], but using the given [`RetryPolicy`] instead of
+    /// the one registered for `E`.
+    ///
+    /// Returns the result of the last attempt.
+    pub async fn send_resp_with_retry<E: Endpoint>(
+        &self,
+        t: &E::Request,
+        policy: &RetryPolicy<WireErr>,
+    ) -> Result<E::Response, HostErr<WireErr>>
+    where
+        E::Request: Serialize + Schema,
+        E::Response: DeserializeOwned + Schema,
+    {
+        let timeout = policy.timeout.or(self.ctx.resp_timeout);
+        let mut delay = policy.backoff_initial;
+        let mut attempt = 1;
+
+        loop {
+            let (frame, seq) = self.request_frame::<E>(t).await;
+            let res = self.send_resp_raw_inner(frame, E::RESP_KEY, timeout).await;
+            // Release the sequence number before waiting to retry
+            drop(seq);
+
+            if attempt >= policy.max_attempts || !policy.should_retry(&res) {
+                let frame = res?;
+                let r = postcard::from_bytes::<E::Response>(&frame.body)?;
+                return Ok(r);
+            }
+
+            tracing::debug!(
+                "Retrying request to '{}', attempt {attempt} failed",
+                E::PATH
+            );
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            delay = (delay * 2).min(policy.backoff_max);
+            attempt += 1;
+        }
+    }
+
+    /// Get the [`RetryPolicy`] registered for the endpoint `E`, if any
+    pub(crate) fn retry_policy<E: Endpoint>(&self) -> Option<RetryPolicy<WireErr>> {
+        self.retries.read().unwrap().get(&E::REQ_KEY).cloned()
+    }
+}
@@ -0,0 +1,230 @@
+//! Limiting and prioritizing requests in flight
+
+use core::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use tokio::sync::Notify;
+
+use crate::{
+    header::VarKey,
+    host_client::HostClient,
+    standard_icd::{BlobReadEndpoint, BlobWriteEndpoint, GetAllSchemasEndpoint},
+    Endpoint, Key,
+};
+
+/// The priority class of requests to an endpoint
+///
+/// When [`HostClientConfig::max_in_flight`][crate::host_client::HostClientConfig::max_in_flight]
+/// is reached, waiting requests are sent in order of priority. [`Priority::Bulk`]
+/// requests always leave one request slot to the others, so bulk transfers can
+/// not starve latency-sensitive requests.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Latency-sensitive requests, e.g. control endpoints
+    High,
+    /// The default for all endpoints
+    #[default]
+    Normal,
+    /// Bulk traffic, the default for schema dumps and blob transfers
+    Bulk,
+}
+
+impl Priority {
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Bulk => 2,
+        }
+    }
+}
+
+/// A snapshot of the requests and frames queued by a [`HostClient`], see
+/// [`HostClient::queue_stats()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Requests sent, waiting for their response
+    pub in_flight: usize,
+    /// The limit of requests in flight, if any
+    pub max_in_flight: Option<NonZeroUsize>,
+    /// Requests of each [`Priority`] waiting to be sent, highest priority first
+    pub waiting: [usize; 3],
+    /// Frames in the outgoing queue, not yet taken by the I/O worker
+    pub outgoing_queued: usize,
+    /// The depth of the outgoing queue
+    pub outgoing_depth: usize,
+}
+
+/// A priority-aware semaphore for requests in flight
+pub(crate) struct Scheduler {
+    limit: Option<NonZeroUsize>,
+    state: Mutex<SchedState>,
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct SchedState {
+    in_flight: usize,
+    waiting: [usize; 3],
+}
+
+/// A request slot, released on drop
+pub(crate) struct Slot<'a> {
+    sched: &'a Scheduler,
+}
+
+/// Counts a request as waiting, also if the waiting future is dropped
+struct Waiting<'a> {
+    sched: &'a Scheduler,
+    prio: Priority,
+}
+
+impl Scheduler {
+    pub(crate) fn new(limit: Option<NonZeroUsize>) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(SchedState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Wait for a slot to send a request with the given priority
+    pub(crate) async fn acquire(&self, prio: Priority) -> Slot<'_> {
+        let mut waiting = None;
+        loop {
+            // Register before checking, so a release in between is not missed
+            let notified = self.notify.notified();
+            let mut notified = std::pin::pin!(notified);
+            notified.as_mut().enable();
+            let admitted = {
+                let mut state = self.state.lock().unwrap();
+                let ahead: usize = state.waiting[..prio.index()].iter().sum();
+                if ahead == 0 && self.has_room(prio, state.in_flight) {
+                    state.in_flight += 1;
+                    true
+                } else {
+                    if waiting.is_none() {
+                        state.waiting[prio.index()] += 1;
+                        waiting = Some(Waiting { sched: self, prio });
+                    }
+                    false
+                }
+            };
+            if admitted {
+                drop(waiting);
+                return Slot { sched: self };
+            }
+            notified.await;
+        }
+    }
+
+    fn has_room(&self, prio: Priority, in_flight: usize) -> bool {
+        let Some(limit) = self.limit.map(NonZeroUsize::get) else {
+            return true;
+        };
+        if prio == Priority::Bulk && limit > 1 {
+            in_flight < limit - 1
+        } else {
+            in_flight < limit
+        }
+    }
+
+    fn stats(&self) -> (usize, [usize; 3]) {
+        let state = self.state.lock().unwrap();
+        (state.in_flight, state.waiting)
+    }
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.sched.state.lock().unwrap().in_flight -= 1;
+        self.sched.notify.notify_waiters();
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.sched.state.lock().unwrap().waiting[self.prio.index()] -= 1;
+        // Requests of lower priority may have waited for this one
+        self.sched.notify.notify_waiters();
+    }
+}
+
+/// The standard endpoints with [`Priority::Bulk`], unless set with [`HostClient::set_priority()`]
+const BULK: &[Key] = &[
+    GetAllSchemasEndpoint::REQ_KEY,
+    BlobReadEndpoint::REQ_KEY,
+    BlobWriteEndpoint::REQ_KEY,
+];
+
+/// The priority of the standard endpoints, `matches` tells if a key is the one we look for
+fn default_priority(matches: impl Fn(Key) -> bool) -> Priority {
+    if BULK.iter().any(|k| matches(*k)) {
+        Priority::Bulk
+    } else {
+        Priority::Normal
+    }
+}
+
+/// # Scheduling Methods
+impl<WireErr> HostClient<WireErr> {
+    /// Set the [`Priority`] of requests to the endpoint `E`
+    ///
+    /// This applies to this client and any clones of it, and replaces any
+    /// priority previously set for `E`.
+    pub fn set_priority<E: Endpoint>(&self, priority: Priority) {
+        self.priorities
+            .write()
+            .unwrap()
+            .insert(E::REQ_KEY, priority);
+    }
+
+    /// Get the [`Priority`] of requests to the endpoint `E`
+    pub fn priority<E: Endpoint>(&self) -> Priority {
+        self.priority_of(E::REQ_KEY)
+    }
+
+    /// Get the current [`QueueStats`]
+    pub fn queue_stats(&self) -> QueueStats {
+        let (in_flight, waiting) = self.ctx.sched.stats();
+        QueueStats {
+            in_flight,
+            max_in_flight: self.ctx.sched.limit,
+            waiting,
+            outgoing_queued: self.out.max_capacity() - self.out.capacity(),
+            outgoing_depth: self.out.max_capacity(),
+        }
+    }
+
+    fn priority_of(&self, key: Key) -> Priority {
+        self.priorities
+            .read()
+            .unwrap()
+            .get(&key)
+            .copied()
+            .unwrap_or_else(|| default_priority(|k| k == key))
+    }
+
+    /// The priority of a request frame
+    ///
+    /// The key of raw requests may be shrunk already, it is then compared to the
+    /// keys with a known priority, shrunk to the same size.
+    pub(crate) fn priority_of_frame(&self, key: &VarKey) -> Priority {
+        if let VarKey::Key8(key) = key {
+            return self.priority_of(*key);
+        }
+        let matches = |k: Key| {
+            let mut k = VarKey::Key8(k);
+            k.shrink_to(key.kind());
+            k == *key
+        };
+        let set = self
+            .priorities
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| matches(**k))
+            .map(|(_, prio)| *prio);
+        set.unwrap_or_else(|| default_priority(matches))
+    }
+}
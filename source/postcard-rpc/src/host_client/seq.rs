@@ -0,0 +1,128 @@
+//! Allocation of request sequence numbers
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::header::{VarSeq, VarSeqKind};
+
+/// Hands out the sequence numbers of requests
+///
+/// Numbers are handed out in order, wrapping around at the end of the space of
+/// the [`VarSeqKind`], and skipping numbers that are still used by a request in
+/// flight. At most as many requests as there are sequence numbers can be in
+/// flight, further requests wait until a number is released.
+pub(crate) struct SeqAllocator {
+    kind: VarSeqKind,
+    inner: Arc<SeqInner>,
+}
+
+struct SeqInner {
+    state: Mutex<SeqState>,
+    permits: Arc<Semaphore>,
+}
+
+struct SeqState {
+    next: u32,
+    in_use: HashSet<u32>,
+}
+
+/// A sequence number reserved for a request, released on drop
+pub(crate) struct SeqGuard {
+    seq_no: VarSeq,
+    inner: Arc<SeqInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl SeqAllocator {
+    pub(crate) fn new(kind: VarSeqKind) -> Self {
+        let space = match kind {
+            VarSeqKind::Seq1 => 1 << 8,
+            VarSeqKind::Seq2 => 1 << 16,
+            VarSeqKind::Seq4 => usize::MAX,
+        };
+        Self {
+            kind,
+            inner: Arc::new(SeqInner {
+                state: Mutex::new(SeqState {
+                    next: 0,
+                    in_use: HashSet::new(),
+                }),
+                permits: Arc::new(Semaphore::new(space.min(Semaphore::MAX_PERMITS))),
+            }),
+        }
+    }
+
+    /// Reserve the next free sequence number, waiting if all are in use
+    pub(crate) async fn allocate(&self) -> SeqGuard {
+        let permit = self.acquire().await;
+        let mut state = self.inner.state.lock().unwrap();
+        let mask = match self.kind {
+            VarSeqKind::Seq1 => u8::MAX.into(),
+            VarSeqKind::Seq2 => u16::MAX.into(),
+            VarSeqKind::Seq4 => u32::MAX,
+        };
+        // There are fewer permits than numbers, so there is a free number
+        let mut seq = state.next;
+        while state.in_use.contains(&seq) {
+            seq = seq.wrapping_add(1) & mask;
+        }
+        state.next = seq.wrapping_add(1) & mask;
+        state.in_use.insert(seq);
+
+        let mut seq_no = VarSeq::Seq4(seq);
+        seq_no.resize(self.kind);
+        SeqGuard {
+            seq_no,
+            inner: self.inner.clone(),
+            _permit: permit,
+        }
+    }
+
+    /// Reserve the given sequence number, chosen by the caller
+    ///
+    /// Returns `None` right away if this one is in use, and waits if all
+    /// sequence numbers are.
+    pub(crate) async fn reserve(&self, seq_no: VarSeq) -> Option<SeqGuard> {
+        let seq: u32 = seq_no.into();
+        if self.inner.state.lock().unwrap().in_use.contains(&seq) {
+            return None;
+        }
+        let permit = self.acquire().await;
+        // Check again, it may have been taken while waiting
+        if !self.inner.state.lock().unwrap().in_use.insert(seq) {
+            return None;
+        }
+        Some(SeqGuard {
+            seq_no,
+            inner: self.inner.clone(),
+            _permit: permit,
+        })
+    }
+
+    async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.inner
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed")
+    }
+}
+
+impl SeqGuard {
+    /// The reserved sequence number
+    pub(crate) fn seq_no(&self) -> VarSeq {
+        self.seq_no
+    }
+}
+
+impl Drop for SeqGuard {
+    fn drop(&mut self) {
+        let seq: u32 = self.seq_no.into();
+        self.inner.state.lock().unwrap().in_use.remove(&seq);
+    }
+}
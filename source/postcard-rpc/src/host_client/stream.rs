@@ -0,0 +1,153 @@
+//! Server-streaming endpoints, with one request and many responses
+
+use std::marker::PhantomData;
+
+use postcard_schema::Schema;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{select, sync::mpsc};
+
+use crate::{
+    header::{VarKey, VarKeyKind},
+    host_client::{seq::SeqGuard, util::StreamSub, HostClient, HostErr, RpcFrame},
+    Key, StreamEndpoint,
+};
+
+/// The number of items that may be buffered in a [`ResponseStream`]
+const STREAM_DEPTH: usize = 32;
+
+/// # Stream Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Send a request to a [`StreamEndpoint`], and receive a [`ResponseStream`]
+    /// of [Item][StreamEndpoint::Item]s in response.
+    ///
+    /// All items of the stream carry the sequence number of this request, so
+    /// many streams of the same endpoint may be active at once.
+    ///
+    /// If the stream isn't received from fast enough, items are dropped after
+    /// [`HostClientConfig::subscriber_timeout_if_full`][crate::host_client::HostClientConfig::subscriber_timeout_if_full].
+    ///
+    /// The request waits for its turn like any other, see
+    /// [`HostClientConfig::max_in_flight`][crate::host_client::HostClientConfig::max_in_flight],
+    /// but an open stream does not count as in flight, so long-lived streams do not
+    /// hold back other requests.
+    pub async fn send_stream<E: StreamEndpoint>(
+        &self,
+        t: &E::Request,
+    ) -> Result<ResponseStream<E::Item, WireErr>, HostErr<WireErr>>
+    where
+        E::Request: Serialize + Schema,
+        E::Item: DeserializeOwned,
+    {
+        let (mut rqst, seq) = self.request_frame::<E>(t).await;
+        let kkind: VarKeyKind = *self.ctx.kkind.read().unwrap();
+        rqst.header.key.shrink_to(kkind);
+        if self.frame_too_large(&rqst) {
+            return Err(HostErr::FrameTooLarge);
+        }
+        let (tx, rx) = mpsc::channel(STREAM_DEPTH);
+
+        // The slot is only held until the request is queued
+        let prio = self.priority_of_frame(&rqst.header.key);
+        let _slot = select! {
+            _ = self.stopper.wait_stopped() => return Err(HostErr::Closed),
+            slot = self.ctx.sched.acquire(prio) => slot,
+        };
+
+        // Register the stream BEFORE we send the request, so we don't miss
+        // any early items
+        {
+            let mut guard = self.subscriptions.lock().await;
+            if guard.stopped {
+                return Err(HostErr::Closed);
+            }
+            guard.stream_list.retain(|s| !s.tx.is_closed());
+            guard.stream_list.push(StreamSub {
+                seq_no: rqst.header.seq_no,
+                resp_key: E::RESP_KEY,
+                err_key: self.err_key,
+                tx,
+            });
+        }
+
+        let cancel_fut = self.stopper.wait_stopped();
+        select! {
+            biased;
+            _ = cancel_fut => return Err(HostErr::Closed),
+            res = self.out.send(rqst) => res.map_err(|_| HostErr::Closed)?,
+        }
+
+        Ok(ResponseStream {
+            rx,
+            resp_key: E::RESP_KEY,
+            done: false,
+            _seq: seq,
+            _pd: PhantomData,
+        })
+    }
+}
+
+/// A stream of responses to a single request, created by [`HostClient::send_stream`]
+pub struct ResponseStream<T, WireErr> {
+    rx: mpsc::Receiver<RpcFrame>,
+    resp_key: Key,
+    done: bool,
+    // Keeps the sequence number reserved while the stream is alive
+    _seq: SeqGuard,
+    _pd: PhantomData<fn() -> (T, WireErr)>,
+}
+
+impl<T, WireErr> ResponseStream<T, WireErr>
+where
+    T: DeserializeOwned,
+    WireErr: DeserializeOwned,
+{
+    /// Await the next item of the stream.
+    ///
+    /// Returns [None] once the server has ended the stream. If the server
+    /// responds with an error, it is returned once, and the stream ends. If the
+    /// connection is closed before the stream ended, [`HostErr::Closed`] is
+    /// returned.
+    ///
+    /// If the server handler never ends the stream, this waits forever; use a
+    /// timeout if the server can not be trusted to.
+    pub async fn recv(&mut self) -> Option<Result<T, HostErr<WireErr>>> {
+        if self.done {
+            return None;
+        }
+        let Some(frame) = self.rx.recv().await else {
+            self.finish();
+            return Some(Err(HostErr::Closed));
+        };
+
+        if VarKey::Key8(self.resp_key) == frame.header.key {
+            match postcard::from_bytes::<Option<T>>(&frame.body) {
+                Ok(Some(t)) => Some(Ok(t)),
+                Ok(None) => {
+                    self.finish();
+                    None
+                }
+                Err(e) => Some(Err(HostErr::Postcard(e))),
+            }
+        } else {
+            self.finish();
+            match postcard::from_bytes::<WireErr>(&frame.body) {
+                Ok(e) => Some(Err(HostErr::Wire(e))),
+                Err(e) => Some(Err(HostErr::Postcard(e))),
+            }
+        }
+    }
+
+    /// Has the stream ended?
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        // Let the I/O worker know that nothing else is expected
+        self.rx.close();
+    }
+}
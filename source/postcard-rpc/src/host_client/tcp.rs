@@ -0,0 +1,266 @@
+//! Implementation of transport using tokio TCP streams and COBS framing
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    net::{TcpStream as StdTcpStream, ToSocketAddrs},
+};
+
+use cobs::encode_vec;
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+};
+
+use crate::{
+    accumulator::raw::{CobsAccumulator, FeedResult},
+    header::VarSeqKind,
+    host_client::{HostClient, WireRx, WireSpawn, WireTx},
+};
+
+/// # TCP Constructor Methods
+///
+/// These methods are used to create a new [HostClient] instance for use with tokio TCP
+/// streams and cobs encoding.
+///
+/// **Requires feature**: `tcp`
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Create a new [HostClient]
+    ///
+    /// `addr` is the address of the server to connect to, for example
+    /// `"127.0.0.1:4040"`. `err_uri_path` is the path associated with the
+    /// `WireErr` message type.
+    ///
+    /// Frames are COBS encoded, the same as with `cobs-serial`. This is useful
+    /// for talking to simulated devices, or to devices bridged over the network.
+    ///
+    /// This constructor is available when the `tcp` feature is enabled, and must
+    /// be called from within a tokio runtime.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use postcard_rpc::host_client::HostClient;
+    /// use postcard_rpc::header::VarSeqKind;
+    /// use serde::{Serialize, Deserialize};
+    /// use postcard_schema::Schema;
+    ///
+    /// /// A "wire error" type your server can use to respond to any
+    /// /// kind of request, for example if deserializing a request fails
+    /// #[derive(Debug, PartialEq, Schema, Serialize, Deserialize)]
+    /// pub enum Error {
+    ///    SomethingBad
+    /// }
+    ///
+    /// let client = HostClient::<Error>::try_new_tcp(
+    ///     // the address of the server
+    ///     "127.0.0.1:4040",
+    ///     // the URI/path for `Error` messages
+    ///     "error",
+    ///     // Outgoing queue depth in messages
+    ///     8,
+    ///     // Use one-byte sequence numbers
+    ///     VarSeqKind::Seq1,
+    /// ).unwrap();
+    /// ```
+    pub fn try_new_tcp<A: ToSocketAddrs>(
+        addr: A,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Result<Self, String> {
+        let stream = StdTcpStream::connect(addr).map_err(|e| format!("Connect Error: {e:?}"))?;
+        stream
+            .set_nonblocking(true)
+            .map_err(|e| format!("Connect Error: {e:?}"))?;
+        stream
+            .set_nodelay(true)
+            .map_err(|e| format!("Connect Error: {e:?}"))?;
+        let stream = TcpStream::from_std(stream).map_err(|e| format!("Connect Error: {e:?}"))?;
+
+        Ok(Self::new_tcp_from_stream(
+            stream,
+            err_uri_path,
+            outgoing_depth,
+            seq_no_kind,
+        ))
+    }
+
+    /// Create a new [HostClient]
+    ///
+    /// Panics if we couldn't connect to the server.
+    ///
+    /// See [`HostClient::try_new_tcp`] for more details
+    pub fn new_tcp<A: ToSocketAddrs>(
+        addr: A,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        Self::try_new_tcp(addr, err_uri_path, outgoing_depth, seq_no_kind).unwrap()
+    }
+
+    /// Create a new [HostClient] from an already connected tokio [`TcpStream`]
+    ///
+    /// This is useful if the connection was established some other way, for
+    /// example by accepting an incoming connection from a device.
+    ///
+    /// See [`HostClient::try_new_tcp`] for more details
+    pub fn new_tcp_from_stream(
+        stream: TcpStream,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        let (rx, tx) = stream.into_split();
+
+        HostClient::new_with_wire(
+            TcpWireTx { tx },
+            TcpWireRx {
+                rx,
+                buf: Box::new([0u8; 1024]),
+                acc: Box::new(CobsAccumulator::new()),
+                pending: VecDeque::new(),
+            },
+            TcpSpawn,
+            seq_no_kind,
+            err_uri_path,
+            outgoing_depth,
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Wire Interface Implementation
+//////////////////////////////////////////////////////////////////////////////
+
+/// Tokio TCP Wire Interface Implementor
+///
+/// Uses Tokio for spawning tasks
+struct TcpSpawn;
+
+impl WireSpawn for TcpSpawn {
+    fn spawn(&mut self, fut: impl Future<Output = ()> + Send + 'static) {
+        // Explicitly drop the joinhandle as it impls Future and this makes
+        // clippy mad if you just let it drop implicitly
+        core::mem::drop(tokio::task::spawn(fut));
+    }
+}
+
+/// Tokio TCP Wire Transmit Interface Implementor
+struct TcpWireTx {
+    tx: OwnedWriteHalf,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum TcpWireTxError {
+    #[error("Transfer Error on Send")]
+    Transfer(#[from] std::io::Error),
+}
+
+impl WireTx for TcpWireTx {
+    type Error = TcpWireTxError;
+
+    #[inline]
+    fn send(&mut self, data: Vec<u8>) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.send_inner(data)
+    }
+}
+
+impl TcpWireTx {
+    async fn send_inner(&mut self, data: Vec<u8>) -> Result<(), TcpWireTxError> {
+        // Turn the serialized message into a COBS encoded message
+        let mut msg = encode_vec(&data);
+        msg.push(0);
+
+        // And send it!
+        self.tx.write_all(&msg).await?;
+        Ok(())
+    }
+}
+
+/// Tokio TCP Wire Receive Interface Implementor
+struct TcpWireRx {
+    rx: OwnedReadHalf,
+    buf: Box<[u8; 1024]>,
+    acc: Box<CobsAccumulator<1024>>,
+    pending: VecDeque<Vec<u8>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum TcpWireRxError {
+    #[error("Transfer Error on Recv")]
+    Transfer(#[from] std::io::Error),
+    #[error("Connection closed by peer")]
+    Closed,
+}
+
+impl WireRx for TcpWireRx {
+    type Error = TcpWireRxError;
+
+    #[inline]
+    fn receive(&mut self) -> impl Future<Output = Result<Vec<u8>, Self::Error>> + Send {
+        self.recv_inner()
+    }
+}
+
+impl TcpWireRx {
+    async fn recv_inner(&mut self) -> Result<Vec<u8>, TcpWireRxError> {
+        // Receive until we've gotten AT LEAST one message, though we will continue
+        // consuming and buffering any read (partial) messages, to ensure they are not lost.
+        loop {
+            // Do we have any messages already prepared?
+            if let Some(p) = self.pending.pop_front() {
+                return Ok(p);
+            }
+
+            // Nothing in the pending queue, do a read to see if we can pull more
+            // data from the socket
+            let used = self.rx.read(self.buf.as_mut_slice()).await?;
+
+            // Unlike serial ports, a zero-length read means the peer has hung up
+            if used == 0 {
+                return Err(TcpWireRxError::Closed);
+            }
+
+            let mut window = &self.buf[..used];
+
+            // This buffering loop is necessary as a single `read()` might include
+            // more than one message
+            'cobs: while !window.is_empty() {
+                window = match self.acc.feed(window) {
+                    // Consumed the whole read
+                    FeedResult::Consumed => break 'cobs,
+                    // Ignore line errors
+                    FeedResult::OverFull(new_wind) => {
+                        tracing::warn!("Overflowed COBS accumulator");
+                        new_wind
+                    }
+                    FeedResult::DeserError(new_wind) => {
+                        tracing::warn!("COBS formatting error");
+                        new_wind
+                    }
+                    // We got a message! Attempt to dispatch it
+                    FeedResult::Success { data, remaining } => {
+                        // The minimum size of a message is 3 bytes: one for the
+                        // discriminant, one for the key, and one for the seq_no.
+                        if data.len() >= 3 {
+                            self.pending.push_back(data.to_vec());
+                        } else {
+                            tracing::warn!("Ignoring too-short message: {} bytes", data.len());
+                        }
+                        remaining
+                    }
+                };
+            }
+        }
+    }
+}
@@ -24,6 +24,35 @@ pub fn new_from_channels(
     )
 }
 
+/// Create a new reconnecting HostClient, calling `connect` for new server channels
+///
+/// See [`HostClient::new_reconnecting`].
+#[cfg(not(target_family = "wasm"))]
+pub fn new_reconnecting_from_channels<C, Fut>(
+    mut connect: C,
+    config: &crate::host_client::HostClientConfig<'_>,
+) -> HostClient<WireError>
+where
+    C: FnMut() -> Fut + Send + 'static,
+    Fut: core::future::Future<Output = (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>)>
+        + Send
+        + 'static,
+{
+    let connect = move || {
+        let fut = connect();
+        async move {
+            let (tx, rx) = fut.await;
+            Ok::<_, ChannelError>((ChannelTx { tx }, ChannelRx { rx }))
+        }
+    };
+    HostClient::new_reconnecting(
+        connect,
+        TokSpawn,
+        config,
+        core::time::Duration::from_millis(10),
+    )
+}
+
 /// Server error kinds
 #[derive(Debug)]
 pub enum ChannelError {
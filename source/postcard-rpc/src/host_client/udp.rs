@@ -0,0 +1,242 @@
+//! Implementation of transport using tokio UDP sockets, one frame per datagram
+
+use std::{
+    future::Future,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket as StdUdpSocket},
+    sync::Arc,
+};
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+use tokio::net::UdpSocket;
+
+use crate::{
+    header::VarSeqKind,
+    host_client::{HostClient, HostClientConfig, WireRx, WireSpawn, WireTx},
+};
+
+/// The largest payload that can be carried by a single UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// # UDP Constructor Methods
+///
+/// These methods are used to create a new [HostClient] instance for use with tokio UDP
+/// sockets. Each frame is sent as a single datagram, no additional framing is used.
+///
+/// **Requires feature**: `udp`
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Create a new [HostClient]
+    ///
+    /// `addr` is the address of the device to talk to, for example
+    /// `"192.168.1.20:4040"`. `err_uri_path` is the path associated with the
+    /// `WireErr` message type.
+    ///
+    /// A new socket is bound to an ephemeral local port and connected to `addr`,
+    /// so datagrams from any other address are ignored. To talk to multiple
+    /// devices, create one [HostClient] per device address.
+    ///
+    /// UDP does not guarantee delivery: a request or response that is lost will
+    /// never be answered. Requests larger than the maximum datagram size fail
+    /// with [`HostErr::FrameTooLarge`][crate::host_client::HostErr::FrameTooLarge],
+    /// published messages with
+    /// [`PublishError::FrameTooLarge`][crate::host_client::PublishError::FrameTooLarge].
+    ///
+    /// This constructor is available when the `udp` feature is enabled, and must
+    /// be called from within a tokio runtime.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use postcard_rpc::host_client::HostClient;
+    /// use postcard_rpc::header::VarSeqKind;
+    /// use serde::{Serialize, Deserialize};
+    /// use postcard_schema::Schema;
+    ///
+    /// /// A "wire error" type your server can use to respond to any
+    /// /// kind of request, for example if deserializing a request fails
+    /// #[derive(Debug, PartialEq, Schema, Serialize, Deserialize)]
+    /// pub enum Error {
+    ///    SomethingBad
+    /// }
+    ///
+    /// let client = HostClient::<Error>::try_new_udp(
+    ///     // the address of the device
+    ///     "192.168.1.20:4040",
+    ///     // the URI/path for `Error` messages
+    ///     "error",
+    ///     // Outgoing queue depth in messages
+    ///     8,
+    ///     // Use one-byte sequence numbers
+    ///     VarSeqKind::Seq1,
+    /// ).unwrap();
+    /// ```
+    pub fn try_new_udp<A: ToSocketAddrs>(
+        addr: A,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Result<Self, String> {
+        let remote = addr
+            .to_socket_addrs()
+            .map_err(|e| format!("Address Error: {e:?}"))?
+            .next()
+            .ok_or_else(|| String::from("Address Error: no addresses found"))?;
+        let local: SocketAddr = match remote {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+
+        let socket = StdUdpSocket::bind(local).map_err(|e| format!("Bind Error: {e:?}"))?;
+        socket
+            .connect(remote)
+            .map_err(|e| format!("Connect Error: {e:?}"))?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| format!("Connect Error: {e:?}"))?;
+        let socket = UdpSocket::from_std(socket).map_err(|e| format!("Connect Error: {e:?}"))?;
+
+        Ok(Self::new_udp_from_socket(
+            socket,
+            err_uri_path,
+            outgoing_depth,
+            seq_no_kind,
+        ))
+    }
+
+    /// Create a new [HostClient]
+    ///
+    /// Panics if we couldn't open the socket.
+    ///
+    /// See [`HostClient::try_new_udp`] for more details
+    pub fn new_udp<A: ToSocketAddrs>(
+        addr: A,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        Self::try_new_udp(addr, err_uri_path, outgoing_depth, seq_no_kind).unwrap()
+    }
+
+    /// Create a new [HostClient] from an already connected tokio [`UdpSocket`]
+    ///
+    /// The socket MUST already be connected (see [`UdpSocket::connect`]) to the
+    /// address of the device, as all frames are sent to the connected address.
+    ///
+    /// See [`HostClient::try_new_udp`] for more details
+    pub fn new_udp_from_socket(
+        socket: UdpSocket,
+        err_uri_path: &str,
+        outgoing_depth: usize,
+        seq_no_kind: VarSeqKind,
+    ) -> Self {
+        let socket = Arc::new(socket);
+        let mut config = HostClientConfig::new(seq_no_kind, err_uri_path, outgoing_depth);
+        config.max_frame_len = Some(MAX_DATAGRAM_SIZE);
+
+        HostClient::new_with_wire_and_config(
+            UdpWireTx {
+                socket: socket.clone(),
+            },
+            UdpWireRx {
+                socket,
+                buf: vec![0u8; MAX_DATAGRAM_SIZE].into_boxed_slice(),
+            },
+            UdpSpawn,
+            &config,
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Wire Interface Implementation
+//////////////////////////////////////////////////////////////////////////////
+
+/// Tokio UDP Wire Interface Implementor
+///
+/// Uses Tokio for spawning tasks
+struct UdpSpawn;
+
+impl WireSpawn for UdpSpawn {
+    fn spawn(&mut self, fut: impl Future<Output = ()> + Send + 'static) {
+        // Explicitly drop the joinhandle as it impls Future and this makes
+        // clippy mad if you just let it drop implicitly
+        core::mem::drop(tokio::task::spawn(fut));
+    }
+}
+
+/// Tokio UDP Wire Transmit Interface Implementor
+struct UdpWireTx {
+    socket: Arc<UdpSocket>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum UdpWireTxError {
+    #[error("Transfer Error on Send")]
+    Transfer(#[from] std::io::Error),
+}
+
+impl WireTx for UdpWireTx {
+    type Error = UdpWireTxError;
+
+    #[inline]
+    fn send(&mut self, data: Vec<u8>) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.send_inner(data)
+    }
+}
+
+impl UdpWireTx {
+    async fn send_inner(&mut self, data: Vec<u8>) -> Result<(), UdpWireTxError> {
+        // Too-large frames are rejected by the HostClient before they are queued
+        self.socket.send(&data).await?;
+        Ok(())
+    }
+}
+
+/// Tokio UDP Wire Receive Interface Implementor
+struct UdpWireRx {
+    socket: Arc<UdpSocket>,
+    buf: Box<[u8]>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum UdpWireRxError {
+    #[error("Transfer Error on Recv")]
+    Transfer(#[from] std::io::Error),
+}
+
+impl WireRx for UdpWireRx {
+    type Error = UdpWireRxError;
+
+    #[inline]
+    fn receive(&mut self) -> impl Future<Output = Result<Vec<u8>, Self::Error>> + Send {
+        self.recv_inner()
+    }
+}
+
+impl UdpWireRx {
+    async fn recv_inner(&mut self) -> Result<Vec<u8>, UdpWireRxError> {
+        loop {
+            let used = match self.socket.recv(&mut self.buf).await {
+                Ok(used) => used,
+                // Some platforms report an ICMP "port unreachable" for an earlier
+                // send as an error on the next receive. The device may just not
+                // be listening yet, so this is not fatal.
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                    tracing::warn!("Device unreachable: {e:?}");
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // The minimum size of a message is 3 bytes: one for the
+            // discriminant, one for the key, and one for the seq_no.
+            if used >= 3 {
+                return Ok(self.buf[..used].to_vec());
+            }
+            tracing::warn!("Ignoring too-short message: {used} bytes");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Uploads larger than a single frame, with per-chunk acknowledgements
+
+use core::num::NonZeroUsize;
+
+use postcard_schema::Schema;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    header::{VarHeader, VarKey},
+    host_client::{HostClient, HostErr, RpcFrame},
+    standard_icd::{UploadChunk, UploadStatus},
+    UploadEndpoint,
+};
+
+/// # Upload Methods
+impl<WireErr> HostClient<WireErr>
+where
+    WireErr: DeserializeOwned + Schema,
+{
+    /// Upload `data` to an [`UploadEndpoint`], in chunks of at most `chunk_size` bytes,
+    /// and await the final [Done][UploadEndpoint::Done] response.
+    ///
+    /// `chunk_size` must leave enough room for the header and [`UploadChunk`]
+    /// fields in the server's receive buffer.
+    ///
+    /// See [`Self::send_upload_with_progress`] for details.
+    pub async fn send_upload<E: UploadEndpoint>(
+        &self,
+        data: &[u8],
+        chunk_size: NonZeroUsize,
+    ) -> Result<E::Done, HostErr<WireErr>>
+    where
+        E::Done: DeserializeOwned,
+    {
+        self.send_upload_with_progress::<E>(data, chunk_size, |_, _| {})
+            .await
+    }
+
+    /// Like [`Self::send_upload`], but calls `progress` with the number of
+    /// acknowledged bytes and the total number of bytes after each chunk.
+    ///
+    /// Only one chunk is in flight at a time, and each chunk continues from the
+    /// `next_offset` of the previous acknowledgement. Servers using
+    /// [`define_dispatch!`][crate::define_dispatch] acknowledge the end of each
+    /// received chunk. If the server answers with `Done` before all data was
+    /// sent, e.g. to report an error, the upload stops early.
+    ///
+    /// [`HostClientConfig::resp_timeout`][crate::host_client::HostClientConfig::resp_timeout]
+    /// applies to each chunk. Uploads must be smaller than 4GiB, larger ones fail
+    /// with [`HostErr::FrameTooLarge`].
+    pub async fn send_upload_with_progress<E: UploadEndpoint>(
+        &self,
+        data: &[u8],
+        chunk_size: NonZeroUsize,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<E::Done, HostErr<WireErr>>
+    where
+        E::Done: DeserializeOwned,
+    {
+        let total_len = data.len();
+        let Ok(total_len_u32) = u32::try_from(total_len) else {
+            return Err(HostErr::FrameTooLarge);
+        };
+
+        // All chunks use the same sequence number
+        let seq = self.ctx.seqs.allocate().await;
+        let mut offset = 0usize;
+
+        loop {
+            let end = total_len.min(offset.saturating_add(chunk_size.get()));
+            let chunk = UploadChunk {
+                offset: offset as u32,
+                total_len: total_len_u32,
+                data: &data[offset..end],
+            };
+            let frame = RpcFrame {
+                header: VarHeader {
+                    key: VarKey::Key8(E::REQ_KEY),
+                    seq_no: seq.seq_no(),
+                },
+                body: postcard::to_stdvec(&chunk).expect("Allocations should not ever fail"),
+            };
+            let resp = self
+                .send_resp_raw_inner(frame, E::RESP_KEY, self.ctx.resp_timeout)
+                .await?;
+
+            match postcard::from_bytes::<UploadStatus<E::Done>>(&resp.body)? {
+                UploadStatus::Done(done) => {
+                    progress(end, total_len);
+                    return Ok(done);
+                }
+                UploadStatus::Ack { next_offset } => {
+                    let next_offset = next_offset as usize;
+                    // Once all data was received, the server must answer with `Done`
+                    if next_offset >= total_len {
+                        return Err(HostErr::BadResponse);
+                    }
+                    offset = next_offset;
+                    progress(offset, total_len);
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use core::time::Duration;
+use core::{num::NonZeroUsize, time::Duration};
 // the contents of this file can probably be moved up to `mod.rs`
 use std::{fmt::Debug, sync::Arc};
 
@@ -12,9 +12,10 @@ use tokio::{
 use tracing::{debug, trace, warn};
 
 use crate::{
-    header::{VarHeader, VarKey, VarSeqKind},
+    header::{VarHeader, VarKey, VarSeq, VarSeqKind},
     host_client::{
-        HostClient, HostContext, ProcessError, RpcFrame, WireContext, WireRx, WireSpawn, WireTx,
+        announce::topic_worker, ConnectionState, HostClient, HostContext, ProcessError, RpcFrame,
+        TopicStats, WireContext, WireRx, WireSpawn, WireTx,
     },
     Key,
 };
@@ -23,9 +24,49 @@ use crate::{
 pub(crate) struct Subscriptions {
     pub(crate) exclusive_list: Vec<(Key, mpsc::Sender<RpcFrame>)>,
     pub(crate) broadcast_list: Vec<(Key, broadcast::Sender<RpcFrame>)>,
+    pub(crate) stream_list: Vec<StreamSub>,
+    pub(crate) topic_stats: Vec<(Key, TopicStats)>,
     pub(crate) stopped: bool,
 }
 
+impl Subscriptions {
+    /// Get the counters of an exclusive subscription, creating them if needed
+    pub(crate) fn stats_mut(&mut self, key: Key) -> &mut TopicStats {
+        let idx = match self.topic_stats.iter().position(|(k, _)| *k == key) {
+            Some(idx) => idx,
+            None => {
+                self.topic_stats.push((key, TopicStats::default()));
+                self.topic_stats.len() - 1
+            }
+        };
+        &mut self.topic_stats[idx].1
+    }
+}
+
+/// What happened to a message for an exclusive subscription
+enum Delivery {
+    Received,
+    /// Received after waiting for room in the channel
+    Late,
+    Dropped,
+}
+
+/// A pending response stream, started by [`HostClient::send_stream`]
+#[derive(Debug)]
+pub(crate) struct StreamSub {
+    pub(crate) seq_no: VarSeq,
+    pub(crate) resp_key: Key,
+    pub(crate) err_key: Key,
+    pub(crate) tx: mpsc::Sender<RpcFrame>,
+}
+
+impl StreamSub {
+    fn matches(&self, hdr: &VarHeader) -> bool {
+        self.seq_no == hdr.seq_no
+            && (VarKey::Key8(self.resp_key) == hdr.key || VarKey::Key8(self.err_key) == hdr.key)
+    }
+}
+
 /// A basic cancellation-token
 ///
 /// Used to terminate (and signal termination of) worker tasks
@@ -71,6 +112,10 @@ impl Default for Stopper {
 }
 
 /// HostClient configuration
+///
+/// Created with [`HostClientConfig::new()`], with the other fields then set
+/// as needed.
+#[non_exhaustive]
 pub struct HostClientConfig<'c> {
     /// The sequence kind to use
     pub seq_kind: VarSeqKind,
@@ -85,6 +130,67 @@ pub struct HostClientConfig<'c> {
     ///
     /// Does not apply to subscribe_multi channels.
     pub subscriber_timeout_if_full: Duration,
+
+    /// Default timeout for `send_resp` and `send_resp_raw`.
+    ///
+    /// If `None`, these wait for a response potentially forever.
+    pub resp_timeout: Option<Duration>,
+
+    /// The maximum number of requests waiting for a response at once.
+    ///
+    /// Further requests wait to be sent, in order of their [`Priority`][crate::host_client::Priority].
+    /// Response streams only count until their request is sent. If `None`, the
+    /// number is only limited by the sequence numbers available.
+    pub max_in_flight: Option<NonZeroUsize>,
+
+    /// The largest frame, header and body, the transport can carry.
+    ///
+    /// Larger requests fail with [`HostErr::FrameTooLarge`][crate::host_client::HostErr::FrameTooLarge],
+    /// and larger published messages with
+    /// [`PublishError::FrameTooLarge`][crate::host_client::PublishError::FrameTooLarge],
+    /// before they are queued. If `None`, frames of any size are sent.
+    pub max_frame_len: Option<usize>,
+
+    /// Negotiate the protocol whenever the client (re)connects.
+    ///
+    /// See [`HostClient::negotiate_protocol`][crate::host_client::HostClient::negotiate_protocol].
+    /// If `false`, the protocol is only negotiated when asked to.
+    pub negotiate_protocol: bool,
+}
+
+impl<'c> HostClientConfig<'c> {
+    /// Create a new configuration
+    ///
+    /// Messages are never dropped for full subscribe channels, there is no
+    /// default response timeout, no limit on the requests in flight or the
+    /// frame size, and the protocol is not negotiated on connect.
+    pub fn new(seq_kind: VarSeqKind, err_uri_path: &'c str, outgoing_depth: usize) -> Self {
+        Self {
+            seq_kind,
+            err_uri_path,
+            outgoing_depth,
+            subscriber_timeout_if_full: Duration::ZERO,
+            resp_timeout: None,
+            max_in_flight: None,
+            max_frame_len: None,
+            negotiate_protocol: false,
+        }
+    }
+
+    /// A copy of this configuration, using another `err_uri_path`
+    #[cfg(all(feature = "raw-nusb", not(target_family = "wasm")))]
+    pub(crate) fn with_err_uri_path<'d>(&self, err_uri_path: &'d str) -> HostClientConfig<'d> {
+        HostClientConfig {
+            seq_kind: self.seq_kind,
+            err_uri_path,
+            outgoing_depth: self.outgoing_depth,
+            subscriber_timeout_if_full: self.subscriber_timeout_if_full,
+            resp_timeout: self.resp_timeout,
+            max_in_flight: self.max_in_flight,
+            max_frame_len: self.max_frame_len,
+            negotiate_protocol: self.negotiate_protocol,
+        }
+    }
 }
 
 impl<WireErr> HostClient<WireErr>
@@ -108,12 +214,7 @@ where
         WRX: WireRx,
         WSP: WireSpawn,
     {
-        let config = HostClientConfig {
-            seq_kind,
-            err_uri_path,
-            outgoing_depth,
-            subscriber_timeout_if_full: Duration::ZERO,
-        };
+        let config = HostClientConfig::new(seq_kind, err_uri_path, outgoing_depth);
 
         Self::new_with_wire_and_config(tx, rx, sp, &config)
     }
@@ -133,11 +234,16 @@ where
         WRX: WireRx,
         WSP: WireSpawn,
     {
-        let (me, wire_ctx) = Self::new_manual_priv(config);
+        let (me, wire_ctx, released) = Self::new_manual_priv(config);
 
         let WireContext { outgoing, incoming } = wire_ctx;
 
-        sp.spawn(out_worker(tx, outgoing, me.stopper.clone()));
+        sp.spawn(topic_worker(
+            me.downgrade(),
+            released,
+            me.connection_state(),
+        ));
+        sp.spawn(out_worker(tx, outgoing, me.ctx.clone(), me.stopper.clone()));
         sp.spawn(in_worker(
             rx,
             incoming,
@@ -150,13 +256,18 @@ where
 }
 
 /// Output worker, feeding frames to the `Client`.
-async fn out_worker<W>(wire: W, rec: mpsc::Receiver<RpcFrame>, stop: Stopper)
-where
+async fn out_worker<W>(
+    wire: W,
+    rec: mpsc::Receiver<RpcFrame>,
+    host_ctx: Arc<HostContext>,
+    stop: Stopper,
+) where
     W: WireTx,
     W::Error: Debug,
 {
+    let mut rec = rec;
     let cancel_fut = stop.wait_stopped();
-    let operate_fut = out_worker_inner(wire, rec);
+    let operate_fut = out_worker_inner(wire, &mut rec, &host_ctx);
     select! {
         biased;
         _ = cancel_fut => {},
@@ -167,7 +278,14 @@ where
     }
 }
 
-async fn out_worker_inner<W>(mut wire: W, mut rec: mpsc::Receiver<RpcFrame>)
+/// Returns `true` if the outgoing queue was closed, `false` if the wire failed
+///
+/// Frames are sent with the protocol version agreed with the server.
+pub(crate) async fn out_worker_inner<W>(
+    mut wire: W,
+    rec: &mut mpsc::Receiver<RpcFrame>,
+    host_ctx: &HostContext,
+) -> bool
 where
     W: WireTx,
     W::Error: Debug,
@@ -175,11 +293,13 @@ where
     loop {
         let Some(msg) = rec.recv().await else {
             tracing::warn!("Receiver Closed, this could be bad");
-            return;
+            return true;
         };
-        if let Err(e) = wire.send(msg.to_bytes()).await {
+        let mut frame = msg.to_bytes();
+        VarHeader::set_version(&mut frame, host_ctx.protocol_version());
+        if let Err(e) = wire.send(frame).await {
             tracing::error!("Output Queue Error: {e:?}, exiting");
-            return;
+            return false;
         }
     }
 }
@@ -195,7 +315,7 @@ async fn in_worker<W>(
     W::Error: Debug,
 {
     let cancel_fut = stop.wait_stopped();
-    let operate_fut = in_worker_inner(wire, host_ctx, subscriptions.clone());
+    let operate_fut = in_worker_inner(wire, host_ctx.clone(), subscriptions.clone());
     select! {
         biased;
         _ = cancel_fut => {},
@@ -204,15 +324,21 @@ async fn in_worker<W>(
             stop.stop();
         },
     }
-    // If we stop, purge the subscription list so that it is clear that no more messages are coming
+    purge_subscriptions(&subscriptions).await;
+    host_ctx.state.send_replace(ConnectionState::Closed);
+}
+
+/// If we stop, purge the subscription list so that it is clear that no more messages are coming
+pub(crate) async fn purge_subscriptions(subscriptions: &Mutex<Subscriptions>) {
     // TODO: Have a "stopped" flag to prevent later additions (e.g. sub after store?)
     let mut guard = subscriptions.lock().await;
     guard.stopped = true;
     guard.exclusive_list.clear();
     guard.broadcast_list.clear();
+    guard.stream_list.clear();
 }
 
-async fn in_worker_inner<W>(
+pub(crate) async fn in_worker_inner<W>(
     mut wire: W,
     host_ctx: Arc<HostContext>,
     subscriptions: Arc<Mutex<Subscriptions>>,
@@ -230,6 +356,12 @@ async fn in_worker_inner<W>(
             warn!("Header decode error!");
             continue;
         };
+        // Version zero frames are always accepted, e.g. replies to a new negotiation
+        let version = VarHeader::version_of(&res).unwrap_or(0);
+        if version != 0 && version != host_ctx.protocol_version() {
+            warn!("Dropping a frame of protocol version {version}, which was not agreed");
+            continue;
+        }
 
         trace!("in_worker received {hdr:?}");
 
@@ -241,7 +373,44 @@ async fn in_worker_inner<W>(
 
             // Remove if sending fails
             //
-            // First, check the broadcast channels
+            // First, check the pending response streams
+            if let Some(idx) = subs_guard.stream_list.iter().position(|s| s.matches(&hdr)) {
+                let frame = RpcFrame {
+                    header: hdr,
+                    body: body.to_vec(),
+                };
+                let m = &subs_guard.stream_list[idx].tx;
+                let closed = match m.try_send(frame) {
+                    Ok(()) => {
+                        trace!("Handled message via response stream");
+                        false
+                    }
+                    Err(mpsc::error::TrySendError::Full(_))
+                        if host_ctx.subscription_timeout.is_zero() =>
+                    {
+                        tracing::error!("Response stream channel full! Message dropped.");
+                        false
+                    }
+                    Err(mpsc::error::TrySendError::Full(frame)) => {
+                        tokio::select! {
+                            // send returns an error if the channel is closed
+                            r = m.send(frame) => r.is_err(),
+                            _ = tokio::time::sleep(host_ctx.subscription_timeout) => {
+                                tracing::error!("Response stream channel full! Message dropped.");
+                                false
+                            }
+                        }
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => true,
+                };
+                if closed {
+                    debug!("Dropping response stream");
+                    subs_guard.stream_list.swap_remove(idx);
+                }
+                continue;
+            }
+
+            // Then, check the broadcast channels
             let remove_mul_sub = if let Some((_h, m)) = subs_guard
                 .broadcast_list
                 .iter()
@@ -266,12 +435,13 @@ async fn in_worker_inner<W>(
                 false
             };
 
-            let remove_exl_sub = if let Some((_h, m)) = subs_guard
+            let remove_exl_sub = if let Some((sub_key, m)) = subs_guard
                 .exclusive_list
                 .iter()
                 .find(|(k, _)| VarKey::Key8(*k) == key)
             {
                 handled = true;
+                let sub_key = *sub_key;
                 let frame = RpcFrame {
                     header: hdr,
                     body: body.to_vec(),
@@ -279,29 +449,43 @@ async fn in_worker_inner<W>(
 
                 let res = m.try_send(frame);
 
-                match res {
+                let (closed, delivery) = match res {
                     Ok(()) => {
                         trace!("Handled message via subscription");
-                        false
+                        (false, Delivery::Received)
                     }
                     Err(mpsc::error::TrySendError::Full(_))
                         if host_ctx.subscription_timeout.is_zero() =>
                     {
                         tracing::error!("Subscription channel full! Message dropped.");
-                        false
+                        (false, Delivery::Dropped)
                     }
                     Err(mpsc::error::TrySendError::Full(frame)) => {
                         tokio::select! {
                             // send returns an error if the channel is closed
-                            r = m.send(frame) => r.is_err(),
+                            r = m.send(frame) => match r {
+                                Ok(()) => (false, Delivery::Late),
+                                Err(_) => (true, Delivery::Dropped),
+                            },
                             _ = tokio::time::sleep(host_ctx.subscription_timeout) => {
                                 tracing::error!("Subscription channel full! Message dropped.");
-                                false
+                                (false, Delivery::Dropped)
                             }
                         }
                     }
-                    Err(mpsc::error::TrySendError::Closed(_)) => true,
+                    Err(mpsc::error::TrySendError::Closed(_)) => (true, Delivery::Dropped),
+                };
+
+                let stats = subs_guard.stats_mut(sub_key);
+                match delivery {
+                    Delivery::Received => stats.received += 1,
+                    Delivery::Late => {
+                        stats.received += 1;
+                        stats.late += 1;
+                    }
+                    Delivery::Dropped => stats.dropped += 1,
                 }
+                closed
             } else {
                 false
             };
@@ -392,6 +392,47 @@ pub trait Endpoint {
     const RESP_KEY1: Key1 = Key1::from_key8(Self::RESP_KEY);
 }
 
+/// A marker trait denoting an [Endpoint] with a typed error
+///
+/// The Response of these endpoints is `Result<Self::Ok, Self::Error>`, so
+/// handlers can report endpoint-specific failures, separately from the
+/// protocol-level `WireErr`.
+///
+/// Typically implemented by the [endpoints] macro, using the `ErrorTy` column.
+pub trait FallibleEndpoint: Endpoint<Response = Result<Self::Ok, Self::Error>> {
+    /// The type of a successful Response
+    type Ok: Schema;
+    /// The type of a failed Response
+    type Error: Schema;
+}
+
+/// A marker trait denoting an [Endpoint] that streams its response
+///
+/// A single request is answered with any number of `Some(Item)` responses,
+/// followed by a `None` marking the end of the stream. All of these use the
+/// sequence number of the request.
+///
+/// Typically implemented by the [endpoints] macro, by adding `stream` after
+/// the `ResponseTy`.
+pub trait StreamEndpoint: Endpoint<Response = Option<Self::Item>> {
+    /// The type of each streamed item
+    type Item: Schema;
+}
+
+/// A marker trait denoting an [Endpoint] that accepts uploads larger than a single frame
+///
+/// The Request of these endpoints is an [UploadChunk][standard_icd::UploadChunk].
+/// The payload is split into chunks that all use the same sequence number, and
+/// each chunk is acknowledged by the server before the next one is sent. The
+/// last chunk is answered with the final `Done` response instead.
+///
+/// Typically implemented by the [endpoints] macro, by adding `upload` after
+/// the `ResponseTy`.
+pub trait UploadEndpoint: Endpoint<Response = standard_icd::UploadStatus<Self::Done>> {
+    /// The type of the final response
+    type Done: Schema;
+}
+
 /// A marker trait denoting a single topic
 ///
 /// Unlike [Endpoint]s, [Topic]s are unidirectional, and can be sent
@@ -89,12 +89,108 @@ macro_rules! endpoint {
 ///     | Endpoint2      | Req2          | Resp2         | "endpoints/two"   |
 /// }
 /// ```
+///
+/// An `ErrorTy` column may be added to give endpoints a typed error. Handlers for
+/// these endpoints return `Result<ResponseTy, ErrorTy>`, which is what is sent on
+/// the wire, and the marker type also implements [FallibleEndpoint][crate::FallibleEndpoint].
+/// Use `-` for endpoints that can't fail.
+///
+/// ```rust
+/// # use postcard_schema::Schema;
+/// # use serde::{Serialize, Deserialize};
+/// use postcard_rpc::endpoints;
+///
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// pub struct Reading(u32);
+///
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// pub enum SensorError {
+///     NotReady,
+///     Overrange,
+/// }
+///
+/// endpoints!{
+///     list = ENDPOINTS_LIST;
+///     | EndpointTy     | RequestTy     | ResponseTy    | ErrorTy       | Path              |
+///     | ----------     | ---------     | ----------    | -------       | ----              |
+///     | ReadEndpoint   | u8            | Reading       | SensorError   | "sensor/read"     |
+///     | ResetEndpoint  | ()            | ()            | -             | "sensor/reset"    |
+/// }
+/// ```
+///
+/// Adding `stream` after the `ResponseTy` makes a server-streaming endpoint, where
+/// the server answers a single request with many responses. The marker type also
+/// implements [StreamEndpoint][crate::StreamEndpoint]. If an `ErrorTy` is given,
+/// each item of the stream is a `Result<ResponseTy, ErrorTy>`.
+///
+/// ```rust
+/// # use postcard_schema::Schema;
+/// # use serde::{Serialize, Deserialize};
+/// use postcard_rpc::endpoints;
+///
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// pub struct StartAccel {
+///     count: u32,
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// pub struct Accel {
+///     x: i16,
+///     y: i16,
+///     z: i16,
+/// }
+///
+/// endpoints!{
+///     list = ENDPOINTS_LIST;
+///     | EndpointTy     | RequestTy     | ResponseTy    | Path              |
+///     | ----------     | ---------     | ----------    | ----              |
+///     | AccelEndpoint  | StartAccel    | Accel stream  | "accel/stream"    |
+/// }
+/// ```
+///
+/// Adding `upload` after the `ResponseTy` makes an upload endpoint, for payloads
+/// that don't fit in a single frame. The `RequestTy` must be
+/// [UploadChunk][crate::standard_icd::UploadChunk], and the marker type also
+/// implements [UploadEndpoint][crate::UploadEndpoint]. The `ResponseTy` (or
+/// `Result<ResponseTy, ErrorTy>`) is sent once the upload has finished.
+///
+/// ```rust
+/// # use postcard_schema::Schema;
+/// # use serde::{Serialize, Deserialize};
+/// use postcard_rpc::{endpoints, standard_icd::UploadChunk};
+///
+/// #[derive(Debug, Serialize, Deserialize, Schema)]
+/// pub struct FirmwareCrc(u32);
+///
+/// endpoints!{
+///     list = ENDPOINTS_LIST;
+///     | EndpointTy        | RequestTy         | ResponseTy            | Path              |
+///     | ----------        | ---------         | ----------            | ----              |
+///     | FirmwareEndpoint  | UploadChunk<'a>   | FirmwareCrc upload    | "firmware/write"  |
+/// }
+/// ```
+///
+/// Other endpoint lists can be added to the generated list with `include`, e.g.
+/// to also serve the optional [blob endpoints][crate::standard_icd::STANDARD_ICD_BLOB_ENDPOINTS]
+/// from the standard ICD.
+///
+/// ```rust
+/// use postcard_rpc::{endpoints, standard_icd::STANDARD_ICD_BLOB_ENDPOINTS};
+///
+/// endpoints!{
+///     list = ENDPOINTS_LIST;
+///     include = [STANDARD_ICD_BLOB_ENDPOINTS];
+///     | EndpointTy     | RequestTy     | ResponseTy    | Path              |
+///     | ----------     | ---------     | ----------    | ----              |
+///     | ResetEndpoint  | ()            | ()            | "reset"           |
+/// }
+/// ```
 #[macro_export]
 macro_rules! endpoints {
-    (@ep_tys $([[$($meta:meta)?] $ep_name:ident])*) => {
-        $crate::endpoints!(@ep_tys omit_std=false; $([[$($meta)?] $ep_name])*)
+    (@ep_tys [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
+        $crate::endpoints!(@ep_tys omit_std=false; [$($inc),*] $([[$($meta)?] $ep_name])*)
     };
-    (@ep_tys omit_std=true; $([[$($meta:meta)?] $ep_name:ident])*) => {
+    (@ep_tys omit_std=true; [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
         const {
             const LISTS: &[&[&'static postcard_schema::schema::NamedType]] = &[
                 $(
@@ -103,6 +199,9 @@ macro_rules! endpoints {
                     $(#[$meta])?
                     $crate::unique_types!(<$ep_name as $crate::Endpoint>::Response),
                 )*
+                $(
+                    $inc.types,
+                )*
             ];
 
             const TTL_COUNT: usize = $crate::uniques::total_len(LISTS);
@@ -111,10 +210,10 @@ macro_rules! endpoints {
             SMALL_RPT.as_slice()
         }
     };
-    (@ep_tys omit_std=false; $([[$($meta:meta)?] $ep_name:ident])*) => {
+    (@ep_tys omit_std=false; [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
         const {
             const USER_TYS: &[&'static postcard_schema::schema::NamedType] =
-                $crate::endpoints!(@ep_tys omit_std=true; $([[$($meta)?] $ep_name])*);
+                $crate::endpoints!(@ep_tys omit_std=true; [$($inc),*] $([[$($meta)?] $ep_name])*);
             const STD_TYS: &[&'static postcard_schema::schema::NamedType]
                 = $crate::standard_icd::STANDARD_ICD_ENDPOINTS.types;
 
@@ -127,25 +226,38 @@ macro_rules! endpoints {
             SMALL_RPT.as_slice()
         }
     };
-    (@ep_eps $([[$($meta:meta)?] $ep_name:ident])*) => {
-        $crate::endpoints!(@ep_eps omit_std=false; $([[$($meta)?] $ep_name])*)
+    (@ep_eps [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
+        $crate::endpoints!(@ep_eps omit_std=false; [$($inc),*] $([[$($meta)?] $ep_name])*)
     };
-    (@ep_eps omit_std=true; $([[$($meta:meta)?] $ep_name:ident])*) => {
-        &[
-            $(
-                $(#[$meta])?
-                (
-                    <$ep_name as $crate::Endpoint>::PATH,
-                    <$ep_name as $crate::Endpoint>::REQ_KEY,
-                    <$ep_name as $crate::Endpoint>::RESP_KEY,
-                ),
-            )*
-        ]
+    (@ep_eps omit_std=true; [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
+        const {
+            const OWN_EPS: &[(&str, $crate::Key, $crate::Key)] = &[
+                $(
+                    $(#[$meta])?
+                    (
+                        <$ep_name as $crate::Endpoint>::PATH,
+                        <$ep_name as $crate::Endpoint>::REQ_KEY,
+                        <$ep_name as $crate::Endpoint>::RESP_KEY,
+                    ),
+                )*
+            ];
+            const NULL_KEY: $crate::Key = unsafe { $crate::Key::from_bytes([0u8; 8]) };
+            const SLI: &[&[(&str, $crate::Key, $crate::Key)]] = &[
+                OWN_EPS,
+                $(
+                    $inc.endpoints,
+                )*
+            ];
+            const LEN: usize = $crate::uniques::total_len(SLI);
+            const ARR: [(&str, $crate::Key, $crate::Key); LEN] =
+                $crate::uniques::combine_with_copy(SLI, ("", NULL_KEY, NULL_KEY));
+            ARR.as_slice()
+        }
     };
-    (@ep_eps omit_std=false; $([[$($meta:meta)?] $ep_name:ident])*) => {
+    (@ep_eps omit_std=false; [$($inc:path),*] $([[$($meta:meta)?] $ep_name:ident])*) => {
         const {
             const USER_EPS: &[(&str, $crate::Key, $crate::Key)] =
-                $crate::endpoints!(@ep_eps omit_std=true; $([[$($meta)?] $ep_name])*);
+                $crate::endpoints!(@ep_eps omit_std=true; [$($inc),*] $([[$($meta)?] $ep_name])*);
             const NULL_KEY: $crate::Key = unsafe { $crate::Key::from_bytes([0u8; 8]) };
             const STD_EPS: &[(&str, $crate::Key, $crate::Key)] =
                 $crate::standard_icd::STANDARD_ICD_ENDPOINTS.endpoints;
@@ -157,41 +269,154 @@ macro_rules! endpoints {
             }
         }
     };
+    (@ep_impl [$($meta:meta)?] $ep_name:ident [$($req_lt:lifetime),*] [$($resp_lt:lifetime),*] [$($req:tt)*] [$($req_key:tt)*] [$($resp:tt)*] [$($resp_key:tt)*] $path_str:literal) => {
+        /// Macro Generated Marker Type
+        $(#[$meta])?
+        pub struct $ep_name < $($req_lt,)* $($resp_lt,)* > {
+            _plt_req: core::marker::PhantomData<($(& $req_lt (),)*)>,
+            _plt_resp: core::marker::PhantomData<($(& $resp_lt (),)*)>,
+            _priv: core::marker::PhantomData<()>,
+        }
+
+        $(#[$meta])?
+        impl < $($req_lt,)* $($resp_lt,)* > $crate::Endpoint for $ep_name < $($req_lt,)* $($resp_lt,)* > {
+            type Request = $($req)*;
+            type Response = $($resp)*;
+            const PATH: &'static str = $path_str;
+            const REQ_KEY: $crate::Key = $crate::Key::for_path::<$($req_key)*>($path_str);
+            const RESP_KEY: $crate::Key = $crate::Key::for_path::<$($resp_key)*>($path_str);
+        }
+    };
+    // A plain endpoint
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [] [-] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [$resp_ty $(< $($resp_lt,)+ >)?] [$resp_ty]
+            $path_str
+        );
+    };
+    // An endpoint with a typed error
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [] [$err_ty:tt] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [core::result::Result<$resp_ty $(< $($resp_lt,)+ >)?, $err_ty>] [core::result::Result<$resp_ty, $err_ty>]
+            $path_str
+        );
+
+        $(#[$meta])?
+        impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::FallibleEndpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
+            type Ok = $resp_ty $(< $($resp_lt,)+ >)?;
+            type Error = $err_ty;
+        }
+    };
+    // A server-streaming endpoint
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [stream] [-] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [core::option::Option<$resp_ty $(< $($resp_lt,)+ >)?>] [core::option::Option<$resp_ty>]
+            $path_str
+        );
+
+        $(#[$meta])?
+        impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::StreamEndpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
+            type Item = $resp_ty $(< $($resp_lt,)+ >)?;
+        }
+    };
+    // A server-streaming endpoint, where each item may be a typed error
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [stream] [$err_ty:tt] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [core::option::Option<core::result::Result<$resp_ty $(< $($resp_lt,)+ >)?, $err_ty>>] [core::option::Option<core::result::Result<$resp_ty, $err_ty>>]
+            $path_str
+        );
+
+        $(#[$meta])?
+        impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::StreamEndpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
+            type Item = core::result::Result<$resp_ty $(< $($resp_lt,)+ >)?, $err_ty>;
+        }
+    };
+    // An upload endpoint
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [upload] [-] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [$crate::standard_icd::UploadStatus<$resp_ty $(< $($resp_lt,)+ >)?>] [$crate::standard_icd::UploadStatus<$resp_ty>]
+            $path_str
+        );
+
+        $(#[$meta])?
+        impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::UploadEndpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
+            type Done = $resp_ty $(< $($resp_lt,)+ >)?;
+        }
+    };
+    // An upload endpoint, where the final response may be a typed error
+    (@ep_def [$($meta:meta)?] $ep_name:ident [$req_ty:tt $(< $($req_lt:lifetime),+ >)?] [$resp_ty:tt $(< $($resp_lt:lifetime),+ >)?] [upload] [$err_ty:tt] $path_str:literal) => {
+        $crate::endpoints!(
+            @ep_impl [$($meta)?] $ep_name [$($($req_lt),+)?] [$($($resp_lt),+)?]
+            [$req_ty $(< $($req_lt,)+ >)?] [$req_ty]
+            [$crate::standard_icd::UploadStatus<core::result::Result<$resp_ty $(< $($resp_lt,)+ >)?, $err_ty>>] [$crate::standard_icd::UploadStatus<core::result::Result<$resp_ty, $err_ty>>]
+            $path_str
+        );
+
+        $(#[$meta])?
+        impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::UploadEndpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
+            type Done = core::result::Result<$resp_ty $(< $($resp_lt,)+ >)?, $err_ty>;
+        }
+    };
     (
            list = $list_name:ident;
            $(omit_std = $omit:tt;)?
+           $(include = [$($inc:path),* $(,)?];)?
            | EndpointTy     | RequestTy                                | ResponseTy                                  | Path              | $( Cfg           |)?
            | $(-)*          | $(-)*                                    | $(-)*                                       | $(-)*             | $($(-)*          |)?
-        $( | $ep_name:ident | $req_ty:tt $(< $($req_lt:lifetime),+ >)? | $resp_ty:tt $(< $($resp_lt:lifetime),+ >)?  | $path_str:literal | $($meta:meta)? $(|)? )*
+        $( | $ep_name:ident | $req_ty:tt $(< $($req_lt:lifetime),+ >)? | $resp_ty:tt $(< $($resp_lt:lifetime),+ >)? $($resp_kind:ident)? | $path_str:literal | $($meta:meta)? $(|)? )*
     ) => {
         // struct definitions and trait impls
         $(
-            /// Macro Generated Marker Type
-            $(#[$meta])?
-            pub struct $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
-                $(
-                    _plt_req: core::marker::PhantomData<($(& $req_lt (),)+)>,
-                )?
-                $(
-                    _plt_resp: core::marker::PhantomData<($(& $resp_lt (),)+)>,
-                )?
-                _priv: core::marker::PhantomData<()>,
-            }
+            $crate::endpoints!(
+                @ep_def [$($meta)?] $ep_name
+                [$req_ty $(< $($req_lt),+ >)?]
+                [$resp_ty $(< $($resp_lt),+ >)?]
+                [$($resp_kind)?]
+                [-]
+                $path_str
+            );
+        )*
 
-            $(#[$meta])?
-            impl < $($($req_lt,)+)? $($($resp_lt,)+)? > $crate::Endpoint for $ep_name < $($($req_lt,)+)? $($($resp_lt,)+)? > {
-                type Request = $req_ty $(< $($req_lt,)+ >)?;
-                type Response = $resp_ty $(< $($resp_lt,)+ >)?;
-                const PATH: &'static str = $path_str;
-                const REQ_KEY: $crate::Key = $crate::Key::for_path::<$req_ty>($path_str);
-                const RESP_KEY: $crate::Key = $crate::Key::for_path::<$resp_ty>($path_str);
-            }
+        /// Macro Generated Endpoint Map
+        pub const $list_name: $crate::EndpointMap = $crate::EndpointMap {
+            types: $crate::endpoints!(@ep_tys $(omit_std = $omit;)? [$($($inc),*)?] $([[$($meta)?] $ep_name])*),
+            endpoints: $crate::endpoints!(@ep_eps $(omit_std = $omit;)? [$($($inc),*)?] $([[$($meta)?] $ep_name])*),
+        };
+    };
+    (
+           list = $list_name:ident;
+           $(omit_std = $omit:tt;)?
+           $(include = [$($inc:path),* $(,)?];)?
+           | EndpointTy     | RequestTy                                | ResponseTy                                  | ErrorTy      | Path              | $( Cfg           |)?
+           | $(-)*          | $(-)*                                    | $(-)*                                       | $(-)*        | $(-)*             | $($(-)*          |)?
+        $( | $ep_name:ident | $req_ty:tt $(< $($req_lt:lifetime),+ >)? | $resp_ty:tt $(< $($resp_lt:lifetime),+ >)? $($resp_kind:ident)? | $err_ty:tt   | $path_str:literal | $($meta:meta)? $(|)? )*
+    ) => {
+        // struct definitions and trait impls
+        $(
+            $crate::endpoints!(
+                @ep_def [$($meta)?] $ep_name
+                [$req_ty $(< $($req_lt),+ >)?]
+                [$resp_ty $(< $($resp_lt),+ >)?]
+                [$($resp_kind)?]
+                [$err_ty]
+                $path_str
+            );
         )*
 
         /// Macro Generated Endpoint Map
         pub const $list_name: $crate::EndpointMap = $crate::EndpointMap {
-            types: $crate::endpoints!(@ep_tys $(omit_std = $omit;)? $([[$($meta)?] $ep_name])*),
-            endpoints: $crate::endpoints!(@ep_eps $(omit_std = $omit;)? $([[$($meta)?] $ep_name])*),
+            types: $crate::endpoints!(@ep_tys $(omit_std = $omit;)? [$($($inc),*)?] $([[$($meta)?] $ep_name])*),
+            endpoints: $crate::endpoints!(@ep_eps $(omit_std = $omit;)? [$($($inc),*)?] $([[$($meta)?] $ep_name])*),
         };
     };
 }
@@ -0,0 +1,128 @@
+//! Server side of the blob transfer endpoints
+//!
+//! The [blob endpoints][crate::standard_icd::STANDARD_ICD_BLOB_ENDPOINTS] are an
+//! optional part of the standard ICD. To serve them, include them in the endpoint
+//! list, and list them in the dispatcher using the handlers of this module. The
+//! context must implement [`BlobContext`]:
+//!
+//! ```rust,ignore
+//! use postcard_rpc::{
+//!     server::blob::{blob_close, blob_open, blob_read, blob_write},
+//!     standard_icd::{
+//!         BlobCloseEndpoint, BlobOpenEndpoint, BlobReadEndpoint, BlobWriteEndpoint,
+//!         STANDARD_ICD_BLOB_ENDPOINTS,
+//!     },
+//! };
+//!
+//! endpoints! {
+//!     list = ENDPOINT_LIST;
+//!     include = [STANDARD_ICD_BLOB_ENDPOINTS];
+//!     // ...
+//! }
+//!
+//! define_dispatch! {
+//!     // ...
+//!     endpoints: {
+//!         list: ENDPOINT_LIST;
+//!
+//!         | EndpointTy        | kind      | handler       |
+//!         | ----------        | ----      | -------       |
+//!         | BlobOpenEndpoint  | async     | blob_open     |
+//!         | BlobWriteEndpoint | async     | blob_write    |
+//!         | BlobReadEndpoint  | async     | blob_read     |
+//!         | BlobCloseEndpoint | async     | blob_close    |
+//!     };
+//!     // ...
+//! }
+//! ```
+
+#![allow(async_fn_in_trait)]
+
+use crate::{
+    header::VarHeader,
+    standard_icd::{
+        BlobClose, BlobData, BlobError, BlobInfo, BlobMode, BlobOpen, BlobRead, BlobSummary,
+        BlobWrite,
+    },
+};
+
+/// A storage for blobs, e.g. in flash or RAM
+pub trait BlobStorage {
+    /// Open the existing blob `name` for reading
+    async fn open_read(&mut self, name: &str) -> Result<BlobInfo, BlobError>;
+
+    /// Open the blob `name` for writing `len` bytes, with the checksum `crc`
+    ///
+    /// If a previous write of the same blob with the same `len` and `crc` was
+    /// interrupted, the storage may keep the data received so far, and return
+    /// where the client should continue in [`BlobInfo::offset`].
+    async fn open_write(&mut self, name: &str, len: u32, crc: u32) -> Result<BlobInfo, BlobError>;
+
+    /// Write `data` at `offset` to an open blob
+    async fn write(&mut self, handle: u32, offset: u32, data: &[u8]) -> Result<(), BlobError>;
+
+    /// Read up to `len` bytes at `offset` from an open blob
+    ///
+    /// Returns an empty slice at the end of the blob.
+    async fn read(&mut self, handle: u32, offset: u32, len: u32) -> Result<&[u8], BlobError>;
+
+    /// Close an open blob, returning the length and [`BlobCrc`][crate::standard_icd::BlobCrc]
+    /// of the stored data
+    async fn close(&mut self, handle: u32) -> Result<BlobSummary, BlobError>;
+}
+
+/// A trait for contexts that provide a [`BlobStorage`] to the blob handlers
+pub trait BlobContext {
+    /// The storage type
+    type Storage: BlobStorage;
+
+    /// Get the storage
+    fn blob_storage(&mut self) -> &mut Self::Storage;
+}
+
+/// Handler for [`BlobOpenEndpoint`][crate::standard_icd::BlobOpenEndpoint]
+pub async fn blob_open<C: BlobContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: BlobOpen<'_>,
+) -> Result<BlobInfo, BlobError> {
+    let storage = context.blob_storage();
+    match req.mode {
+        BlobMode::Read => storage.open_read(req.name).await,
+        BlobMode::Write { len, crc } => storage.open_write(req.name, len, crc).await,
+    }
+}
+
+/// Handler for [`BlobWriteEndpoint`][crate::standard_icd::BlobWriteEndpoint]
+pub async fn blob_write<C: BlobContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: BlobWrite<'_>,
+) -> Result<(), BlobError> {
+    context
+        .blob_storage()
+        .write(req.handle, req.offset, req.data)
+        .await
+}
+
+/// Handler for [`BlobReadEndpoint`][crate::standard_icd::BlobReadEndpoint]
+pub async fn blob_read<C: BlobContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: BlobRead,
+) -> Result<BlobData<'_>, BlobError> {
+    let data = context
+        .blob_storage()
+        .read(req.handle, req.offset, req.len)
+        .await?;
+    Ok(BlobData { data })
+}
+
+/// Handler for [`BlobCloseEndpoint`][crate::standard_icd::BlobCloseEndpoint]
+pub async fn blob_close<C: BlobContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: BlobClose,
+) -> Result<BlobSummary, BlobError> {
+    context.blob_storage().close(req.handle).await
+}
@@ -29,6 +29,14 @@
 ///         | ----------        | ----      | -------               |
 ///         | AlphaEndpoint     | async     | test_alpha_handler    |
 ///         | BetaEndpoint      | spawn     | test_beta_handler     |
+///         // Handlers for endpoints with an `ErrorTy` return `Result<ResponseTy, ErrorTy>`
+///         | GammaEndpoint     | blocking  | test_gamma_handler    |
+///         // Handlers for streaming endpoints are spawned, and get a `StreamSink`
+///         // that they must `end()`
+///         | DeltaEndpoint     | stream    | test_delta_handler    |
+///         // Handlers for upload endpoints are called once per chunk, and return
+///         // `Some(response)` once the upload is complete
+///         | EpsilonEndpoint   | upload    | test_epsilon_handler  |
 ///     };
 ///     topics_in: {
 ///         // This is the list you get from the `topics!()` macro
@@ -92,6 +100,36 @@ macro_rules! define_dispatch {
         }
     };
 
+    // This is the "async execution" arm for defining an upload endpoint, called once per chunk
+    (@ep_arm upload ($endpoint:ty) $handler:ident $context:ident $header:ident $req:ident $outputter:ident ($spawn_fn:path) $spawner:ident) => {
+        {
+            let next_offset = $req.offset.saturating_add($req.data.len() as u32);
+            let reply = match $handler($context, $header.clone(), $req).await {
+                Some(done) => $crate::standard_icd::UploadStatus::Done(done),
+                None => $crate::standard_icd::UploadStatus::Ack { next_offset },
+            };
+            if $outputter.reply::<$endpoint>($header.seq_no, &reply).await.is_err() {
+                let err = $crate::standard_icd::WireError::SerFailed;
+                $outputter.error($header.seq_no, err).await
+            } else {
+                Ok(())
+            }
+        }
+    };
+    // This is the "spawn a streaming task" arm for defining a streaming endpoint
+    (@ep_arm stream ($endpoint:ty) $handler:ident $context:ident $header:ident $req:ident $outputter:ident ($spawn_fn:path) $spawner:ident) => {
+        {
+            let context = $crate::server::SpawnContext::spawn_ctxt($context);
+            let sink = $crate::server::StreamSink::<$endpoint, _>::new($outputter.clone(), $header.seq_no);
+            if $spawn_fn($spawner, $handler(context, $header.clone(), $req, sink)).is_err() {
+                let err = $crate::standard_icd::WireError::FailedToSpawn;
+                $outputter.error($header.seq_no, err).await
+            } else {
+                Ok(())
+            }
+        }
+    };
+
     //////////////////////////////////////////////////////////////////////////////
     // TOPIC HANDLER EXPANSION ARMS
     //////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,183 @@
+//! Server side of topic flow control
+//!
+//! By default, [`Sender::publish()`][crate::server::Sender::publish] sends every
+//! message, and a client that can not keep up drops them on its side. With flow
+//! control, the client grants the server a number of *credits* for a topic, and
+//! the server only publishes while credits are left, dropping (and counting) the
+//! rest before they are sent. The client grants more credits as it consumes
+//! messages, see `HostClient::subscribe_flow()`. Grants name the total number of
+//! messages that may be published, rather than adding to the credits, so the
+//! client may safely send a grant again.
+//!
+//! To support this, declare a [`TopicFlow`] for each flow-controlled topic, publish
+//! with [`Sender::publish_flow()`][crate::server::Sender::publish_flow], include the
+//! [flow endpoint][crate::standard_icd::STANDARD_ICD_FLOW_ENDPOINTS] in the endpoint
+//! list, and list it in the dispatcher using [`topic_flow`]. The context must
+//! implement [`FlowContext`]:
+//!
+//! ```rust,ignore
+//! use postcard_rpc::{
+//!     server::flow::{topic_flow, FlowContext, TopicFlow},
+//!     standard_icd::{TopicFlowEndpoint, STANDARD_ICD_FLOW_ENDPOINTS},
+//! };
+//!
+//! static ACCEL_FLOW: TopicFlow = TopicFlow::new(AccelTopic::TOPIC_KEY);
+//!
+//! impl FlowContext for Context {
+//!     fn topic_flows(&self) -> &[&TopicFlow] {
+//!         &[&ACCEL_FLOW]
+//!     }
+//! }
+//!
+//! endpoints! {
+//!     list = ENDPOINT_LIST;
+//!     include = [STANDARD_ICD_FLOW_ENDPOINTS];
+//!     // ...
+//! }
+//!
+//! define_dispatch! {
+//!     // ...
+//!     endpoints: {
+//!         list: ENDPOINT_LIST;
+//!
+//!         | EndpointTy        | kind      | handler       |
+//!         | ----------        | ----      | -------       |
+//!         | TopicFlowEndpoint | blocking  | topic_flow    |
+//!     };
+//!     // ...
+//! }
+//!
+//! // In the publishing task
+//! sender.publish_flow::<AccelTopic>(&ACCEL_FLOW, seq.into(), &reading).await?;
+//! ```
+
+use portable_atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::{
+    header::VarHeader,
+    standard_icd::{TopicFlowError, TopicFlowRequest, TopicFlowStats},
+    Key,
+};
+
+/// The flow control state of a single topic
+///
+/// Flow control is disabled, publishing without limit, until the client sends
+/// the first grant. Usually stored in a `static`, shared between the context
+/// and the publishing task.
+pub struct TopicFlow {
+    key: Key,
+    enabled: AtomicBool,
+    /// Messages taken since flow control was enabled
+    taken: AtomicU32,
+    /// The total number of messages that may be taken
+    limit: AtomicU32,
+    sent: AtomicU32,
+    dropped: AtomicU32,
+}
+
+impl TopicFlow {
+    /// Create the flow control state for the topic with the given key
+    pub const fn new(key: Key) -> Self {
+        Self {
+            key,
+            enabled: AtomicBool::new(false),
+            taken: AtomicU32::new(0),
+            limit: AtomicU32::new(0),
+            sent: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// The key of the topic
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    /// Take one credit, returns whether a message may be published
+    ///
+    /// Messages that are not published must be counted with [`Self::record_dropped()`].
+    pub fn try_take(&self) -> bool {
+        if !self.enabled.load(Ordering::Acquire) {
+            return true;
+        }
+        let limit = self.limit.load(Ordering::Acquire);
+        self.taken
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |t| {
+                (t != limit).then(|| t.wrapping_add(1))
+            })
+            .is_ok()
+    }
+
+    /// Count a published message
+    pub fn record_sent(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a dropped message
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Allow publishing until `limit` messages were taken, enabling flow control
+    ///
+    /// Limits below the current one are ignored, see [`TopicFlowRequest::Grant`].
+    pub fn grant(&self, limit: u32) {
+        // Only ever grows, comparing with wrapping arithmetic
+        let _ = self
+            .limit
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |cur| {
+                ((limit.wrapping_sub(cur) as i32) > 0).then_some(limit)
+            });
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Disable flow control, publishing without limit, and discard remaining credits
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::Release);
+        self.taken.store(0, Ordering::Release);
+        self.limit.store(0, Ordering::Release);
+    }
+
+    /// Get the current counters
+    pub fn stats(&self) -> TopicFlowStats {
+        TopicFlowStats {
+            enabled: self.enabled.load(Ordering::Acquire),
+            credits: self
+                .limit
+                .load(Ordering::Acquire)
+                .wrapping_sub(self.taken.load(Ordering::Acquire)),
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A trait for contexts that provide the [`TopicFlow`]s to the [`topic_flow`] handler
+pub trait FlowContext {
+    /// All flow-controlled topics
+    fn topic_flows(&self) -> &[&TopicFlow];
+}
+
+/// Handler for [`TopicFlowEndpoint`][crate::standard_icd::TopicFlowEndpoint]
+pub fn topic_flow<C: FlowContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: TopicFlowRequest,
+) -> Result<TopicFlowStats, TopicFlowError> {
+    let key = match req {
+        TopicFlowRequest::Grant { key, .. } => key,
+        TopicFlowRequest::Disable { key } => key,
+        TopicFlowRequest::Stats { key } => key,
+    };
+    let flow = context
+        .topic_flows()
+        .iter()
+        .find(|f| f.key == key)
+        .ok_or(TopicFlowError::UnknownTopic)?;
+    match req {
+        TopicFlowRequest::Grant { limit, .. } => flow.grant(limit),
+        TopicFlowRequest::Disable { .. } => flow.disable(),
+        TopicFlowRequest::Stats { .. } => {}
+    }
+    Ok(flow.stats())
+}
@@ -0,0 +1,280 @@
+//! Implementation using `embassy-net` UDP sockets, one frame per datagram
+//!
+//! This is intended for devices connected over Ethernet or Wi-Fi. The socket
+//! must already be bound to a local port before being handed to
+//! [`WireStorage::init()`](dispatch_impl::WireStorage::init). It can be reached
+//! with [`HostClient::try_new_udp()`][crate::host_client::HostClient::try_new_udp].
+//!
+//! UDP is connectionless: the server replies to (and publishes to) whichever
+//! address it most recently received a frame from.
+
+use crate::{
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq},
+    server::{log_fmt::write_log_fmt, WireRx, WireRxErrorKind, WireSpawn, WireTx, WireTxErrorKind},
+    standard_icd::LoggingTopic,
+    Topic,
+};
+use core::cell::Cell;
+use core::fmt::Arguments;
+use embassy_executor::{SpawnError, SpawnToken, Spawner};
+use embassy_net_0_6::udp::{RecvError, SendError, UdpMetadata, UdpSocket};
+use embassy_sync::{
+    blocking_mutex::{raw::RawMutex, Mutex as BlockingMutex},
+    mutex::Mutex,
+};
+use serde::Serialize;
+
+/// A collection of types and aliases useful for importing the correct types
+pub mod dispatch_impl {
+    pub use super::embassy_spawn as spawn_fn;
+    use super::{ENetUdpShared, ENetUdpWireRx, ENetUdpWireTx, ENetUdpWireTxInner};
+
+    use core::cell::Cell;
+    use embassy_net_0_6::udp::UdpSocket;
+    use embassy_sync::{
+        blocking_mutex::{raw::RawMutex, Mutex as BlockingMutex},
+        mutex::Mutex,
+    };
+    use static_cell::StaticCell;
+
+    /// Type alias for `WireTx` impl
+    pub type WireTxImpl<M> = super::ENetUdpWireTx<M>;
+    /// Type alias for `WireRx` impl
+    pub type WireRxImpl<M> = super::ENetUdpWireRx<M>;
+    /// Type alias for `WireSpawn` impl
+    pub type WireSpawnImpl = super::ENetUdpWireSpawn;
+    /// Type alias for the receive buffer
+    pub type WireRxBuf = &'static mut [u8];
+
+    /// A helper type for `static` storage of the socket and sender state
+    pub struct WireStorage<M: RawMutex + 'static> {
+        /// Socket and WireTx/Sender static storage
+        pub cell: StaticCell<ENetUdpShared<M>>,
+    }
+
+    impl<M: RawMutex + 'static> Default for WireStorage<M> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<M: RawMutex + 'static> WireStorage<M> {
+        /// Create a new, uninitialized static storage
+        pub const fn new() -> Self {
+            Self {
+                cell: StaticCell::new(),
+            }
+        }
+
+        /// Initialize the static storage.
+        ///
+        /// `socket` must already be bound. `tx_buf` is used for serializing
+        /// outgoing frames, and limits the largest frame that can be sent.
+        ///
+        /// This must only be called once.
+        pub fn init(
+            &'static self,
+            socket: UdpSocket<'static>,
+            tx_buf: &'static mut [u8],
+        ) -> (WireTxImpl<M>, WireRxImpl<M>) {
+            let shared = self.cell.init(ENetUdpShared {
+                socket,
+                peer: BlockingMutex::new(Cell::new(None)),
+                tx: Mutex::new(ENetUdpWireTxInner { log_seq: 0, tx_buf }),
+            });
+
+            (ENetUdpWireTx { shared }, ENetUdpWireRx { shared })
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// SHARED
+//////////////////////////////////////////////////////////////////////////////
+
+/// Implementation detail, holding the socket and the most recent peer address
+pub struct ENetUdpShared<M: RawMutex + 'static> {
+    socket: UdpSocket<'static>,
+    peer: BlockingMutex<M, Cell<Option<UdpMetadata>>>,
+    tx: Mutex<M, ENetUdpWireTxInner>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TX
+//////////////////////////////////////////////////////////////////////////////
+
+/// Implementation detail, holding the scratch buffer used for sending
+pub struct ENetUdpWireTxInner {
+    log_seq: u16,
+    tx_buf: &'static mut [u8],
+}
+
+/// A [`WireTx`] implementation for embassy-net 0.6 UDP sockets.
+#[derive(Copy)]
+pub struct ENetUdpWireTx<M: RawMutex + 'static> {
+    shared: &'static ENetUdpShared<M>,
+}
+
+impl<M: RawMutex + 'static> Clone for ENetUdpWireTx<M> {
+    fn clone(&self) -> Self {
+        ENetUdpWireTx {
+            shared: self.shared,
+        }
+    }
+}
+
+impl<M: RawMutex + 'static> ENetUdpWireTx<M> {
+    async fn send_datagram(&self, out: &[u8]) -> Result<(), WireTxErrorKind> {
+        let socket = &self.shared.socket;
+        // embassy-net waits forever for space that will never be available if the
+        // datagram is larger than the socket buffer, so check that here.
+        if out.len() > socket.payload_send_capacity() {
+            return Err(WireTxErrorKind::MessageTooLarge);
+        }
+        let Some(peer) = self.shared.peer.lock(|p| p.get()) else {
+            return Err(WireTxErrorKind::Other);
+        };
+        match socket.send_to(out, peer).await {
+            Ok(()) => Ok(()),
+            Err(SendError::NoRoute) => Err(WireTxErrorKind::Other),
+            Err(SendError::SocketNotBound) => Err(WireTxErrorKind::ConnectionClosed),
+        }
+    }
+}
+
+impl<M: RawMutex + 'static> WireTx for ENetUdpWireTx<M> {
+    type Error = WireTxErrorKind;
+
+    async fn send<T: Serialize + ?Sized>(
+        &self,
+        hdr: VarHeader,
+        msg: &T,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.shared.tx.lock().await;
+        let ENetUdpWireTxInner { log_seq: _, tx_buf } = &mut *inner;
+
+        let (hdr_used, remain) = hdr
+            .write_to_slice(tx_buf)
+            .ok_or(WireTxErrorKind::MessageTooLarge)?;
+        let bdy_used =
+            postcard::to_slice(msg, remain).map_err(|_| WireTxErrorKind::MessageTooLarge)?;
+        let used_ttl = hdr_used.len() + bdy_used.len();
+
+        if let Some(used) = tx_buf.get(..used_ttl) {
+            self.send_datagram(used).await
+        } else {
+            Err(WireTxErrorKind::Other)
+        }
+    }
+
+    async fn send_raw(&self, buf: &[u8]) -> Result<(), Self::Error> {
+        // Hold the lock so raw frames are not interleaved with other sends
+        let _inner = self.shared.tx.lock().await;
+        self.send_datagram(buf).await
+    }
+
+    async fn send_log_str(&self, kkind: VarKeyKind, s: &str) -> Result<(), Self::Error> {
+        let mut inner = self.shared.tx.lock().await;
+        let ENetUdpWireTxInner { log_seq, tx_buf } = &mut *inner;
+
+        let wh = log_header(kkind, log_seq);
+
+        let (hdr_used, remain) = wh.write_to_slice(tx_buf).ok_or(WireTxErrorKind::Other)?;
+        let bdy_used = postcard::to_slice::<str>(s, remain).map_err(|_| WireTxErrorKind::Other)?;
+        let used_ttl = hdr_used.len() + bdy_used.len();
+
+        if let Some(used) = tx_buf.get(..used_ttl) {
+            self.send_datagram(used).await
+        } else {
+            Err(WireTxErrorKind::Other)
+        }
+    }
+
+    async fn send_log_fmt<'a>(
+        &self,
+        kkind: VarKeyKind,
+        args: Arguments<'a>,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.shared.tx.lock().await;
+        let ENetUdpWireTxInner { log_seq, tx_buf } = &mut *inner;
+
+        let wh = log_header(kkind, log_seq);
+        let act_used = write_log_fmt(wh, tx_buf, args)?;
+
+        self.send_datagram(&tx_buf[..act_used]).await
+    }
+}
+
+fn log_header(kkind: VarKeyKind, log_seq: &mut u16) -> VarHeader {
+    let key = match kkind {
+        VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
+        VarKeyKind::Key2 => VarKey::Key2(LoggingTopic::TOPIC_KEY2),
+        VarKeyKind::Key4 => VarKey::Key4(LoggingTopic::TOPIC_KEY4),
+        VarKeyKind::Key8 => VarKey::Key8(LoggingTopic::TOPIC_KEY),
+    };
+    let ctr = *log_seq;
+    *log_seq = log_seq.wrapping_add(1);
+    VarHeader {
+        key,
+        seq_no: VarSeq::Seq2(ctr),
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// RX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireRx`] implementation for embassy-net 0.6 UDP sockets.
+pub struct ENetUdpWireRx<M: RawMutex + 'static> {
+    shared: &'static ENetUdpShared<M>,
+}
+
+impl<M: RawMutex + 'static> WireRx for ENetUdpWireRx<M> {
+    type Error = WireRxErrorKind;
+
+    async fn receive<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a mut [u8], Self::Error> {
+        match self.shared.socket.recv_from(buf).await {
+            Ok((n, meta)) => {
+                self.shared.peer.lock(|p| p.set(Some(meta)));
+                Ok(&mut buf[..n])
+            }
+            Err(RecvError::Truncated) => Err(WireRxErrorKind::ReceivedMessageTooLarge),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// SPAWN
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireSpawn`] impl using the embassy executor
+#[derive(Clone)]
+pub struct ENetUdpWireSpawn {
+    /// The embassy-executor spawner
+    pub spawner: Spawner,
+}
+
+impl From<Spawner> for ENetUdpWireSpawn {
+    fn from(value: Spawner) -> Self {
+        Self { spawner: value }
+    }
+}
+
+impl WireSpawn for ENetUdpWireSpawn {
+    type Error = SpawnError;
+
+    type Info = Spawner;
+
+    fn info(&self) -> &Self::Info {
+        &self.spawner
+    }
+}
+
+/// Attempt to spawn the given token
+pub fn embassy_spawn<Sp, S: Sized>(sp: &Sp, tok: SpawnToken<S>) -> Result<(), Sp::Error>
+where
+    Sp: WireSpawn<Error = SpawnError, Info = Spawner>,
+{
+    let info = sp.info();
+    info.spawn(tok)
+}
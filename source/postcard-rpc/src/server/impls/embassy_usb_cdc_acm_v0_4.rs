@@ -0,0 +1,249 @@
+//! Implementation using `embassy-usb` CDC-ACM and COBS framing
+//!
+//! The device appears to the host as a standard serial port, so no driver
+//! installation is necessary, and it is compatible with the host's `cobs-serial`
+//! transport, e.g.
+//! [`HostClient::try_new_serial_cobs()`][crate::host_client::HostClient::try_new_serial_cobs].
+//! The baud rate is ignored.
+//!
+//! Framing is the same as [`embedded_io_cobs`][super::embedded_io_cobs], which
+//! is used on top of the CDC-ACM packets.
+
+use embassy_usb_0_4::class::cdc_acm::{Receiver, Sender};
+use embassy_usb_driver::{Driver, EndpointError};
+use embedded_io_async::{ErrorType, Read, Write};
+
+/// The max packet size of the CDC-ACM endpoints
+const MAX_PACKET_SIZE: usize = 64;
+
+/// A collection of types and aliases useful for importing the correct types
+pub mod dispatch_impl {
+    use super::{CdcAcmReader, CdcAcmWriter, MAX_PACKET_SIZE};
+    pub use crate::server::impls::embedded_io_cobs::embassy_spawn as spawn_fn;
+
+    use crate::server::impls::{embassy_usb_v0_4::UsbDeviceBuffers, embedded_io_cobs};
+    use embassy_sync::blocking_mutex::raw::RawMutex;
+    use embassy_usb_0_4::{
+        class::cdc_acm::{CdcAcmClass, State},
+        Builder, Config, UsbDevice,
+    };
+    use embassy_usb_driver::Driver;
+    use static_cell::{ConstStaticCell, StaticCell};
+
+    /// Type alias for `WireTx` impl
+    pub type WireTxImpl<M, D> = embedded_io_cobs::EioCobsWireTx<M, CdcAcmWriter<D>>;
+    /// Type alias for `WireRx` impl
+    pub type WireRxImpl<D, const N: usize = 1024> =
+        embedded_io_cobs::EioCobsWireRx<CdcAcmReader<D>, N>;
+    /// Type alias for `WireSpawn` impl
+    pub type WireSpawnImpl = embedded_io_cobs::EioCobsWireSpawn;
+    /// Type alias for the receive buffer
+    pub type WireRxBuf = &'static mut [u8];
+
+    /// A helper type for `static` storage of buffers and driver components
+    ///
+    /// `N` is the size of the COBS accumulator, which must be large enough to
+    /// hold the largest *encoded* frame that will be received.
+    pub struct WireStorage<
+        M: RawMutex + 'static,
+        D: Driver<'static> + 'static,
+        const N: usize = 1024,
+        const CONFIG: usize = 256,
+        const BOS: usize = 256,
+        const CONTROL: usize = 64,
+        const MSOS: usize = 256,
+    > {
+        /// Usb buffer storage
+        pub bufs_usb: ConstStaticCell<UsbDeviceBuffers<CONFIG, BOS, CONTROL, MSOS>>,
+        /// CDC-ACM class state storage
+        pub state: StaticCell<State<'static>>,
+        /// COBS framing storage
+        pub cobs: embedded_io_cobs::dispatch_impl::WireStorage<M, CdcAcmWriter<D>, N>,
+    }
+
+    impl<
+            M: RawMutex + 'static,
+            D: Driver<'static> + 'static,
+            const N: usize,
+            const CONFIG: usize,
+            const BOS: usize,
+            const CONTROL: usize,
+            const MSOS: usize,
+        > Default for WireStorage<M, D, N, CONFIG, BOS, CONTROL, MSOS>
+    {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<
+            M: RawMutex + 'static,
+            D: Driver<'static> + 'static,
+            const N: usize,
+            const CONFIG: usize,
+            const BOS: usize,
+            const CONTROL: usize,
+            const MSOS: usize,
+        > WireStorage<M, D, N, CONFIG, BOS, CONTROL, MSOS>
+    {
+        /// Create a new, uninitialized static set of buffers
+        pub const fn new() -> Self {
+            Self {
+                bufs_usb: ConstStaticCell::new(UsbDeviceBuffers::new()),
+                state: StaticCell::new(),
+                cobs: embedded_io_cobs::dispatch_impl::WireStorage::new(),
+            }
+        }
+
+        /// Initialize the static storage.
+        ///
+        /// This must only be called once.
+        pub fn init(
+            &'static self,
+            driver: D,
+            config: Config<'static>,
+            tx_buf: &'static mut [u8],
+        ) -> (UsbDevice<'static, D>, WireTxImpl<M, D>, WireRxImpl<D, N>) {
+            let (builder, wtx, wrx) = self.init_without_build(driver, config, tx_buf);
+            let usb = builder.build();
+            (usb, wtx, wrx)
+        }
+
+        /// Initialize the static storage, without building `Builder`
+        ///
+        /// This must only be called once.
+        pub fn init_without_build(
+            &'static self,
+            driver: D,
+            config: Config<'static>,
+            tx_buf: &'static mut [u8],
+        ) -> (Builder<'static, D>, WireTxImpl<M, D>, WireRxImpl<D, N>) {
+            let bufs = self.bufs_usb.take();
+
+            let mut builder = Builder::new(
+                driver,
+                config,
+                &mut bufs.config_descriptor,
+                &mut bufs.bos_descriptor,
+                &mut bufs.msos_descriptor,
+                &mut bufs.control_buf,
+            );
+
+            // Add a CDC-ACM function, which uses the standard serial port
+            // drivers of the host.
+            let state = self.state.init(State::new());
+            let class = CdcAcmClass::new(&mut builder, state, MAX_PACKET_SIZE as u16);
+            let (tx, rx) = class.split();
+
+            let (wtx, wrx) = self.cobs.init(
+                CdcAcmWriter {
+                    tx,
+                    packet: [0u8; MAX_PACKET_SIZE],
+                    used: 0,
+                    needs_zlp: false,
+                },
+                CdcAcmReader {
+                    rx,
+                    disconnected: false,
+                },
+                tx_buf,
+            );
+
+            (builder, wtx, wrx)
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TX
+//////////////////////////////////////////////////////////////////////////////
+
+/// An `embedded-io-async` writer, sending the written bytes as CDC-ACM packets
+///
+/// Bytes are gathered into full packets, and sent when the packet is full or
+/// on flush.
+pub struct CdcAcmWriter<D: Driver<'static>> {
+    tx: Sender<'static, D>,
+    packet: [u8; MAX_PACKET_SIZE],
+    used: usize,
+    needs_zlp: bool,
+}
+
+impl<D: Driver<'static>> CdcAcmWriter<D> {
+    async fn send_packet(&mut self) -> Result<(), EndpointError> {
+        let used = core::mem::take(&mut self.used);
+        self.tx.write_packet(&self.packet[..used]).await?;
+        // A full packet is not processed by the host until a shorter one follows
+        self.needs_zlp = used == MAX_PACKET_SIZE;
+        Ok(())
+    }
+}
+
+impl<D: Driver<'static>> ErrorType for CdcAcmWriter<D> {
+    type Error = EndpointError;
+}
+
+impl<D: Driver<'static>> Write for CdcAcmWriter<D> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.used == MAX_PACKET_SIZE {
+            self.send_packet().await?;
+        }
+        let n = buf.len().min(MAX_PACKET_SIZE - self.used);
+        self.packet[self.used..][..n].copy_from_slice(&buf[..n]);
+        self.used += n;
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if self.used != 0 {
+            self.send_packet().await?;
+        }
+        if self.needs_zlp {
+            // Nothing is left, so this sends a zero length packet
+            self.send_packet().await?;
+        }
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// RX
+//////////////////////////////////////////////////////////////////////////////
+
+/// An `embedded-io-async` reader, receiving CDC-ACM packets
+///
+/// When the USB connection is lost, a read returns zero bytes once, which closes
+/// the connection for the `Server`. The next read waits for the host to connect again.
+pub struct CdcAcmReader<D: Driver<'static>> {
+    rx: Receiver<'static, D>,
+    disconnected: bool,
+}
+
+impl<D: Driver<'static>> ErrorType for CdcAcmReader<D> {
+    type Error = EndpointError;
+}
+
+impl<D: Driver<'static>> Read for CdcAcmReader<D> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.disconnected {
+            self.rx.wait_connection().await;
+            self.disconnected = false;
+        }
+        // `read_packet` needs room for a whole packet
+        if buf.len() < MAX_PACKET_SIZE {
+            return Err(EndpointError::BufferOverflow);
+        }
+        loop {
+            match self.rx.read_packet(buf).await {
+                // Empty packets carry no data, they are not the end of the stream
+                Ok(0) => continue,
+                Ok(n) => return Ok(n),
+                Err(EndpointError::Disabled) => {
+                    self.disconnected = true;
+                    return Ok(0);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use crate::{
     header::{VarHeader, VarKey, VarKeyKind, VarSeq},
-    server::{WireRx, WireRxErrorKind, WireSpawn, WireTx, WireTxErrorKind},
+    server::{log_fmt::write_log_fmt, WireRx, WireRxErrorKind, WireSpawn, WireTx, WireTxErrorKind},
     standard_icd::LoggingTopic,
     Topic,
 };
@@ -174,6 +174,9 @@ pub mod dispatch_impl {
         }
         /// Initialize the static storage, without building `Builder`
         ///
+        /// For devices with more than one postcard-rpc interface, use
+        /// [`Self::init_composite_without_build`] instead.
+        ///
         /// This must only be called once.
         pub fn init_without_build(
             &'static self,
@@ -223,6 +226,133 @@ pub mod dispatch_impl {
 
             (builder, EUsbWireTx { inner: wtx }, EUsbWireRx { ep_out })
         }
+
+        /// Initialize the static storage for a device with more than one postcard-rpc
+        /// interface, without building `Builder`
+        ///
+        /// Unlike [`Self::init_without_build`], the WinUSB features are added to the
+        /// function of the first interface instead of the device, as Windows only
+        /// applies device level features to non-composite devices. The interface
+        /// uses [`DEVICE_INTERFACE_GUIDS`], add the other interfaces with
+        /// [`InterfaceStorage::init`].
+        ///
+        /// This must only be called once.
+        pub fn init_composite_without_build(
+            &'static self,
+            driver: D,
+            config: Config<'static>,
+            tx_buf: &'static mut [u8],
+        ) -> (Builder<'static, D>, WireTxImpl<M, D>, WireRxImpl<D>) {
+            let bufs = self.bufs_usb.take();
+
+            let mut builder = Builder::new(
+                driver,
+                config,
+                &mut bufs.config_descriptor,
+                &mut bufs.bos_descriptor,
+                &mut bufs.msos_descriptor,
+                &mut bufs.control_buf,
+            );
+            builder.msos_descriptor(windows_version::WIN8_1, 0);
+
+            let (ep_out, ep_in) = winusb_function(&mut builder, 0, DEVICE_INTERFACE_GUIDS);
+            let wtx = self.cell.init(Mutex::new(EUsbWireTxInner {
+                ep_in,
+                log_seq: 0,
+                tx_buf,
+                pending_frame: false,
+            }));
+
+            (builder, EUsbWireTx { inner: wtx }, EUsbWireRx { ep_out })
+        }
+    }
+
+    /// Add a vendor-specific function (class 0xFF) with one interface, that Windows
+    /// binds to WinUSB on its own
+    fn winusb_function<D: Driver<'static>>(
+        builder: &mut Builder<'static, D>,
+        subclass: u8,
+        device_interface_guids: &'static [&'static str],
+    ) -> (D::EndpointOut, D::EndpointIn) {
+        let mut function = builder.function(0xFF, subclass, 0);
+        function.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
+        function.msos_feature(msos::RegistryPropertyFeatureDescriptor::new(
+            "DeviceInterfaceGUIDs",
+            msos::PropertyData::RegMultiSz(device_interface_guids),
+        ));
+        let mut interface = function.interface();
+        let mut alt = interface.alt_setting(0xFF, subclass, 0, None);
+        let ep_out = alt.endpoint_bulk_out(64);
+        let ep_in = alt.endpoint_bulk_in(64);
+        (ep_out, ep_in)
+    }
+
+    /// A helper type for `static` storage of an additional postcard-rpc interface
+    ///
+    /// This allows running two or more independent postcard-rpc servers on one USB
+    /// device, e.g. a control channel and a high-rate data channel, each with its
+    /// own dispatcher. Create the device and the first interface with
+    /// [`WireStorage::init_composite_without_build`], then call [`InterfaceStorage::init`]
+    /// for each additional interface before building the device. All interfaces
+    /// share the [`UsbDeviceBuffers`] of the [`WireStorage`].
+    ///
+    /// On the host, each interface is reached with its own client, selecting the
+    /// interface by its subclass, e.g. with the `RawNusbConfig` of `postcard-rpc`'s
+    /// `raw-nusb` host client. Windows can not enumerate interfaces, so there the
+    /// interface must be selected by its number instead:
+    ///
+    /// ```rust,ignore
+    /// let config = RawNusbConfig::new().with_interface_class(0xFF, Some(DATA_SUBCLASS));
+    /// // On Windows
+    /// let config = RawNusbConfig::new().with_interface_number(1);
+    /// ```
+    pub struct InterfaceStorage<M: RawMutex + 'static, D: Driver<'static> + 'static> {
+        /// WireTx/Sender static storage
+        pub cell: StaticCell<Mutex<M, EUsbWireTxInner<D>>>,
+    }
+
+    impl<M: RawMutex + 'static, D: Driver<'static> + 'static> Default for InterfaceStorage<M, D> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<M: RawMutex + 'static, D: Driver<'static> + 'static> InterfaceStorage<M, D> {
+        /// Create a new, uninitialized static interface storage
+        pub const fn new() -> Self {
+            Self {
+                cell: StaticCell::new(),
+            }
+        }
+
+        /// Add a vendor-specific interface to `builder`, with its own bulk endpoints
+        ///
+        /// The interface uses class 0xFF and the given `subclass`, which should be
+        /// unique for each interface so the host can tell them apart. The first
+        /// interface, added by [`WireStorage`], uses subclass 0. Windows binds
+        /// WinUSB to the interface, and registers it with the
+        /// `device_interface_guids`, which must differ from [`DEVICE_INTERFACE_GUIDS`]
+        /// and those of the other interfaces.
+        ///
+        /// This must only be called once.
+        pub fn init(
+            &'static self,
+            builder: &mut Builder<'static, D>,
+            subclass: u8,
+            device_interface_guids: &'static [&'static str],
+            tx_buf: &'static mut [u8],
+        ) -> (WireTxImpl<M, D>, WireRxImpl<D>) {
+            let (ep_out, ep_in) = winusb_function(builder, subclass, device_interface_guids);
+
+            let wtx = self.cell.init(Mutex::new(EUsbWireTxInner {
+                ep_in,
+                log_seq: 0,
+                tx_buf,
+                pending_frame: false,
+            }));
+
+            (EUsbWireTx { inner: wtx }, EUsbWireRx { ep_out })
+        }
     }
 }
 
@@ -335,7 +465,6 @@ impl<M: RawMutex + 'static, D: Driver<'static> + 'static> WireTx for EUsbWireTx<
             tx_buf,
             pending_frame,
         }: &mut EUsbWireTxInner<D> = &mut inner;
-        let ttl_len = tx_buf.len();
 
         let key = match kkind {
             VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
@@ -349,60 +478,7 @@ impl<M: RawMutex + 'static, D: Driver<'static> + 'static> WireTx for EUsbWireTx<
             key,
             seq_no: VarSeq::Seq2(ctr),
         };
-        let Some((_hdr, remaining)) = wh.write_to_slice(tx_buf) else {
-            return Err(WireTxErrorKind::Other);
-        };
-        let max_log_len = actual_varint_max_len(remaining.len());
-
-        // Then, reserve space for non-canonical length fields
-        // We also set all but the last bytes to be "continuation"
-        // bytes
-        if remaining.len() < max_log_len {
-            return Err(WireTxErrorKind::Other);
-        }
-
-        let (len_field, body) = remaining.split_at_mut(max_log_len);
-        for b in len_field.iter_mut() {
-            *b = 0x80;
-        }
-        if let Some(b) = len_field.last_mut() {
-            *b = 0x00;
-        }
-
-        // Then, do the formatting
-        let body_len = body.len();
-        let mut sw = SliceWriter(body);
-        let res = core::fmt::write(&mut sw, args);
-
-        // Calculate the number of bytes used *for formatting*.
-        let remain = sw.0.len();
-        let used = body_len - remain;
-
-        // If we had an error, that's probably because we ran out
-        // of room. If we had an error, AND there is at least three
-        // bytes, then replace those with '.'s like ...
-        if res.is_err() && (body.len() >= 3) {
-            let start = body.len() - 3;
-            body[start..].iter_mut().for_each(|b| *b = b'.');
-        }
-
-        // then go back and fill in the len - we write the len
-        // directly to the reserved bytes, and if we DIDN'T use
-        // the full space, we mark the end of the real length as
-        // a continuation field. This will result in a non-canonical
-        // "extended" length in postcard, and will "spill into" the
-        // bytes we wrote previously above
-        let mut len_bytes = [0u8; varint_max::<usize>()];
-        let len_used = varint_usize(used, &mut len_bytes);
-        if len_used.len() != len_field.len() {
-            if let Some(b) = len_used.last_mut() {
-                *b |= 0x80;
-            }
-        }
-        len_field[..len_used.len()].copy_from_slice(len_used);
-
-        // Calculate the TOTAL amount
-        let act_used = ttl_len - remain;
+        let act_used = write_log_fmt(wh, tx_buf, args)?;
 
         send_all::<D>(ep_in, &tx_buf[..act_used], pending_frame).await
     }
@@ -458,76 +534,6 @@ where
     }
 }
 
-struct SliceWriter<'a>(&'a mut [u8]);
-
-impl<'a> core::fmt::Write for SliceWriter<'a> {
-    fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
-        let sli = core::mem::take(&mut self.0);
-
-        // If this write would overflow us, note that, but still take
-        // as much as we possibly can here
-        let bad = s.len() > sli.len();
-        let to_write = s.len().min(sli.len());
-        let (now, later) = sli.split_at_mut(to_write);
-        now.copy_from_slice(s.as_bytes());
-        self.0 = later;
-
-        // Now, report whether we overflowed or not
-        if bad {
-            Err(core::fmt::Error)
-        } else {
-            Ok(())
-        }
-    }
-}
-
-/// Returns the maximum number of bytes required to encode T.
-const fn varint_max<T: Sized>() -> usize {
-    const BITS_PER_BYTE: usize = 8;
-    const BITS_PER_VARINT_BYTE: usize = 7;
-
-    // How many data bits do we need for this type?
-    let bits = core::mem::size_of::<T>() * BITS_PER_BYTE;
-
-    // We add (BITS_PER_VARINT_BYTE - 1), to ensure any integer divisions
-    // with a remainder will always add exactly one full byte, but
-    // an evenly divided number of bits will be the same
-    let roundup_bits = bits + (BITS_PER_VARINT_BYTE - 1);
-
-    // Apply division, using normal "round down" integer division
-    roundup_bits / BITS_PER_VARINT_BYTE
-}
-
-#[inline]
-fn varint_usize(n: usize, out: &mut [u8; varint_max::<usize>()]) -> &mut [u8] {
-    let mut value = n;
-    for i in 0..varint_max::<usize>() {
-        out[i] = value.to_le_bytes()[0];
-        if value < 128 {
-            return &mut out[..=i];
-        }
-
-        out[i] |= 0x80;
-        value >>= 7;
-    }
-    debug_assert_eq!(value, 0);
-    &mut out[..]
-}
-
-fn actual_varint_max_len(largest: usize) -> usize {
-    if largest < (2 << 7) {
-        1
-    } else if largest < (2 << 14) {
-        2
-    } else if largest < (2 << 21) {
-        3
-    } else if largest < (2 << 28) {
-        4
-    } else {
-        varint_max::<usize>()
-    }
-}
-
 //////////////////////////////////////////////////////////////////////////////
 // RX
 //////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,478 @@
+//! Implementation using `embedded-io-async` streams and COBS framing
+//!
+//! This is intended for UARTs and other byte streams, and is compatible with
+//! the host's `cobs-serial` transport, e.g.
+//! [`HostClient::try_new_serial_cobs()`][crate::host_client::HostClient::try_new_serial_cobs].
+//!
+//! Frames are COBS encoded and terminated with a `0x00` byte. Incoming data is
+//! decoded with a [`CobsAccumulator`], so the accumulator size limits the size
+//! of the (encoded) frames that can be received.
+
+use crate::{
+    accumulator::raw::{CobsAccumulator, FeedResult},
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq},
+    server::{log_fmt::write_log_fmt, WireRx, WireRxErrorKind, WireSpawn, WireTx, WireTxErrorKind},
+    standard_icd::LoggingTopic,
+    Topic,
+};
+use core::fmt::Arguments;
+use embassy_executor::{SpawnError, SpawnToken, Spawner};
+use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
+use embedded_io_async::{Read, Write};
+use serde::Serialize;
+
+/// A collection of types and aliases useful for importing the correct types
+pub mod dispatch_impl {
+    pub use super::embassy_spawn as spawn_fn;
+    use super::{EioCobsWireRx, EioCobsWireTx, EioCobsWireTxInner};
+
+    use crate::accumulator::raw::CobsAccumulator;
+    use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
+    use embedded_io_async::{Read, Write};
+    use static_cell::{ConstStaticCell, StaticCell};
+
+    /// Type alias for `WireTx` impl
+    pub type WireTxImpl<M, W> = super::EioCobsWireTx<M, W>;
+    /// Type alias for `WireRx` impl
+    pub type WireRxImpl<R, const N: usize = 1024> = super::EioCobsWireRx<R, N>;
+    /// Type alias for `WireSpawn` impl
+    pub type WireSpawnImpl = super::EioCobsWireSpawn;
+    /// Type alias for the receive buffer
+    pub type WireRxBuf = &'static mut [u8];
+
+    /// A helper type for `static` storage of buffers and stream halves
+    ///
+    /// `N` is the size of the COBS accumulator, which must be large enough to
+    /// hold the largest *encoded* frame that will be received.
+    pub struct WireStorage<M: RawMutex + 'static, W: Write + 'static, const N: usize = 1024> {
+        /// WireTx/Sender static storage
+        pub cell: StaticCell<Mutex<M, EioCobsWireTxInner<W>>>,
+        /// COBS accumulator storage
+        pub acc: ConstStaticCell<CobsAccumulator<N>>,
+    }
+
+    impl<M: RawMutex + 'static, W: Write + 'static, const N: usize> Default for WireStorage<M, W, N> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<M: RawMutex + 'static, W: Write + 'static, const N: usize> WireStorage<M, W, N> {
+        /// Create a new, uninitialized static set of buffers
+        pub const fn new() -> Self {
+            Self {
+                cell: StaticCell::new(),
+                acc: ConstStaticCell::new(CobsAccumulator::new()),
+            }
+        }
+
+        /// Initialize the static storage.
+        ///
+        /// `tx` and `rx` are typically the two halves of a split UART. `tx_buf`
+        /// is used for serializing outgoing frames, and limits the largest
+        /// frame that can be sent.
+        ///
+        /// This must only be called once.
+        pub fn init<R: Read>(
+            &'static self,
+            tx: W,
+            rx: R,
+            tx_buf: &'static mut [u8],
+        ) -> (WireTxImpl<M, W>, WireRxImpl<R, N>) {
+            let wtx = self.cell.init(Mutex::new(EioCobsWireTxInner {
+                tx,
+                log_seq: 0,
+                tx_buf,
+            }));
+            let acc = self.acc.take();
+
+            (
+                EioCobsWireTx { inner: wtx },
+                EioCobsWireRx {
+                    rx,
+                    acc,
+                    rd_buf: [0u8; 64],
+                    rd_start: 0,
+                    rd_end: 0,
+                    discarding: false,
+                },
+            )
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TX
+//////////////////////////////////////////////////////////////////////////////
+
+/// Implementation detail, holding the stream and scratch buffer used for sending
+pub struct EioCobsWireTxInner<W: Write> {
+    tx: W,
+    log_seq: u16,
+    tx_buf: &'static mut [u8],
+}
+
+/// A [`WireTx`] implementation for `embedded-io-async` streams.
+pub struct EioCobsWireTx<M: RawMutex + 'static, W: Write + 'static> {
+    inner: &'static Mutex<M, EioCobsWireTxInner<W>>,
+}
+
+impl<M: RawMutex + 'static, W: Write + 'static> Copy for EioCobsWireTx<M, W> {}
+
+impl<M: RawMutex + 'static, W: Write + 'static> Clone for EioCobsWireTx<M, W> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: RawMutex + 'static, W: Write + 'static> WireTx for EioCobsWireTx<M, W> {
+    type Error = WireTxErrorKind;
+
+    async fn send<T: Serialize + ?Sized>(
+        &self,
+        hdr: VarHeader,
+        msg: &T,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.inner.lock().await;
+
+        let EioCobsWireTxInner {
+            tx,
+            log_seq: _,
+            tx_buf,
+        }: &mut EioCobsWireTxInner<W> = &mut inner;
+
+        let (hdr_used, remain) = hdr.write_to_slice(tx_buf).ok_or(WireTxErrorKind::Other)?;
+        let bdy_used = postcard::to_slice(msg, remain).map_err(|_| WireTxErrorKind::Other)?;
+        let used_ttl = hdr_used.len() + bdy_used.len();
+
+        if let Some(used) = tx_buf.get(..used_ttl) {
+            send_cobs(tx, used).await
+        } else {
+            Err(WireTxErrorKind::Other)
+        }
+    }
+
+    async fn send_raw(&self, buf: &[u8]) -> Result<(), Self::Error> {
+        let mut inner = self.inner.lock().await;
+        send_cobs(&mut inner.tx, buf).await
+    }
+
+    async fn send_log_str(&self, kkind: VarKeyKind, s: &str) -> Result<(), Self::Error> {
+        let mut inner = self.inner.lock().await;
+
+        let EioCobsWireTxInner {
+            tx,
+            log_seq,
+            tx_buf,
+        }: &mut EioCobsWireTxInner<W> = &mut inner;
+
+        let key = match kkind {
+            VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
+            VarKeyKind::Key2 => VarKey::Key2(LoggingTopic::TOPIC_KEY2),
+            VarKeyKind::Key4 => VarKey::Key4(LoggingTopic::TOPIC_KEY4),
+            VarKeyKind::Key8 => VarKey::Key8(LoggingTopic::TOPIC_KEY),
+        };
+        let ctr = *log_seq;
+        *log_seq = log_seq.wrapping_add(1);
+        let wh = VarHeader {
+            key,
+            seq_no: VarSeq::Seq2(ctr),
+        };
+
+        let (hdr_used, remain) = wh.write_to_slice(tx_buf).ok_or(WireTxErrorKind::Other)?;
+        let bdy_used = postcard::to_slice::<str>(s, remain).map_err(|_| WireTxErrorKind::Other)?;
+        let used_ttl = hdr_used.len() + bdy_used.len();
+
+        if let Some(used) = tx_buf.get(..used_ttl) {
+            send_cobs(tx, used).await
+        } else {
+            Err(WireTxErrorKind::Other)
+        }
+    }
+
+    async fn send_log_fmt<'a>(
+        &self,
+        kkind: VarKeyKind,
+        args: Arguments<'a>,
+    ) -> Result<(), Self::Error> {
+        let mut inner = self.inner.lock().await;
+
+        let EioCobsWireTxInner {
+            tx,
+            log_seq,
+            tx_buf,
+        }: &mut EioCobsWireTxInner<W> = &mut inner;
+
+        let key = match kkind {
+            VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
+            VarKeyKind::Key2 => VarKey::Key2(LoggingTopic::TOPIC_KEY2),
+            VarKeyKind::Key4 => VarKey::Key4(LoggingTopic::TOPIC_KEY4),
+            VarKeyKind::Key8 => VarKey::Key8(LoggingTopic::TOPIC_KEY),
+        };
+        let ctr = *log_seq;
+        *log_seq = log_seq.wrapping_add(1);
+        let wh = VarHeader {
+            key,
+            seq_no: VarSeq::Seq2(ctr),
+        };
+        let act_used = write_log_fmt(wh, tx_buf, args)?;
+
+        send_cobs(tx, &tx_buf[..act_used]).await
+    }
+}
+
+/// COBS encode `data` directly to the stream, followed by a `0x00` delimiter
+///
+/// Each run of up to 254 non-zero bytes is written as a length code followed
+/// by the run itself, so no separate encoding buffer is necessary.
+async fn send_cobs<W: Write>(tx: &mut W, data: &[u8]) -> Result<(), WireTxErrorKind> {
+    let mut rest = data;
+    let res = async {
+        loop {
+            let max = rest.len().min(254);
+            match rest[..max].iter().position(|b| *b == 0) {
+                // A run ending in a zero, the zero is implied by the length code
+                Some(n) => {
+                    tx.write_all(&[n as u8 + 1]).await?;
+                    tx.write_all(&rest[..n]).await?;
+                    rest = &rest[n + 1..];
+                }
+                // A maximum length run, with no implied zero
+                None if max == 254 => {
+                    tx.write_all(&[0xFF]).await?;
+                    tx.write_all(&rest[..max]).await?;
+                    rest = &rest[max..];
+                    if rest.is_empty() {
+                        break;
+                    }
+                }
+                // The final run
+                None => {
+                    tx.write_all(&[max as u8 + 1]).await?;
+                    tx.write_all(&rest[..max]).await?;
+                    break;
+                }
+            }
+        }
+        tx.write_all(&[0x00]).await?;
+        tx.flush().await
+    };
+    res.await.map_err(|_| WireTxErrorKind::Other)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// RX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireRx`] implementation for `embedded-io-async` streams.
+pub struct EioCobsWireRx<R: Read, const N: usize> {
+    rx: R,
+    acc: &'static mut CobsAccumulator<N>,
+    rd_buf: [u8; 64],
+    rd_start: usize,
+    rd_end: usize,
+    discarding: bool,
+}
+
+impl<R: Read, const N: usize> WireRx for EioCobsWireRx<R, N> {
+    type Error = WireRxErrorKind;
+
+    async fn receive<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a mut [u8], Self::Error> {
+        loop {
+            // Refill the read buffer if we've used everything from the last read
+            if self.rd_start == self.rd_end {
+                let n = match self.rx.read(&mut self.rd_buf).await {
+                    Ok(0) => return Err(WireRxErrorKind::ConnectionClosed),
+                    Ok(n) => n,
+                    // UART errors (framing, overrun, etc) are usually transient,
+                    // the next frame is likely to be fine.
+                    Err(_) => return Err(WireRxErrorKind::Other),
+                };
+                self.rd_start = 0;
+                self.rd_end = n;
+            }
+
+            // A single read may contain the end of one frame and the start of
+            // another, so keep track of what is left over for next time.
+            let window = &self.rd_buf[self.rd_start..self.rd_end];
+
+            // If we overflowed, throw away the rest of that frame
+            if self.discarding {
+                match window.iter().position(|b| *b == 0) {
+                    Some(pos) => {
+                        self.rd_start += pos + 1;
+                        self.discarding = false;
+                    }
+                    None => self.rd_start = self.rd_end,
+                }
+                continue;
+            }
+
+            match self.acc.feed(window) {
+                FeedResult::Consumed => {
+                    self.rd_start = self.rd_end;
+                }
+                FeedResult::OverFull(remaining) => {
+                    // If the consumed part didn't end with a delimiter, we are
+                    // still in the middle of the too-large frame
+                    let consumed = &window[..window.len() - remaining.len()];
+                    self.discarding = consumed.last() != Some(&0);
+                    self.rd_start = self.rd_end - remaining.len();
+                    return Err(WireRxErrorKind::ReceivedMessageTooLarge);
+                }
+                FeedResult::DeserError(remaining) => {
+                    self.rd_start = self.rd_end - remaining.len();
+                    return Err(WireRxErrorKind::Other);
+                }
+                FeedResult::Success { data, remaining } => {
+                    self.rd_start = self.rd_end - remaining.len();
+                    // Ignore empty frames, e.g. from repeated delimiters
+                    if data.is_empty() {
+                        continue;
+                    }
+                    let out = buf
+                        .get_mut(..data.len())
+                        .ok_or(WireRxErrorKind::ReceivedMessageTooLarge)?;
+                    out.copy_from_slice(data);
+                    return Ok(out);
+                }
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// SPAWN
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireSpawn`] impl using the embassy executor
+#[derive(Clone)]
+pub struct EioCobsWireSpawn {
+    /// The embassy-executor spawner
+    pub spawner: Spawner,
+}
+
+impl From<Spawner> for EioCobsWireSpawn {
+    fn from(value: Spawner) -> Self {
+        Self { spawner: value }
+    }
+}
+
+impl WireSpawn for EioCobsWireSpawn {
+    type Error = SpawnError;
+
+    type Info = Spawner;
+
+    fn info(&self) -> &Self::Info {
+        &self.spawner
+    }
+}
+
+/// Attempt to spawn the given token
+pub fn embassy_spawn<Sp, S: Sized>(sp: &Sp, tok: SpawnToken<S>) -> Result<(), Sp::Error>
+where
+    Sp: WireSpawn<Error = SpawnError, Info = Spawner>,
+{
+    let info = sp.info();
+    info.spawn(tok)
+}
+
+#[cfg(all(test, feature = "use-std"))]
+mod test {
+    use super::{send_cobs, EioCobsWireRx};
+    use crate::{
+        accumulator::raw::CobsAccumulator,
+        server::{WireRx, WireRxErrorKind},
+    };
+    use core::convert::Infallible;
+
+    struct VecWriter(Vec<u8>);
+
+    impl embedded_io_async::ErrorType for VecWriter {
+        type Error = Infallible;
+    }
+
+    impl embedded_io_async::Write for VecWriter {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    struct SliceReader(Vec<u8>);
+
+    impl embedded_io_async::ErrorType for SliceReader {
+        type Error = Infallible;
+    }
+
+    impl embedded_io_async::Read for SliceReader {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let used = buf.len().min(self.0.len());
+            buf[..used].copy_from_slice(&self.0[..used]);
+            self.0.drain(..used);
+            Ok(used)
+        }
+    }
+
+    #[tokio::test]
+    async fn rx_frames() {
+        let mut stream = VecWriter(Vec::new());
+        send_cobs(&mut stream, &[1, 0, 2]).await.unwrap();
+        send_cobs(&mut stream, &[9; 100]).await.unwrap();
+        send_cobs(&mut stream, &[3, 4, 5]).await.unwrap();
+
+        let mut rx: EioCobsWireRx<SliceReader, 32> = EioCobsWireRx {
+            rx: SliceReader(stream.0),
+            acc: Box::leak(Box::new(CobsAccumulator::new())),
+            rd_buf: [0u8; 64],
+            rd_start: 0,
+            rd_end: 0,
+            discarding: false,
+        };
+        let mut buf = [0u8; 32];
+
+        // Several frames in one read must all be delivered
+        assert_eq!(rx.receive(&mut buf).await.unwrap(), &[1, 0, 2]);
+        // Too large for the accumulator, but the next frame is still received
+        assert!(matches!(
+            rx.receive(&mut buf).await,
+            Err(WireRxErrorKind::ReceivedMessageTooLarge)
+        ));
+        assert_eq!(rx.receive(&mut buf).await.unwrap(), &[3, 4, 5]);
+        assert!(matches!(
+            rx.receive(&mut buf).await,
+            Err(WireRxErrorKind::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn cobs_roundtrip() {
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![1, 2, 3],
+            vec![1, 0, 2, 0],
+            vec![7; 253],
+            vec![7; 254],
+            vec![7; 255],
+            (0..=255).cycle().take(1000).collect(),
+        ];
+
+        for case in cases {
+            let mut w = VecWriter(Vec::new());
+            send_cobs(&mut w, &case).await.unwrap();
+
+            // Must be properly terminated, with no other zeroes
+            let (last, enc) = w.0.split_last().unwrap();
+            assert_eq!(*last, 0);
+            assert!(!enc.contains(&0));
+            assert!(enc.len() <= cobs::max_encoding_length(case.len()).max(1));
+
+            // And must decode back to the original
+            let mut dec = vec![0u8; enc.len()];
+            let used = cobs::decode(enc, &mut dec).unwrap();
+            assert_eq!(&dec[..used], &case[..]);
+        }
+    }
+}
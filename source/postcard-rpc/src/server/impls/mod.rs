@@ -8,5 +8,20 @@ pub mod embassy_usb_v0_3;
 #[cfg(feature = "embassy-usb-0_4-server")]
 pub mod embassy_usb_v0_4;
 
+#[cfg(feature = "embassy-usb-0_4-cdc-acm-server")]
+pub mod embassy_usb_cdc_acm_v0_4;
+
+#[cfg(feature = "embassy-net-0_6-udp-server")]
+pub mod embassy_net_udp_v0_6;
+
+#[cfg(feature = "embedded-io-cobs-server")]
+pub mod embedded_io_cobs;
+
+#[cfg(feature = "tcp")]
+pub mod tcp;
+
+#[cfg(feature = "udp")]
+pub mod udp;
+
 #[cfg(feature = "test-utils")]
 pub mod test_channels;
@@ -0,0 +1,312 @@
+//! Implementation using tokio TCP streams and COBS framing
+//!
+//! This is mostly useful for running a server on a PC, for example when
+//! simulating a device, or when bridging a device over the network. It can
+//! be reached with [`HostClient::try_new_tcp()`][crate::host_client::HostClient::try_new_tcp].
+
+use core::{
+    convert::Infallible,
+    future::Future,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use std::sync::Arc;
+
+use crate::{
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq},
+    server::{
+        AsWireRxErrorKind, AsWireTxErrorKind, WireRx, WireRxErrorKind, WireSpawn, WireTx,
+        WireTxErrorKind,
+    },
+    standard_icd::LoggingTopic,
+    Topic,
+};
+use cobs::{decode_vec, encode_vec};
+use core::fmt::Arguments;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::Mutex,
+};
+
+//////////////////////////////////////////////////////////////////////////////
+// DISPATCH IMPL
+//////////////////////////////////////////////////////////////////////////////
+
+/// A collection of types and aliases useful for importing the correct types
+pub mod dispatch_impl {
+    use crate::{
+        header::VarKeyKind,
+        server::{Dispatch, Server},
+    };
+
+    pub use super::tokio_spawn as spawn_fn;
+
+    /// The settings necessary for creating a new TCP server
+    pub struct Settings {
+        /// The frame sender
+        pub tx: WireTxImpl,
+        /// The frame receiver
+        pub rx: WireRxImpl,
+        /// The size of the receive buffer
+        pub buf: usize,
+        /// The sender key size to use
+        pub kkind: VarKeyKind,
+    }
+
+    /// Type alias for `WireTx` impl
+    pub type WireTxImpl = super::TcpWireTx;
+    /// Type alias for `WireRx` impl
+    pub type WireRxImpl = super::TcpWireRx;
+    /// Type alias for `WireSpawn` impl
+    pub type WireSpawnImpl = super::TcpWireSpawn;
+    /// Type alias for the receive buffer
+    pub type WireRxBuf = Box<[u8]>;
+
+    /// Create a new server using the [`Settings`] and [`Dispatch`] implementation
+    pub fn new_server<D>(
+        dispatch: D,
+        settings: Settings,
+    ) -> crate::server::Server<WireTxImpl, WireRxImpl, WireRxBuf, D>
+    where
+        D: Dispatch<Tx = WireTxImpl>,
+    {
+        let buf = vec![0; settings.buf];
+        Server::new(
+            settings.tx,
+            settings.rx,
+            buf.into_boxed_slice(),
+            dispatch,
+            settings.kkind,
+        )
+    }
+}
+
+/// Split a connected [`TcpStream`] into a [`TcpWireTx`] and [`TcpWireRx`] pair
+pub fn split(stream: TcpStream) -> (TcpWireTx, TcpWireRx) {
+    // Frames are usually small, don't wait around to coalesce them
+    let _ = stream.set_nodelay(true);
+    let (rx, tx) = stream.into_split();
+    (TcpWireTx::new(tx), TcpWireRx::new(rx))
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireTx`] impl using a tokio TCP stream
+#[derive(Clone)]
+pub struct TcpWireTx {
+    tx: Arc<Mutex<OwnedWriteHalf>>,
+    log_ctr: Arc<AtomicU32>,
+}
+
+impl TcpWireTx {
+    /// Create a new [`TcpWireTx`]
+    pub fn new(tx: OwnedWriteHalf) -> Self {
+        Self {
+            tx: Arc::new(Mutex::new(tx)),
+            log_ctr: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    async fn inner_send(&self, msg: &[u8]) -> Result<(), TcpWireTxError> {
+        let mut enc = encode_vec(msg);
+        enc.push(0);
+        let mut tx = self.tx.lock().await;
+        tx.write_all(&enc)
+            .await
+            .map_err(|_| TcpWireTxError::ConnectionClosed)
+    }
+
+    fn log_header(&self, kkind: VarKeyKind) -> VarHeader {
+        let ctr = self.log_ctr.fetch_add(1, Ordering::Relaxed);
+        let key = match kkind {
+            VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
+            VarKeyKind::Key2 => VarKey::Key2(LoggingTopic::TOPIC_KEY2),
+            VarKeyKind::Key4 => VarKey::Key4(LoggingTopic::TOPIC_KEY4),
+            VarKeyKind::Key8 => VarKey::Key8(LoggingTopic::TOPIC_KEY),
+        };
+        VarHeader {
+            key,
+            seq_no: VarSeq::Seq4(ctr),
+        }
+    }
+}
+
+impl WireTx for TcpWireTx {
+    type Error = TcpWireTxError;
+
+    async fn send<T: serde::Serialize + ?Sized>(
+        &self,
+        hdr: VarHeader,
+        msg: &T,
+    ) -> Result<(), Self::Error> {
+        let mut buf = hdr.write_to_vec();
+        let bdy_ser = postcard::to_stdvec(msg).map_err(|_| TcpWireTxError::SerFailed)?;
+        buf.extend_from_slice(&bdy_ser);
+        self.inner_send(&buf).await
+    }
+
+    async fn send_raw(&self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.inner_send(buf).await
+    }
+
+    async fn send_log_str(&self, kkind: VarKeyKind, s: &str) -> Result<(), Self::Error> {
+        let wh = self.log_header(kkind);
+        self.send::<<LoggingTopic as Topic>::Message>(wh, &s.to_string())
+            .await
+    }
+
+    async fn send_log_fmt<'a>(
+        &self,
+        kkind: VarKeyKind,
+        a: Arguments<'a>,
+    ) -> Result<(), Self::Error> {
+        let wh = self.log_header(kkind);
+        self.send::<<LoggingTopic as Topic>::Message>(wh, &format!("{a}"))
+            .await
+    }
+}
+
+/// A wire tx error
+#[derive(Debug)]
+pub enum TcpWireTxError {
+    /// The connection was closed, or a write failed
+    ConnectionClosed,
+    /// The message could not be serialized
+    SerFailed,
+}
+
+impl AsWireTxErrorKind for TcpWireTxError {
+    fn as_kind(&self) -> WireTxErrorKind {
+        match self {
+            TcpWireTxError::ConnectionClosed => WireTxErrorKind::ConnectionClosed,
+            TcpWireTxError::SerFailed => WireTxErrorKind::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// RX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireRx`] impl using a tokio TCP stream
+pub struct TcpWireRx {
+    rx: OwnedReadHalf,
+    raw: Vec<u8>,
+    overflowed: bool,
+}
+
+impl TcpWireRx {
+    /// Create a new [`TcpWireRx`]
+    pub fn new(rx: OwnedReadHalf) -> Self {
+        Self {
+            rx,
+            raw: Vec::new(),
+            overflowed: false,
+        }
+    }
+}
+
+impl WireRx for TcpWireRx {
+    type Error = TcpWireRxError;
+
+    async fn receive<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a mut [u8], Self::Error> {
+        // The COBS encoded form of a frame that fits in `buf` is never larger than this
+        let max_encoded = buf.len() + (buf.len() / 254) + 1;
+
+        loop {
+            // Do we already have a complete frame buffered?
+            if let Some(pos) = self.raw.iter().position(|b| *b == 0) {
+                let enc: Vec<u8> = self.raw.drain(..=pos).collect();
+                let enc = &enc[..pos];
+
+                if core::mem::take(&mut self.overflowed) {
+                    return Err(TcpWireRxError::MessageTooLarge);
+                }
+                // Ignore stray delimiters
+                if enc.is_empty() {
+                    continue;
+                }
+                let Ok(frame) = decode_vec(enc) else {
+                    return Err(TcpWireRxError::DecodeFailed);
+                };
+                let out = buf
+                    .get_mut(..frame.len())
+                    .ok_or(TcpWireRxError::MessageTooLarge)?;
+                out.copy_from_slice(&frame);
+                return Ok(out);
+            }
+
+            // No complete frame yet. If what we have can't possibly fit, throw
+            // it away, and remember to report the error at the end of the frame
+            if self.raw.len() > max_encoded {
+                self.raw.clear();
+                self.overflowed = true;
+            }
+
+            let mut chunk = [0u8; 1024];
+            let used = self
+                .rx
+                .read(&mut chunk)
+                .await
+                .map_err(|_| TcpWireRxError::ConnectionClosed)?;
+            if used == 0 {
+                return Err(TcpWireRxError::ConnectionClosed);
+            }
+            self.raw.extend_from_slice(&chunk[..used]);
+        }
+    }
+}
+
+/// A wire rx error
+#[derive(Debug)]
+pub enum TcpWireRxError {
+    /// The connection was closed
+    ConnectionClosed,
+    /// The client sent a too-large message
+    MessageTooLarge,
+    /// The client sent a message that was not validly COBS encoded
+    DecodeFailed,
+}
+
+impl AsWireRxErrorKind for TcpWireRxError {
+    fn as_kind(&self) -> WireRxErrorKind {
+        match self {
+            TcpWireRxError::ConnectionClosed => WireRxErrorKind::ConnectionClosed,
+            TcpWireRxError::MessageTooLarge => WireRxErrorKind::ReceivedMessageTooLarge,
+            TcpWireRxError::DecodeFailed => WireRxErrorKind::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// SPAWN
+//////////////////////////////////////////////////////////////////////////////
+
+/// A wire spawn implementation
+#[derive(Clone)]
+pub struct TcpWireSpawn;
+
+impl WireSpawn for TcpWireSpawn {
+    type Error = Infallible;
+
+    type Info = ();
+
+    fn info(&self) -> &Self::Info {
+        &()
+    }
+}
+
+/// Spawn a task using tokio
+pub fn tokio_spawn<Sp, F>(_sp: &Sp, fut: F) -> Result<(), Sp::Error>
+where
+    Sp: WireSpawn<Error = Infallible, Info = ()>,
+    F: Future<Output = ()> + 'static + Send,
+{
+    tokio::task::spawn(fut);
+    Ok(())
+}
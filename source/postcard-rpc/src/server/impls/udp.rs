@@ -0,0 +1,295 @@
+//! Implementation using tokio UDP sockets, one frame per datagram
+//!
+//! This is mostly useful for running a server on a PC, for example when
+//! simulating a networked device on localhost. It can be reached with
+//! [`HostClient::try_new_udp()`][crate::host_client::HostClient::try_new_udp].
+//!
+//! UDP is connectionless: the server replies to (and publishes to) whichever
+//! address it most recently received a frame from.
+
+use core::{
+    convert::Infallible,
+    future::Future,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    header::{VarHeader, VarKey, VarKeyKind, VarSeq},
+    server::{
+        AsWireRxErrorKind, AsWireTxErrorKind, WireRx, WireRxErrorKind, WireSpawn, WireTx,
+        WireTxErrorKind,
+    },
+    standard_icd::LoggingTopic,
+    Topic,
+};
+use core::fmt::Arguments;
+use tokio::net::UdpSocket;
+
+/// The largest payload that can be carried by a single UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+//////////////////////////////////////////////////////////////////////////////
+// DISPATCH IMPL
+//////////////////////////////////////////////////////////////////////////////
+
+/// A collection of types and aliases useful for importing the correct types
+pub mod dispatch_impl {
+    use crate::{
+        header::VarKeyKind,
+        server::{Dispatch, Server},
+    };
+
+    pub use super::tokio_spawn as spawn_fn;
+
+    /// The settings necessary for creating a new UDP server
+    pub struct Settings {
+        /// The frame sender
+        pub tx: WireTxImpl,
+        /// The frame receiver
+        pub rx: WireRxImpl,
+        /// The size of the receive buffer
+        pub buf: usize,
+        /// The sender key size to use
+        pub kkind: VarKeyKind,
+    }
+
+    /// Type alias for `WireTx` impl
+    pub type WireTxImpl = super::UdpWireTx;
+    /// Type alias for `WireRx` impl
+    pub type WireRxImpl = super::UdpWireRx;
+    /// Type alias for `WireSpawn` impl
+    pub type WireSpawnImpl = super::UdpWireSpawn;
+    /// Type alias for the receive buffer
+    pub type WireRxBuf = Box<[u8]>;
+
+    /// Create a new server using the [`Settings`] and [`Dispatch`] implementation
+    pub fn new_server<D>(
+        dispatch: D,
+        settings: Settings,
+    ) -> crate::server::Server<WireTxImpl, WireRxImpl, WireRxBuf, D>
+    where
+        D: Dispatch<Tx = WireTxImpl>,
+    {
+        let buf = vec![0; settings.buf];
+        Server::new(
+            settings.tx,
+            settings.rx,
+            buf.into_boxed_slice(),
+            dispatch,
+            settings.kkind,
+        )
+    }
+}
+
+/// Split a bound [`UdpSocket`] into a [`UdpWireTx`] and [`UdpWireRx`] pair
+pub fn split(socket: UdpSocket) -> (UdpWireTx, UdpWireRx) {
+    let socket = Arc::new(socket);
+    let peer = Arc::new(Mutex::new(None));
+    let tx = UdpWireTx {
+        socket: socket.clone(),
+        peer: peer.clone(),
+        log_ctr: Arc::new(AtomicU32::new(0)),
+    };
+    let rx = UdpWireRx {
+        socket,
+        peer,
+        scratch: vec![0; MAX_DATAGRAM_SIZE].into_boxed_slice(),
+    };
+    (tx, rx)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireTx`] impl using a tokio UDP socket
+#[derive(Clone)]
+pub struct UdpWireTx {
+    socket: Arc<UdpSocket>,
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+    log_ctr: Arc<AtomicU32>,
+}
+
+impl UdpWireTx {
+    /// The address frames are currently sent to, if any frame has been received yet
+    pub fn peer(&self) -> Option<SocketAddr> {
+        *self.peer.lock().unwrap()
+    }
+
+    async fn inner_send(&self, msg: &[u8]) -> Result<(), UdpWireTxError> {
+        if msg.len() > MAX_DATAGRAM_SIZE {
+            return Err(UdpWireTxError::MessageTooLarge);
+        }
+        let peer = self.peer().ok_or(UdpWireTxError::NoPeer)?;
+        self.socket
+            .send_to(msg, peer)
+            .await
+            .map_err(|_| UdpWireTxError::SendFailed)?;
+        Ok(())
+    }
+
+    fn log_header(&self, kkind: VarKeyKind) -> VarHeader {
+        let ctr = self.log_ctr.fetch_add(1, Ordering::Relaxed);
+        let key = match kkind {
+            VarKeyKind::Key1 => VarKey::Key1(LoggingTopic::TOPIC_KEY1),
+            VarKeyKind::Key2 => VarKey::Key2(LoggingTopic::TOPIC_KEY2),
+            VarKeyKind::Key4 => VarKey::Key4(LoggingTopic::TOPIC_KEY4),
+            VarKeyKind::Key8 => VarKey::Key8(LoggingTopic::TOPIC_KEY),
+        };
+        VarHeader {
+            key,
+            seq_no: VarSeq::Seq4(ctr),
+        }
+    }
+}
+
+impl WireTx for UdpWireTx {
+    type Error = UdpWireTxError;
+
+    async fn send<T: serde::Serialize + ?Sized>(
+        &self,
+        hdr: VarHeader,
+        msg: &T,
+    ) -> Result<(), Self::Error> {
+        let mut buf = hdr.write_to_vec();
+        let bdy_ser = postcard::to_stdvec(msg).map_err(|_| UdpWireTxError::SerFailed)?;
+        buf.extend_from_slice(&bdy_ser);
+        self.inner_send(&buf).await
+    }
+
+    async fn send_raw(&self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.inner_send(buf).await
+    }
+
+    async fn send_log_str(&self, kkind: VarKeyKind, s: &str) -> Result<(), Self::Error> {
+        let wh = self.log_header(kkind);
+        self.send::<<LoggingTopic as Topic>::Message>(wh, &s.to_string())
+            .await
+    }
+
+    async fn send_log_fmt<'a>(
+        &self,
+        kkind: VarKeyKind,
+        a: Arguments<'a>,
+    ) -> Result<(), Self::Error> {
+        let wh = self.log_header(kkind);
+        self.send::<<LoggingTopic as Topic>::Message>(wh, &format!("{a}"))
+            .await
+    }
+}
+
+/// A wire tx error
+#[derive(Debug)]
+pub enum UdpWireTxError {
+    /// No frame has been received yet, so there is nobody to send to
+    NoPeer,
+    /// The frame does not fit in a single datagram
+    MessageTooLarge,
+    /// Sending the datagram failed
+    SendFailed,
+    /// The message could not be serialized
+    SerFailed,
+}
+
+impl AsWireTxErrorKind for UdpWireTxError {
+    fn as_kind(&self) -> WireTxErrorKind {
+        match self {
+            UdpWireTxError::NoPeer => WireTxErrorKind::Other,
+            UdpWireTxError::MessageTooLarge => WireTxErrorKind::MessageTooLarge,
+            UdpWireTxError::SendFailed => WireTxErrorKind::Other,
+            UdpWireTxError::SerFailed => WireTxErrorKind::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// RX
+//////////////////////////////////////////////////////////////////////////////
+
+/// A [`WireRx`] impl using a tokio UDP socket
+pub struct UdpWireRx {
+    socket: Arc<UdpSocket>,
+    peer: Arc<Mutex<Option<SocketAddr>>>,
+    scratch: Box<[u8]>,
+}
+
+impl WireRx for UdpWireRx {
+    type Error = UdpWireRxError;
+
+    async fn receive<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a mut [u8], Self::Error> {
+        // Receive into a buffer large enough for any datagram, as some platforms
+        // silently truncate datagrams that don't fit.
+        let (used, from) = match self.socket.recv_from(&mut self.scratch).await {
+            Ok(r) => r,
+            // An ICMP "port unreachable" for an earlier send may be reported
+            // here on some platforms. The client may have just gone away.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                return Err(UdpWireRxError::ReceiveFailed)
+            }
+            Err(_) => return Err(UdpWireRxError::ConnectionClosed),
+        };
+        *self.peer.lock().unwrap() = Some(from);
+
+        let out = buf.get_mut(..used).ok_or(UdpWireRxError::MessageTooLarge)?;
+        out.copy_from_slice(&self.scratch[..used]);
+        Ok(out)
+    }
+}
+
+/// A wire rx error
+#[derive(Debug)]
+pub enum UdpWireRxError {
+    /// The socket is no longer usable
+    ConnectionClosed,
+    /// The client sent a datagram larger than the receive buffer
+    MessageTooLarge,
+    /// Receiving a datagram failed, but the socket is still usable
+    ReceiveFailed,
+}
+
+impl AsWireRxErrorKind for UdpWireRxError {
+    fn as_kind(&self) -> WireRxErrorKind {
+        match self {
+            UdpWireRxError::ConnectionClosed => WireRxErrorKind::ConnectionClosed,
+            UdpWireRxError::MessageTooLarge => WireRxErrorKind::ReceivedMessageTooLarge,
+            UdpWireRxError::ReceiveFailed => WireRxErrorKind::Other,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// SPAWN
+//////////////////////////////////////////////////////////////////////////////
+
+/// A wire spawn implementation
+#[derive(Clone)]
+pub struct UdpWireSpawn;
+
+impl WireSpawn for UdpWireSpawn {
+    type Error = Infallible;
+
+    type Info = ();
+
+    fn info(&self) -> &Self::Info {
+        &()
+    }
+}
+
+/// Spawn a task using tokio
+pub fn tokio_spawn<Sp, F>(_sp: &Sp, fut: F) -> Result<(), Sp::Error>
+where
+    Sp: WireSpawn<Error = Infallible, Info = ()>,
+    F: Future<Output = ()> + 'static + Send,
+{
+    tokio::task::spawn(fut);
+    Ok(())
+}
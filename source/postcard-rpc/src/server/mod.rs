@@ -12,7 +12,12 @@
 //! Examples of impls include:
 //!
 //! * A no-std impl using embassy and embassy-usb to provide transport over USB
+//! * A no-std impl using embedded-io-async and COBS framing to provide transport
+//!   over UARTs
 //! * A std impl using Tokio channels to provide transport for testing
+//! * A std impl using Tokio TCP streams to provide transport over the network
+//! * A std impl using Tokio UDP sockets, and a no-std impl using embassy-net
+//!   UDP sockets, to provide transport over the network one datagram per frame
 //!
 //! Impls are expected to implement three traits:
 //!
@@ -25,7 +30,11 @@
 #[doc(hidden)]
 pub mod dispatch_macro;
 
+pub mod blob;
+pub mod flow;
 pub mod impls;
+pub mod protocol;
+pub mod subscriptions;
 
 use core::{fmt::Arguments, ops::DerefMut};
 
@@ -83,6 +92,10 @@ pub enum WireTxErrorKind {
     Other,
     /// Timeout (WireTx impl specific) reached
     Timeout,
+    /// The frame was too large to be sent in one piece, for example
+    /// because it exceeds the maximum datagram size. The frame was
+    /// discarded, but the connection is still usable.
+    MessageTooLarge,
 }
 
 /// A conversion trait to convert a user error into a base Kind type
@@ -98,6 +111,194 @@ impl AsWireTxErrorKind for WireTxErrorKind {
     }
 }
 
+/// Formatting of log messages for [`WireTx`] impls that send from a fixed buffer
+#[cfg(any(
+    feature = "embassy-usb-0_3-server",
+    feature = "embassy-usb-0_4-server",
+    feature = "embassy-net-0_6-udp-server",
+    feature = "embedded-io-cobs-server",
+))]
+pub(crate) mod log_fmt {
+    use core::fmt::Arguments;
+
+    use super::WireTxErrorKind;
+    use crate::header::VarHeader;
+
+    /// Write the header and the formatted message to `buf`, returning the number of bytes used
+    ///
+    /// Messages that do not fit are truncated, ending with `...`.
+    pub(crate) fn write_log_fmt(
+        hdr: VarHeader,
+        buf: &mut [u8],
+        args: Arguments<'_>,
+    ) -> Result<usize, WireTxErrorKind> {
+        let ttl_len = buf.len();
+        let Some((_hdr, remaining)) = hdr.write_to_slice(buf) else {
+            return Err(WireTxErrorKind::Other);
+        };
+        let max_log_len = actual_varint_max_len(remaining.len());
+
+        // Then, reserve space for non-canonical length fields
+        // We also set all but the last bytes to be "continuation"
+        // bytes
+        if remaining.len() < max_log_len {
+            return Err(WireTxErrorKind::Other);
+        }
+
+        let (len_field, body) = remaining.split_at_mut(max_log_len);
+        for b in len_field.iter_mut() {
+            *b = 0x80;
+        }
+        if let Some(b) = len_field.last_mut() {
+            *b = 0x00;
+        }
+
+        // Then, do the formatting
+        let body_len = body.len();
+        let mut sw = SliceWriter(body);
+        let res = core::fmt::write(&mut sw, args);
+
+        // Calculate the number of bytes used *for formatting*.
+        let remain = sw.0.len();
+        let used = body_len - remain;
+
+        // If we had an error, that's probably because we ran out
+        // of room. If we had an error, AND there is at least three
+        // bytes, then replace those with '.'s like ...
+        if res.is_err() && (body.len() >= 3) {
+            let start = body.len() - 3;
+            body[start..].iter_mut().for_each(|b| *b = b'.');
+        }
+
+        // then go back and fill in the len - we write the len
+        // directly to the reserved bytes, and if we DIDN'T use
+        // the full space, we mark the end of the real length as
+        // a continuation field. This will result in a non-canonical
+        // "extended" length in postcard, and will "spill into" the
+        // bytes we wrote previously above
+        let mut len_bytes = [0u8; varint_max::<usize>()];
+        let len_used = varint_usize(used, &mut len_bytes);
+        if len_used.len() != len_field.len() {
+            if let Some(b) = len_used.last_mut() {
+                *b |= 0x80;
+            }
+        }
+        len_field[..len_used.len()].copy_from_slice(len_used);
+
+        // Calculate the TOTAL amount
+        Ok(ttl_len - remain)
+    }
+
+    struct SliceWriter<'a>(&'a mut [u8]);
+
+    impl<'a> core::fmt::Write for SliceWriter<'a> {
+        fn write_str(&mut self, s: &str) -> Result<(), core::fmt::Error> {
+            let sli = core::mem::take(&mut self.0);
+
+            // If this write would overflow us, note that, but still take
+            // as much as we possibly can here
+            let bad = s.len() > sli.len();
+            let to_write = s.len().min(sli.len());
+            let (now, later) = sli.split_at_mut(to_write);
+            now.copy_from_slice(&s.as_bytes()[..to_write]);
+            self.0 = later;
+
+            // Now, report whether we overflowed or not
+            if bad {
+                Err(core::fmt::Error)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the maximum number of bytes required to encode T.
+    const fn varint_max<T: Sized>() -> usize {
+        const BITS_PER_BYTE: usize = 8;
+        const BITS_PER_VARINT_BYTE: usize = 7;
+
+        // How many data bits do we need for this type?
+        let bits = core::mem::size_of::<T>() * BITS_PER_BYTE;
+
+        // We add (BITS_PER_VARINT_BYTE - 1), to ensure any integer divisions
+        // with a remainder will always add exactly one full byte, but
+        // an evenly divided number of bits will be the same
+        let roundup_bits = bits + (BITS_PER_VARINT_BYTE - 1);
+
+        // Apply division, using normal "round down" integer division
+        roundup_bits / BITS_PER_VARINT_BYTE
+    }
+
+    #[inline]
+    fn varint_usize(n: usize, out: &mut [u8; varint_max::<usize>()]) -> &mut [u8] {
+        let mut value = n;
+        for i in 0..varint_max::<usize>() {
+            out[i] = value.to_le_bytes()[0];
+            if value < 128 {
+                return &mut out[..=i];
+            }
+
+            out[i] |= 0x80;
+            value >>= 7;
+        }
+        debug_assert_eq!(value, 0);
+        &mut out[..]
+    }
+
+    fn actual_varint_max_len(largest: usize) -> usize {
+        if largest < (1 << 7) {
+            1
+        } else if largest < (1 << 14) {
+            2
+        } else if largest < (1 << 21) {
+            3
+        } else if largest < (1 << 28) {
+            4
+        } else {
+            varint_max::<usize>()
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::{actual_varint_max_len, write_log_fmt};
+        use crate::{
+            header::{VarHeader, VarKey, VarSeq},
+            Key,
+        };
+
+        #[test]
+        fn varint_len() {
+            assert_eq!(actual_varint_max_len(127), 1);
+            assert_eq!(actual_varint_max_len(128), 2);
+            assert_eq!(actual_varint_max_len(255), 2);
+            assert_eq!(actual_varint_max_len((1 << 14) - 1), 2);
+            assert_eq!(actual_varint_max_len(1 << 14), 3);
+            assert_eq!(actual_varint_max_len(1 << 21), 4);
+        }
+
+        #[test]
+        fn log_fmt_two_byte_len() {
+            // 128..=255 bytes remaining after the header used to reserve a
+            // single length byte, which could not hold the message length
+            let hdr = VarHeader {
+                key: VarKey::Key8(Key::for_path::<str>("log")),
+                seq_no: VarSeq::Seq4(0),
+            };
+            let hdr_len = hdr.write_to_vec().len();
+            let long = "x".repeat(300);
+            for remaining in 120..=260 {
+                let mut buf = vec![0u8; hdr_len + remaining];
+                let used = write_log_fmt(hdr, &mut buf, format_args!("{long}")).unwrap();
+                let (_hdr, body) = VarHeader::take_from_slice(&buf[..used]).unwrap();
+                let msg: &str = postcard::from_bytes(body).unwrap();
+                assert!(msg.ends_with("..."));
+                assert_eq!(msg.len() + actual_varint_max_len(remaining), remaining);
+            }
+        }
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // RX
 //////////////////////////////////////////////////////////////////////////////
@@ -176,6 +377,8 @@ pub trait WireSpawn: Clone {
 pub struct Sender<Tx: WireTx> {
     tx: Tx,
     kkind: VarKeyKind,
+    topics: Option<&'static dyn subscriptions::TopicFilter>,
+    protocol: Option<&'static protocol::ProtocolState>,
 }
 
 impl<Tx: WireTx> Sender<Tx> {
@@ -186,7 +389,36 @@ impl<Tx: WireTx> Sender<Tx> {
     ///
     /// `kkind` should usually come from [`Dispatch::min_key_len()`].
     pub fn new(tx: Tx, kkind: VarKeyKind) -> Self {
-        Self { tx, kkind }
+        Self {
+            tx,
+            kkind,
+            topics: None,
+            protocol: None,
+        }
+    }
+
+    /// Only publish the topics the client subscribed to, see [`subscriptions`]
+    pub fn set_topic_filter(&mut self, filter: &'static dyn subscriptions::TopicFilter) {
+        self.topics = Some(filter);
+    }
+
+    /// Would a message of the topic with the given key be published?
+    ///
+    /// This is always true without a topic filter.
+    pub fn is_subscribed(&self, key: Key) -> bool {
+        self.topics.is_none_or(|f| f.is_subscribed(key))
+    }
+
+    /// Use the protocol version agreed with the client, see [`protocol`]
+    pub fn set_protocol(&mut self, state: &'static protocol::ProtocolState) {
+        self.protocol = Some(state);
+    }
+
+    /// The protocol version agreed with the client
+    ///
+    /// This is always zero without a [`protocol::ProtocolState`].
+    pub fn protocol_version(&self) -> u8 {
+        self.protocol.map(|p| p.version()).unwrap_or(0)
     }
 
     /// Send a reply for the given endpoint
@@ -221,6 +453,19 @@ impl<Tx: WireTx> Sender<Tx> {
     /// Publish a Topic message
     #[inline]
     pub async fn publish<T>(&self, seq_no: VarSeq, msg: &T::Message) -> Result<(), Tx::Error>
+    where
+        T: ?Sized,
+        T: crate::Topic,
+        T::Message: Serialize + Schema,
+    {
+        if !self.is_subscribed(T::TOPIC_KEY) {
+            return Ok(());
+        }
+        self.publish_unfiltered::<T>(seq_no, msg).await
+    }
+
+    /// Publish a message, even if the client did not subscribe to the topic
+    async fn publish_unfiltered<T>(&self, seq_no: VarSeq, msg: &T::Message) -> Result<(), Tx::Error>
     where
         T: ?Sized,
         T: crate::Topic,
@@ -232,6 +477,42 @@ impl<Tx: WireTx> Sender<Tx> {
         self.tx.send::<T::Message>(wh, msg).await
     }
 
+    /// Publish a message to a flow-controlled topic
+    ///
+    /// The message is only sent if `flow` has a credit left, see [`flow`].
+    /// Returns whether the message was sent, messages that were not sent are
+    /// counted as dropped.
+    pub async fn publish_flow<T>(
+        &self,
+        flow: &flow::TopicFlow,
+        seq_no: VarSeq,
+        msg: &T::Message,
+    ) -> Result<bool, Tx::Error>
+    where
+        T: ?Sized,
+        T: crate::Topic,
+        T::Message: Serialize + Schema,
+    {
+        debug_assert!(flow.key() == T::TOPIC_KEY, "TopicFlow of another topic");
+        if !self.is_subscribed(T::TOPIC_KEY) {
+            return Ok(false);
+        }
+        if !flow.try_take() {
+            flow.record_dropped();
+            return Ok(false);
+        }
+        match self.publish_unfiltered::<T>(seq_no, msg).await {
+            Ok(()) => {
+                flow.record_sent();
+                Ok(true)
+            }
+            Err(e) => {
+                flow.record_dropped();
+                Err(e)
+            }
+        }
+    }
+
     /// Log a `str` directly to the [`LoggingTopic`][crate::standard_icd::LoggingTopic]
     #[inline]
     pub async fn log_str(&self, msg: &str) -> Result<(), Tx::Error> {
@@ -272,7 +553,7 @@ impl<Tx: WireTx> Sender<Tx> {
         // First, send all types
         for ty in device_map.types {
             let res = self
-                .publish::<GetAllSchemaDataTopic>(
+                .publish_unfiltered::<GetAllSchemaDataTopic>(
                     VarSeq::Seq2(msg_ctr),
                     &SchemaData::Type((*ty).into()),
                 )
@@ -286,7 +567,7 @@ impl<Tx: WireTx> Sender<Tx> {
         // Then all endpoints
         for ep in device_map.endpoints {
             let res = self
-                .publish::<GetAllSchemaDataTopic>(
+                .publish_unfiltered::<GetAllSchemaDataTopic>(
                     VarSeq::Seq2(msg_ctr),
                     &SchemaData::Endpoint {
                         path: ep.0.into(),
@@ -305,7 +586,7 @@ impl<Tx: WireTx> Sender<Tx> {
         // Then output topics
         for to in device_map.topics_out {
             let res = self
-                .publish::<GetAllSchemaDataTopic>(
+                .publish_unfiltered::<GetAllSchemaDataTopic>(
                     VarSeq::Seq2(msg_ctr),
                     &SchemaData::Topic {
                         direction: TopicDirection::ToClient,
@@ -323,7 +604,7 @@ impl<Tx: WireTx> Sender<Tx> {
         // Then input topics
         for ti in device_map.topics_in {
             let res = self
-                .publish::<GetAllSchemaDataTopic>(
+                .publish_unfiltered::<GetAllSchemaDataTopic>(
                     VarSeq::Seq2(msg_ctr),
                     &SchemaData::Topic {
                         direction: TopicDirection::ToServer,
@@ -355,6 +636,79 @@ impl<Tx: WireTx> Sender<Tx> {
     }
 }
 
+//////////////////////////////////////////////////////////////////////////////
+// STREAM SINK
+//////////////////////////////////////////////////////////////////////////////
+
+/// The [`StreamSink`] type is handed to the handler of a streaming endpoint, and
+/// is used to send the items of a single response stream
+///
+/// All items are sent with the sequence number of the request that started the
+/// stream. Handlers must call [`StreamSink::end()`] once they are done, also when
+/// returning early, so the client knows no more items are coming. Dropping the
+/// sink does not end the stream, as that requires an async send.
+#[must_use = "the stream only ends for the client once `StreamSink::end()` is called"]
+pub struct StreamSink<E: crate::StreamEndpoint, Tx: WireTx> {
+    sender: Sender<Tx>,
+    seq_no: VarSeq,
+    _pd: core::marker::PhantomData<fn() -> E>,
+}
+
+impl<E: crate::StreamEndpoint, Tx: WireTx + Clone> Clone for StreamSink<E, Tx> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            seq_no: self.seq_no,
+            _pd: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<E, Tx> StreamSink<E, Tx>
+where
+    E: crate::StreamEndpoint,
+    E::Item: Serialize,
+    Tx: WireTx,
+{
+    /// Create a new StreamSink, replying to the request with the given `seq_no`
+    pub fn new(sender: Sender<Tx>, seq_no: VarSeq) -> Self {
+        Self {
+            sender,
+            seq_no,
+            _pd: core::marker::PhantomData,
+        }
+    }
+
+    /// The sequence number of the request this stream responds to
+    pub fn seq_no(&self) -> VarSeq {
+        self.seq_no
+    }
+
+    /// Send a single item of the stream
+    #[inline]
+    pub async fn send(&self, item: &E::Item) -> Result<(), Tx::Error> {
+        self.send_inner(Some(item)).await
+    }
+
+    /// Send the end-of-stream marker, consuming the sink
+    ///
+    /// Clones of the sink send to the same stream, so only one of them is ended.
+    #[inline]
+    pub async fn end(self) -> Result<(), Tx::Error> {
+        self.send_inner(None).await
+    }
+
+    async fn send_inner(&self, item: Option<&E::Item>) -> Result<(), Tx::Error> {
+        let mut key = VarKey::Key8(E::RESP_KEY);
+        key.shrink_to(self.sender.kkind);
+        let wh = VarHeader {
+            key,
+            seq_no: self.seq_no,
+        };
+        self.sender.tx.send(wh, &item).await
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////
 // SERVER
 //////////////////////////////////////////////////////////////////////////////
@@ -403,13 +757,30 @@ where
     /// * a [`VarKeyKind`], which controls the key sizes sent by the [`WireTx`] impl
     pub fn new(tx: Tx, rx: Rx, buf: Buf, dis: D, kkind: VarKeyKind) -> Self {
         Self {
-            tx: Sender { tx, kkind },
+            tx: Sender::new(tx, kkind),
             rx,
             buf,
             dis,
         }
     }
 
+    /// Only publish the topics the client subscribed to, see [`subscriptions`]
+    ///
+    /// This applies to the dispatcher, and all senders retrieved with
+    /// [`Self::sender()`] after this call.
+    pub fn set_topic_filter(&mut self, filter: &'static dyn subscriptions::TopicFilter) {
+        self.tx.set_topic_filter(filter);
+    }
+
+    /// Use the protocol version agreed with the client, see [`protocol`]
+    ///
+    /// `state` must be the state used by the [`protocol::protocol_negotiation`]
+    /// handler. Like [`Self::set_topic_filter()`], this applies to the dispatcher,
+    /// and all senders retrieved with [`Self::sender()`] after this call.
+    pub fn set_protocol(&mut self, state: &'static protocol::ProtocolState) {
+        self.tx.set_protocol(state);
+    }
+
     /// Run until a fatal error occurs
     ///
     /// The server will receive frames, and dispatch them. When a fatal error occurs,
@@ -417,7 +788,14 @@ where
     ///
     /// The caller may decide to wait until a connection is re-established, reset any
     /// state, or immediately begin re-running.
+    ///
+    /// Each run starts out with protocol version zero, until the client negotiates
+    /// again, see [`Self::set_protocol()`]. Frames of other versions than zero and
+    /// the agreed one are ignored.
     pub async fn run(&mut self) -> ServerError<Tx, Rx> {
+        if let Some(protocol) = self.tx.protocol {
+            protocol.reset();
+        }
         loop {
             let Self {
                 tx,
@@ -441,6 +819,11 @@ where
                 // much to say because we don't have a key or seq no or anything
                 continue;
             };
+            // Version zero frames are always accepted, e.g. to negotiate again
+            let version = VarHeader::version_of(used).unwrap_or(0);
+            if version != 0 && version != tx.protocol_version() {
+                continue;
+            }
             let fut = d.handle(tx, &hdr, body);
             if let Err(e) = fut.await {
                 let kind = e.as_kind();
@@ -448,6 +831,7 @@ where
                     WireTxErrorKind::ConnectionClosed => return ServerError::TxFatal(e),
                     WireTxErrorKind::Other => {}
                     WireTxErrorKind::Timeout => return ServerError::TxFatal(e),
+                    WireTxErrorKind::MessageTooLarge => {}
                 }
             }
         }
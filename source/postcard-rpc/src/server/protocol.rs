@@ -0,0 +1,128 @@
+//! Server side of protocol version negotiation
+//!
+//! The version bits of each [`VarHeader`] name the protocol version the frame is
+//! encoded with. Without negotiation, both sides use version zero. With it, the
+//! client sends the versions and capabilities it supports, and the server picks
+//! the highest version both sides support, so new wire formats can be rolled out
+//! without breaking deployed firmware or older hosts. The client does this with
+//! `HostClient::negotiate_protocol()`, or on every (re)connect if configured to,
+//! and then sends all frames with the agreed version. It also leaves out the
+//! features the server did not list in its capabilities. The negotiation itself
+//! always uses version zero frames.
+//!
+//! To support this, declare a [`ProtocolState`] with the capabilities of the
+//! server, include the [protocol endpoint][crate::standard_icd::STANDARD_ICD_PROTOCOL_ENDPOINTS]
+//! in the endpoint list, and list it in the dispatcher using [`protocol_negotiation`].
+//! The context must implement [`ProtocolContext`], and the server must be given
+//! the same state with [`Server::set_protocol()`][crate::server::Server::set_protocol].
+//! The server then goes back to version zero whenever it is run again, and
+//! ignores frames of versions that were not agreed. Version zero is the only
+//! version defined so far, so the [`WireTx`][crate::server::WireTx] impls
+//! encode all frames with it.
+//!
+//! ```rust,ignore
+//! use postcard_rpc::{
+//!     server::protocol::{protocol_negotiation, ProtocolContext, ProtocolState},
+//!     standard_icd::{ProtocolEndpoint, ProtocolInfo, STANDARD_ICD_PROTOCOL_ENDPOINTS},
+//! };
+//!
+//! static PROTOCOL: ProtocolState = ProtocolState::new(ProtocolInfo::CAP_BLOB);
+//!
+//! impl ProtocolContext for Context {
+//!     fn protocol(&self) -> &ProtocolState {
+//!         &PROTOCOL
+//!     }
+//! }
+//!
+//! endpoints! {
+//!     list = ENDPOINT_LIST;
+//!     include = [STANDARD_ICD_PROTOCOL_ENDPOINTS];
+//!     // ...
+//! }
+//!
+//! define_dispatch! {
+//!     // ...
+//!     endpoints: {
+//!         list: ENDPOINT_LIST;
+//!
+//!         | EndpointTy        | kind      | handler               |
+//!         | ----------        | ----      | -------               |
+//!         | ProtocolEndpoint  | blocking  | protocol_negotiation  |
+//!     };
+//!     // ...
+//! }
+//!
+//! let mut server = new_server(dispatch, settings);
+//! server.set_protocol(&PROTOCOL);
+//! ```
+
+use portable_atomic::{AtomicU8, Ordering};
+
+use crate::{
+    header::VarHeader,
+    standard_icd::{ProtocolAgreement, ProtocolError, ProtocolInfo},
+};
+
+/// The protocol version agreed with the client, and the capabilities of the server
+///
+/// Usually stored in a `static`, shared between the context and anything that
+/// depends on the agreed version.
+pub struct ProtocolState {
+    capabilities: u32,
+    version: AtomicU8,
+}
+
+impl ProtocolState {
+    /// Create the state for a server with the given capabilities, see the
+    /// `CAP_*` flags of [`ProtocolInfo`]
+    pub const fn new(capabilities: u32) -> Self {
+        Self {
+            capabilities,
+            version: AtomicU8::new(0),
+        }
+    }
+
+    /// The capabilities of the server
+    pub fn capabilities(&self) -> u32 {
+        self.capabilities
+    }
+
+    /// The agreed protocol version, zero until the client negotiated one
+    pub fn version(&self) -> u8 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Go back to version zero, e.g. when the client reconnects
+    ///
+    /// Done by [`Server::run()`][crate::server::Server::run] once given the state.
+    pub fn reset(&self) {
+        self.version.store(0, Ordering::Release);
+    }
+
+    /// Pick the highest protocol version supported by both the client and the server
+    pub fn negotiate(&self, client: &ProtocolInfo) -> Result<ProtocolAgreement, ProtocolError> {
+        let version =
+            VarHeader::highest_common_version(VarHeader::SUPPORTED_VERSIONS, client.versions)
+                .ok_or(ProtocolError::NoCommonVersion)?;
+        self.version.store(version, Ordering::Release);
+        Ok(ProtocolAgreement {
+            version,
+            capabilities: self.capabilities & client.capabilities,
+        })
+    }
+}
+
+/// A trait for contexts that provide the [`ProtocolState`] to the [`protocol_negotiation`] handler
+pub trait ProtocolContext {
+    /// The protocol state of the server
+    fn protocol(&self) -> &ProtocolState;
+}
+
+/// Handler for [`ProtocolEndpoint`][crate::standard_icd::ProtocolEndpoint]
+pub fn protocol_negotiation<C: ProtocolContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: ProtocolInfo,
+) -> Result<ProtocolAgreement, ProtocolError> {
+    context.protocol().negotiate(&req)
+}
@@ -0,0 +1,177 @@
+//! Server side of topic subscription negotiation
+//!
+//! By default, [`Sender::publish()`][crate::server::Sender::publish] sends every
+//! message, whether the client listens to the topic or not. With a topic
+//! registry, the client tells the server which topics it subscribed to, and
+//! messages of all other topics are discarded before they are serialized. The
+//! client does this automatically when subscribing, when the last subscription to a
+//! topic is dropped, and after negotiating the protocol, once the server agreed on
+//! [`ProtocolInfo::CAP_TOPIC_SUBSCRIPTION`][crate::standard_icd::ProtocolInfo::CAP_TOPIC_SUBSCRIPTION],
+//! see [`crate::server::protocol`]. So the
+//! server must also serve the protocol endpoint, with that capability, and the
+//! client must negotiate, e.g. on every (re)connect. Devices that do not serve the
+//! subscription endpoint are not affected.
+//!
+//! To support this, declare a [`TopicRegistry`], set it as the topic filter of the
+//! server with [`Server::set_topic_filter()`][crate::server::Server::set_topic_filter],
+//! include the [subscription endpoint][crate::standard_icd::STANDARD_ICD_SUBSCRIPTION_ENDPOINTS]
+//! in the endpoint list, and list it in the dispatcher using [`topic_subscription`].
+//! The context must implement [`SubscriptionContext`]:
+//!
+//! ```rust,ignore
+//! use postcard_rpc::{
+//!     server::subscriptions::{topic_subscription, SubscriptionContext, TopicFilter, TopicRegistry},
+//!     standard_icd::{TopicSubscriptionEndpoint, STANDARD_ICD_SUBSCRIPTION_ENDPOINTS},
+//! };
+//!
+//! static TOPICS: TopicRegistry<8> = TopicRegistry::new();
+//!
+//! impl SubscriptionContext for Context {
+//!     fn topic_filter(&self) -> &dyn TopicFilter {
+//!         &TOPICS
+//!     }
+//! }
+//!
+//! endpoints! {
+//!     list = ENDPOINT_LIST;
+//!     include = [STANDARD_ICD_SUBSCRIPTION_ENDPOINTS];
+//!     // ...
+//! }
+//!
+//! define_dispatch! {
+//!     // ...
+//!     endpoints: {
+//!         list: ENDPOINT_LIST;
+//!
+//!         | EndpointTy                | kind      | handler               |
+//!         | ----------                | ----      | -------               |
+//!         | TopicSubscriptionEndpoint | blocking  | topic_subscription    |
+//!     };
+//!     // ...
+//! }
+//!
+//! // Before running the server, or getting any senders
+//! server.set_topic_filter(&TOPICS);
+//! ```
+//!
+//! Responses, logs, and the schema topics are always sent.
+
+use portable_atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{
+    header::VarHeader,
+    standard_icd::{TopicSubscriptionError, TopicSubscriptionRequest},
+    Key,
+};
+
+/// A set of topics the client subscribed to
+pub trait TopicFilter: Sync {
+    /// Add a topic, does nothing if the topic was already subscribed
+    fn subscribe(&self, key: Key) -> Result<(), TopicSubscriptionError>;
+
+    /// Remove a topic, does nothing if the topic was not subscribed
+    fn unsubscribe(&self, key: Key);
+
+    /// Has the client subscribed to the topic with this key?
+    fn is_subscribed(&self, key: Key) -> bool;
+}
+
+/// A [`TopicFilter`] with room for `N` subscribed topics
+///
+/// Usually stored in a `static`, shared between the context and the [`Server`][crate::server::Server].
+pub struct TopicRegistry<const N: usize> {
+    slots: [TopicSlot; N],
+}
+
+struct TopicSlot {
+    used: AtomicBool,
+    key: AtomicU64,
+}
+
+impl TopicSlot {
+    const fn new() -> Self {
+        Self {
+            used: AtomicBool::new(false),
+            key: AtomicU64::new(0),
+        }
+    }
+
+    fn matches(&self, key: u64) -> bool {
+        self.used.load(Ordering::Acquire) && self.key.load(Ordering::Acquire) == key
+    }
+}
+
+impl<const N: usize> TopicRegistry<N> {
+    /// Create a registry with no subscribed topics
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { TopicSlot::new() }; N],
+        }
+    }
+
+    /// Remove all topics, e.g. when the client reconnects
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.used.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl<const N: usize> Default for TopicRegistry<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TopicFilter for TopicRegistry<N> {
+    fn subscribe(&self, key: Key) -> Result<(), TopicSubscriptionError> {
+        let key = u64::from_le_bytes(key.to_bytes());
+        if self.slots.iter().any(|s| s.matches(key)) {
+            return Ok(());
+        }
+        // Only the dispatcher adds topics, so the free slot can't be taken concurrently
+        let slot = self
+            .slots
+            .iter()
+            .find(|s| !s.used.load(Ordering::Acquire))
+            .ok_or(TopicSubscriptionError::TooManyTopics)?;
+        slot.key.store(key, Ordering::Release);
+        slot.used.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    fn unsubscribe(&self, key: Key) {
+        let key = u64::from_le_bytes(key.to_bytes());
+        for slot in self.slots.iter().filter(|s| s.matches(key)) {
+            slot.used.store(false, Ordering::Release);
+        }
+    }
+
+    fn is_subscribed(&self, key: Key) -> bool {
+        let key = u64::from_le_bytes(key.to_bytes());
+        self.slots.iter().any(|s| s.matches(key))
+    }
+}
+
+/// A trait for contexts that provide the [`TopicFilter`] to the [`topic_subscription`] handler
+pub trait SubscriptionContext {
+    /// The filter, usually the same one set with
+    /// [`Server::set_topic_filter()`][crate::server::Server::set_topic_filter]
+    fn topic_filter(&self) -> &dyn TopicFilter;
+}
+
+/// Handler for [`TopicSubscriptionEndpoint`][crate::standard_icd::TopicSubscriptionEndpoint]
+pub fn topic_subscription<C: SubscriptionContext>(
+    context: &mut C,
+    _header: VarHeader,
+    req: TopicSubscriptionRequest,
+) -> Result<(), TopicSubscriptionError> {
+    let filter = context.topic_filter();
+    match req {
+        TopicSubscriptionRequest::Subscribe(key) => filter.subscribe(key),
+        TopicSubscriptionRequest::Unsubscribe(key) => {
+            filter.unsubscribe(key);
+            Ok(())
+        }
+    }
+}
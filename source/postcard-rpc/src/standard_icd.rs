@@ -124,6 +124,288 @@ pub struct SchemaTotals {
     pub errors: u32,
 }
 
+/// A single chunk of an upload to an [`UploadEndpoint`][crate::UploadEndpoint]
+///
+/// All chunks of one upload are sent with the same sequence number.
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct UploadChunk<'a> {
+    /// The position of `data` within the whole upload
+    pub offset: u32,
+    /// The total length of the upload
+    pub total_len: u32,
+    /// The contents of this chunk
+    pub data: &'a [u8],
+}
+
+/// The response to each chunk sent to an [`UploadEndpoint`][crate::UploadEndpoint]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum UploadStatus<T> {
+    /// The chunk was accepted, and the client should continue with the chunk
+    /// starting at `next_offset`
+    Ack {
+        /// The offset of the next chunk that should be sent
+        next_offset: u32,
+    },
+    /// The upload has finished, with the final response of the handler
+    Done(T),
+}
+
+/// How a blob should be opened with [`BlobOpenEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum BlobMode {
+    /// Open an existing blob for reading
+    Read,
+    /// Open a blob for writing, replacing any existing contents
+    Write {
+        /// The total length of the blob that will be written
+        len: u32,
+        /// The [`BlobCrc`] of the complete blob
+        crc: u32,
+    },
+}
+
+/// The request of [`BlobOpenEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobOpen<'a> {
+    /// The name of the blob
+    pub name: &'a str,
+    /// Whether the blob is read or written
+    pub mode: BlobMode,
+}
+
+/// The response of [`BlobOpenEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobInfo {
+    /// The handle used for all further requests on this blob
+    pub handle: u32,
+    /// The total length of the blob
+    pub len: u32,
+    /// The offset the client should continue at.
+    ///
+    /// When writing, this is non-zero if a previous, interrupted write of the
+    /// same blob (with the same `len` and `crc`) is resumed.
+    pub offset: u32,
+}
+
+/// The request of [`BlobWriteEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobWrite<'a> {
+    /// The handle from [`BlobInfo`]
+    pub handle: u32,
+    /// The position of `data` within the blob
+    pub offset: u32,
+    /// The data to write
+    pub data: &'a [u8],
+}
+
+/// The request of [`BlobReadEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobRead {
+    /// The handle from [`BlobInfo`]
+    pub handle: u32,
+    /// The position to read from
+    pub offset: u32,
+    /// The maximum number of bytes to read
+    pub len: u32,
+}
+
+/// The response of [`BlobReadEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobData<'a> {
+    /// The data that was read, which is empty at the end of the blob
+    pub data: &'a [u8],
+}
+
+/// The request of [`BlobCloseEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobClose {
+    /// The handle from [`BlobInfo`]
+    pub handle: u32,
+}
+
+/// The response of [`BlobCloseEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct BlobSummary {
+    /// The length of the stored blob
+    pub len: u32,
+    /// The [`BlobCrc`] of the stored blob
+    pub crc: u32,
+}
+
+/// An error of the blob endpoints
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum BlobError {
+    /// No blob with the given name exists
+    NotFound,
+    /// The handle is not (or no longer) open
+    InvalidHandle,
+    /// The offset is outside of the blob, or not where the server expected it
+    BadOffset,
+    /// The blob is too large for the storage
+    TooLarge,
+    /// Too many blobs are open at once
+    TooManyOpen,
+    /// The checksum of the transferred blob did not match
+    CrcMismatch {
+        /// The checksum the client expected
+        expected: u32,
+        /// The checksum of the stored blob
+        actual: u32,
+    },
+    /// The storage failed
+    Storage,
+}
+
+/// The checksum used by the blob endpoints (CRC-32/ISO-HDLC, as used by zip and ethernet)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlobCrc(u32);
+
+impl BlobCrc {
+    /// Start a new checksum
+    pub const fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    /// Calculate the checksum of `data` at once
+    pub const fn checksum(data: &[u8]) -> u32 {
+        Self::new().update(data).finish()
+    }
+
+    /// Add `data` to the checksum
+    pub const fn update(self, data: &[u8]) -> Self {
+        let mut crc = self.0;
+        let mut i = 0;
+        while i < data.len() {
+            crc ^= data[i] as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            i += 1;
+        }
+        Self(crc)
+    }
+
+    /// Get the final checksum
+    pub const fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for BlobCrc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The request of [`TopicFlowEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum TopicFlowRequest {
+    /// Allow the server to publish messages of the topic `key` until `limit`
+    /// messages were published since flow control was enabled.
+    ///
+    /// The first grant enables flow control for the topic, before that (and
+    /// after [`TopicFlowRequest::Disable`]) messages are published without limit.
+    /// Limits only ever grow, so a grant that is repeated or arrives late has no
+    /// effect. They are compared with wrapping arithmetic.
+    Grant {
+        /// The key of the topic
+        key: Key,
+        /// The total number of messages the server may publish
+        limit: u32,
+    },
+    /// Disable flow control for the topic `key`, publishing without limit
+    Disable {
+        /// The key of the topic
+        key: Key,
+    },
+    /// Only get the [`TopicFlowStats`] of the topic `key`
+    Stats {
+        /// The key of the topic
+        key: Key,
+    },
+}
+
+/// The response of [`TopicFlowEndpoint`], counters of a flow-controlled topic
+///
+/// Counters wrap around on overflow.
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct TopicFlowStats {
+    /// Whether flow control is enabled for the topic
+    pub enabled: bool,
+    /// The number of messages the server may still publish
+    pub credits: u32,
+    /// The number of messages published
+    pub sent: u32,
+    /// The number of messages dropped, because no credits were left or sending failed
+    pub dropped: u32,
+}
+
+/// An error of [`TopicFlowEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum TopicFlowError {
+    /// The server does not flow-control the topic with this key
+    UnknownTopic,
+}
+
+/// The request of [`TopicSubscriptionEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum TopicSubscriptionRequest {
+    /// Start publishing the topic with this key
+    Subscribe(Key),
+    /// Stop publishing the topic with this key
+    Unsubscribe(Key),
+}
+
+/// An error of [`TopicSubscriptionEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum TopicSubscriptionError {
+    /// The server can not keep track of any more subscribed topics
+    TooManyTopics,
+}
+
+/// The protocol versions and capabilities of a client, the request of [`ProtocolEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct ProtocolInfo {
+    /// The supported protocol versions, bit N is set if version N is supported,
+    /// see [`VarHeader::SUPPORTED_VERSIONS`][crate::header::VarHeader::SUPPORTED_VERSIONS]
+    pub versions: u16,
+    /// The supported optional capabilities, a combination of the `CAP_*` flags
+    pub capabilities: u32,
+}
+
+impl ProtocolInfo {
+    /// The [blob endpoints][STANDARD_ICD_BLOB_ENDPOINTS]
+    pub const CAP_BLOB: u32 = 1 << 0;
+    /// The [topic flow control endpoint][STANDARD_ICD_FLOW_ENDPOINTS]
+    pub const CAP_TOPIC_FLOW: u32 = 1 << 1;
+    /// The [topic subscription endpoint][STANDARD_ICD_SUBSCRIPTION_ENDPOINTS]
+    pub const CAP_TOPIC_SUBSCRIPTION: u32 = 1 << 2;
+    /// All capabilities known to this version of the crate
+    pub const CAP_ALL: u32 = Self::CAP_BLOB | Self::CAP_TOPIC_FLOW | Self::CAP_TOPIC_SUBSCRIPTION;
+}
+
+/// The response of [`ProtocolEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub struct ProtocolAgreement {
+    /// The highest protocol version supported by both sides, used from now on
+    pub version: u8,
+    /// The capabilities supported by both sides
+    pub capabilities: u32,
+}
+
+/// An error of [`ProtocolEndpoint`]
+#[derive(Serialize, Deserialize, Schema, Debug, PartialEq, Copy, Clone)]
+pub enum ProtocolError {
+    /// The client and server do not support any protocol version in common
+    NoCommonVersion,
+}
+
 endpoints! {
     list = STANDARD_ICD_ENDPOINTS;
     omit_std = true;
@@ -133,6 +415,41 @@ endpoints! {
     | GetAllSchemasEndpoint | ()            | SchemaTotals  | "postcard-rpc/schemas/get" |
 }
 
+endpoints! {
+    list = STANDARD_ICD_BLOB_ENDPOINTS;
+    omit_std = true;
+    | EndpointTy            | RequestTy     | ResponseTy    | ErrorTy       | Path                       |
+    | ----------            | ---------     | ----------    | -------       | ----                       |
+    | BlobOpenEndpoint      | BlobOpen<'a>  | BlobInfo      | BlobError     | "postcard-rpc/blob/open"   |
+    | BlobWriteEndpoint     | BlobWrite<'a> | ()            | BlobError     | "postcard-rpc/blob/write"  |
+    | BlobReadEndpoint      | BlobRead      | BlobData<'a>  | BlobError     | "postcard-rpc/blob/read"   |
+    | BlobCloseEndpoint     | BlobClose     | BlobSummary   | BlobError     | "postcard-rpc/blob/close"  |
+}
+
+endpoints! {
+    list = STANDARD_ICD_FLOW_ENDPOINTS;
+    omit_std = true;
+    | EndpointTy            | RequestTy         | ResponseTy        | ErrorTy           | Path                          |
+    | ----------            | ---------         | ----------        | -------           | ----                          |
+    | TopicFlowEndpoint     | TopicFlowRequest  | TopicFlowStats    | TopicFlowError    | "postcard-rpc/topic/flow"     |
+}
+
+endpoints! {
+    list = STANDARD_ICD_SUBSCRIPTION_ENDPOINTS;
+    omit_std = true;
+    | EndpointTy                | RequestTy                 | ResponseTy    | ErrorTy                   | Path                              |
+    | ----------                | ---------                 | ----------    | -------                   | ----                              |
+    | TopicSubscriptionEndpoint | TopicSubscriptionRequest  | ()            | TopicSubscriptionError    | "postcard-rpc/topic/subscription" |
+}
+
+endpoints! {
+    list = STANDARD_ICD_PROTOCOL_ENDPOINTS;
+    omit_std = true;
+    | EndpointTy        | RequestTy     | ResponseTy        | ErrorTy       | Path                      |
+    | ----------        | ---------     | ----------        | -------       | ----                      |
+    | ProtocolEndpoint  | ProtocolInfo  | ProtocolAgreement | ProtocolError | "postcard-rpc/protocol"   |
+}
+
 topics! {
     list = STANDARD_ICD_TOPICS_OUT;
     direction = crate::TopicDirection::ToClient;
@@ -1,11 +1,13 @@
 //! Test utilities for doctests and integration tests
 
-use core::{fmt::Display, future::Future};
+use core::{fmt::Display, future::Future, time::Duration};
 
 use crate::header::{VarHeader, VarKey, VarSeq, VarSeqKind};
 use crate::host_client::util::Stopper;
 use crate::{
-    host_client::{HostClient, RpcFrame, WireRx, WireSpawn, WireTx},
+    host_client::{
+        capture::Capture, HostClient, HostClientConfig, RpcFrame, WireRx, WireSpawn, WireTx,
+    },
     Endpoint, Topic,
 };
 use postcard_schema::Schema;
@@ -215,3 +217,120 @@ where
 
     (lfs, client)
 }
+
+/// Like [`local_setup`], but creates the [`HostClient`] with the given config
+///
+/// `config.outgoing_depth` is also used as the bound of the channels.
+pub fn local_setup_with_config<E>(config: &HostClientConfig) -> (LocalFakeServer, HostClient<E>)
+where
+    E: Schema + DeserializeOwned,
+{
+    let (c2s_tx, c2s_rx) = channel(config.outgoing_depth);
+    let (s2c_tx, s2c_rx) = channel(config.outgoing_depth);
+    let fake_error = Stopper::new();
+
+    let client = HostClient::<E>::new_with_wire_and_config(
+        LocalTx {
+            to_server: c2s_tx,
+            fake_error: fake_error.clone(),
+        },
+        LocalRx {
+            from_server: s2c_rx,
+            fake_error: fake_error.clone(),
+        },
+        LocalSpawn,
+        config,
+    );
+
+    let lfs = LocalFakeServer {
+        from_client: c2s_rx,
+        to_client: s2c_tx,
+        fake_error,
+    };
+
+    (lfs, client)
+}
+
+/// Like [`local_setup`], but records all frames to `capture`
+pub fn local_setup_with_capture<E>(
+    bound: usize,
+    err_uri_path: &str,
+    capture: &Capture,
+) -> (LocalFakeServer, HostClient<E>)
+where
+    E: Schema + DeserializeOwned,
+{
+    let (c2s_tx, c2s_rx) = channel(bound);
+    let (s2c_tx, s2c_rx) = channel(bound);
+    let fake_error = Stopper::new();
+
+    let client = HostClient::<E>::new_with_wire(
+        capture.tap_tx(LocalTx {
+            to_server: c2s_tx,
+            fake_error: fake_error.clone(),
+        }),
+        capture.tap_rx(LocalRx {
+            from_server: s2c_rx,
+            fake_error: fake_error.clone(),
+        }),
+        LocalSpawn,
+        VarSeqKind::Seq2,
+        err_uri_path,
+        bound,
+    );
+
+    let lfs = LocalFakeServer {
+        from_client: c2s_rx,
+        to_client: s2c_tx,
+        fake_error,
+    };
+
+    (lfs, client)
+}
+
+/// Like [`local_setup`], but creates a reconnecting [`HostClient`]
+///
+/// Each time the client (re)connects, a new [`LocalFakeServer`] is created and
+/// sent on the returned channel. Use [`LocalFakeServer::cause_fatal_error`] to
+/// simulate the device being unplugged.
+pub fn local_setup_reconnecting<E>(
+    bound: usize,
+    err_uri_path: &str,
+) -> (Receiver<LocalFakeServer>, HostClient<E>)
+where
+    E: Schema + DeserializeOwned,
+{
+    let (srv_tx, srv_rx) = channel(4);
+
+    let connect = move || {
+        let srv_tx = srv_tx.clone();
+        async move {
+            let (c2s_tx, c2s_rx) = channel(bound);
+            let (s2c_tx, s2c_rx) = channel(bound);
+            let fake_error = Stopper::new();
+
+            let lfs = LocalFakeServer {
+                from_client: c2s_rx,
+                to_client: s2c_tx,
+                fake_error: fake_error.clone(),
+            };
+            srv_tx.send(lfs).await.map_err(|_| LocalError::TxClosed)?;
+
+            let tx = LocalTx {
+                to_server: c2s_tx,
+                fake_error: fake_error.clone(),
+            };
+            let rx = LocalRx {
+                from_server: s2c_rx,
+                fake_error,
+            };
+            Ok::<_, LocalError>((tx, rx))
+        }
+    };
+
+    let config = HostClientConfig::new(VarSeqKind::Seq2, err_uri_path, bound);
+    let client =
+        HostClient::<E>::new_reconnecting(connect, LocalSpawn, &config, Duration::from_millis(10));
+
+    (srv_rx, client)
+}